@@ -1,113 +1,185 @@
-// use mollusk_svm::{program, Mollusk};
-// use mollusk_svm_bencher::MolluskComputeUnitBencher;
-// use solana_pinocchio_starter::{
-//     instruction::{InitializeMyStateIxData, UpdateMyStateIxData},
-//     state::{to_bytes, DataLen, MyState, State},
-//     ID,
-// };
-// use solana_sdk::pubkey;
-// use solana_sdk::{
-//     account::Account,
-//     instruction::{AccountMeta, Instruction},
-//     native_token::LAMPORTS_PER_SOL,
-//     pubkey::Pubkey,
-// };
-
-// pub const PROGRAM: Pubkey = Pubkey::new_from_array(ID);
-
-// pub const RENT: Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
-
-// pub const PAYER: Pubkey = pubkey!("41LzznNicELmc5iCR9Jxke62a3v1VhzpBYodQF5AQwHX");
-
-// fn main() {
-//     let mollusk = Mollusk::new(&PROGRAM, "target/deploy/solana_pinocchio_starter");
-
-//     let (system_program, system_account) = program::keyed_account_for_system_program();
-
-//     // Create the PDA
-//     let (mystate_pda, bump) =
-//         Pubkey::find_program_address(&[MyState::SEED.as_bytes(), &PAYER.to_bytes()], &PROGRAM);
-
-//     //Initialize the accounts
-//     let payer_account = Account::new(1 * LAMPORTS_PER_SOL, 0, &system_program);
-//     let mystate_account = Account::new(0, 0, &system_program);
-//     let min_balance = mollusk.sysvars.rent.minimum_balance(0);
-//     let rent_account = Account::new(min_balance, 0, &RENT);
-
-//     //Push the accounts in to the instruction_accounts vec!
-//     let ix_accounts = vec![
-//         AccountMeta::new(PAYER, true),
-//         AccountMeta::new(mystate_pda, false),
-//         AccountMeta::new_readonly(RENT, false),
-//         AccountMeta::new_readonly(system_program, false),
-//     ];
-
-//     // Create the instruction data
-//     let ix_data = InitializeMyStateIxData {
-//         owner: *PAYER.as_array(),
-//         data: [1; 32],
-//         bump,
-//     };
-
-//     // Ix discriminator = 0
-//     let mut ser_ix_data = vec![0];
-
-//     // Serialize the instruction data
-//     ser_ix_data.extend_from_slice(unsafe { to_bytes(&ix_data) });
-
-//     // Create instruction
-//     let instruction0 = Instruction::new_with_bytes(PROGRAM, &ser_ix_data, ix_accounts);
-
-//     // Create tx_accounts vec
-//     let tx_accounts0 = &vec![
-//         (PAYER, payer_account.clone()),
-//         (mystate_pda, mystate_account.clone()),
-//         (RENT, rent_account.clone()),
-//         (system_program, system_account.clone()),
-//     ];
-
-//     let rent = mollusk.sysvars.rent.minimum_balance(MyState::LEN);
-//     let mut mystate_account = Account::new(rent, MyState::LEN, &ID.into());
-
-//     let my_state = MyState {
-//         is_initialized: true,
-//         owner: *PAYER.as_array(),
-//         state: State::Initialized,
-//         data: [1; 32],
-//         update_count: 0,
-//     };
-
-//     mystate_account.data = unsafe { to_bytes(&my_state).to_vec() };
-
-//     //Push the accounts in to the instruction_accounts vec!
-//     let ix_accounts = vec![
-//         AccountMeta::new(PAYER, true),
-//         AccountMeta::new(mystate_pda, false),
-//     ];
-
-//     // Create the instruction data
-//     let ix_data = UpdateMyStateIxData { data: [1; 32] };
-
-//     // Ix discriminator = 1
-//     let mut ser_ix_data = vec![1];
-
-//     // Serialize the instruction data
-//     ser_ix_data.extend_from_slice(unsafe { to_bytes(&ix_data) });
-
-//     // Create instruction
-//     let instruction1 = Instruction::new_with_bytes(PROGRAM, &ser_ix_data, ix_accounts);
-//     // Create tx_accounts vec
-//     let tx_accounts1 = &vec![
-//         (PAYER, payer_account.clone()),
-//         (mystate_pda, mystate_account.clone()),
-//     ];
-
-//     MolluskComputeUnitBencher::new(mollusk)
-//         .bench(("InitializeMyState", &instruction0, tx_accounts0))
-//         .bench(("UpdateMyState", &instruction1, tx_accounts1))
-//         .must_pass(true)
-//         .out_dir("benches/")
-//         .execute();
-// }
-
-fn main() {}
+//! Compute units of the wired instructions on their happy paths. Each run appends a
+//! table to `compute_units.md` whose `Delta` column is the change from the previous
+//! run, so a before/after comparison is one run on each side of a change:
+//!
+//! ```sh
+//! cargo build-sbf && cargo bench --features bench-default
+//! ```
+
+use mollusk_svm::Mollusk;
+use mollusk_svm_bencher::MolluskComputeUnitBencher;
+use solana_pinocchio_starter::ID;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    stake::{
+        instruction::LockupArgs,
+        stake_flags::StakeFlags,
+        state::{Authorized, Delegation, Lockup, Meta, Stake, StakeStateV2},
+    },
+    stake_history::StakeHistoryEntry,
+};
+
+pub const PROGRAM: Pubkey = Pubkey::new_from_array(ID);
+
+/// `StakeInstruction::Deactivate`
+const DEACTIVATE_DISCRIMINANT: u8 = 5;
+/// `StakeInstruction::SetLockup`
+const SET_LOCKUP_DISCRIMINANT: u8 = 6;
+/// `StakeInstruction::Merge`
+const MERGE_DISCRIMINANT: u8 = 7;
+/// `StakeInstruction::GetMinimumDelegation`
+const GET_MINIMUM_DELEGATION_DISCRIMINANT: u8 = 13;
+
+fn stake_account(mollusk: &Mollusk, state: &StakeStateV2) -> Account {
+    let mut data = vec![0; StakeStateV2::size_of()];
+    bincode::serialize_into(&mut data[..], state).unwrap();
+    Account {
+        lamports: mollusk.sysvars.rent.minimum_balance(StakeStateV2::size_of()) + LAMPORTS_PER_SOL,
+        data,
+        owner: PROGRAM,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn meta(mollusk: &Mollusk, authority: &Pubkey) -> Meta {
+    Meta {
+        rent_exempt_reserve: mollusk.sysvars.rent.minimum_balance(StakeStateV2::size_of()),
+        authorized: Authorized::auto(authority),
+        lockup: Lockup::default(),
+    }
+}
+
+/// Fills the stake history with `epochs` epochs of a cluster whose warmup was slow,
+/// so classifying a delegation made at epoch 0 walks many entries
+fn slow_warmup_history(mollusk: &mut Mollusk, epochs: u64) {
+    mollusk.sysvars.clock.epoch = epochs;
+    for epoch in 0..epochs {
+        mollusk.sysvars.stake_history.add(
+            epoch,
+            StakeHistoryEntry {
+                effective: 1_000 * LAMPORTS_PER_SOL,
+                activating: 10_000 * LAMPORTS_PER_SOL,
+                deactivating: 0,
+            },
+        );
+    }
+}
+
+fn main() {
+    let mut mollusk = Mollusk::new(&PROGRAM, "target/deploy/solana_pinocchio_starter");
+    slow_warmup_history(&mut mollusk, 100);
+    let authority = Pubkey::new_unique();
+    let (clock, clock_account) = mollusk.sysvars.keyed_account_for_clock_sysvar();
+    let (stake_history, stake_history_account) =
+        mollusk.sysvars.keyed_account_for_stake_history_sysvar();
+
+    let initialized = StakeStateV2::Initialized(meta(&mollusk, &authority));
+    let delegated = StakeStateV2::Stake(
+        meta(&mollusk, &authority),
+        Stake {
+            delegation: Delegation::new(&Pubkey::new_unique(), LAMPORTS_PER_SOL, 0),
+            credits_observed: 0,
+        },
+        StakeFlags::empty(),
+    );
+
+    // Merge: two initialized accounts, signed by their shared staker
+    let destination = Pubkey::new_unique();
+    let source = Pubkey::new_unique();
+    let merge_instruction = Instruction::new_with_bytes(
+        PROGRAM,
+        &[MERGE_DISCRIMINANT],
+        vec![
+            AccountMeta::new(destination, false),
+            AccountMeta::new(source, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(stake_history, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+    );
+    let merge_accounts = vec![
+        (destination, stake_account(&mollusk, &initialized)),
+        (source, stake_account(&mollusk, &initialized)),
+        (clock, clock_account.clone()),
+        (stake_history, stake_history_account.clone()),
+        (authority, Account::default()),
+    ];
+
+    // Merge of two delegations made 100 epochs ago: the history walks stop once the
+    // stake is fully active, rather than running up to the current epoch
+    let old_destination = Pubkey::new_unique();
+    let old_source = Pubkey::new_unique();
+    let old_merge_instruction = Instruction::new_with_bytes(
+        PROGRAM,
+        &[MERGE_DISCRIMINANT],
+        vec![
+            AccountMeta::new(old_destination, false),
+            AccountMeta::new(old_source, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(stake_history, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+    );
+    let old_merge_accounts = vec![
+        (old_destination, stake_account(&mollusk, &delegated)),
+        (old_source, stake_account(&mollusk, &delegated)),
+        (clock, clock_account.clone()),
+        (stake_history, stake_history_account),
+        (authority, Account::default()),
+    ];
+
+    // Deactivate: a delegated account, signed by its staker
+    let stake = Pubkey::new_unique();
+    let deactivate_instruction = Instruction::new_with_bytes(
+        PROGRAM,
+        &[DEACTIVATE_DISCRIMINANT],
+        vec![
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+    );
+    let deactivate_accounts = vec![
+        (stake, stake_account(&mollusk, &delegated)),
+        (clock, clock_account),
+        (authority, Account::default()),
+    ];
+
+    // SetLockup: no lockup in force, so the withdrawer sets a new epoch
+    let args = LockupArgs {
+        unix_timestamp: None,
+        epoch: Some(1),
+        custodian: None,
+    };
+    let mut set_lockup_data = vec![SET_LOCKUP_DISCRIMINANT];
+    set_lockup_data.extend(bincode::serialize(&args).unwrap());
+    let set_lockup_instruction = Instruction::new_with_bytes(
+        PROGRAM,
+        &set_lockup_data,
+        vec![
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+    );
+    let set_lockup_accounts = vec![
+        (stake, stake_account(&mollusk, &initialized)),
+        (authority, Account::default()),
+    ];
+
+    let get_minimum_delegation_instruction =
+        Instruction::new_with_bytes(PROGRAM, &[GET_MINIMUM_DELEGATION_DISCRIMINANT], vec![]);
+
+    MolluskComputeUnitBencher::new(mollusk)
+        .bench(("Merge", &merge_instruction, &merge_accounts))
+        .bench(("MergeOldDelegations", &old_merge_instruction, &old_merge_accounts))
+        .bench(("Deactivate", &deactivate_instruction, &deactivate_accounts))
+        .bench(("SetLockup", &set_lockup_instruction, &set_lockup_accounts))
+        .bench(("GetMinimumDelegation", &get_minimum_delegation_instruction, &[]))
+        .must_pass(true)
+        .out_dir("benches/")
+        .execute();
+}
@@ -2,15 +2,25 @@ use pinocchio::pubkey::Pubkey;
 use pinocchio_pubkey::pubkey;
 
 pub const MAX_SIGNERS: usize = 32;
-pub const FEATURE_STAKE_RAISE_MINIMUM_DELEGATION_TO_1_SOL: bool = false;
-pub const PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH: Option<[u8; 8]> = Some((0u64).to_le_bytes());
 pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
 pub const SYSVAR: Pubkey = pubkey!("Sysvar1111111111111111111111111111111111111");
 pub const DEFAULT_WARMUP_COOLDOWN_RATE: f64 = 0.25;
 pub const NEW_WARMUP_COOLDOWN_RATE: f64 = 0.09;
 pub const CLOCK_ID: Pubkey = pubkey!("SysvarC1ock11111111111111111111111111111111");
+pub const RENT_ID: Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
+pub const STAKE_CONFIG_ID: Pubkey = pubkey!("StakeConfig11111111111111111111111111111111");
 pub const VOTE_PROGRAM_ID: Pubkey = pubkey!("Vote111111111111111111111111111111111111111");
 
+// Default `Rent` parameters, which every cluster runs with
+pub const DEFAULT_LAMPORTS_PER_BYTE_YEAR: u64 = 1_000_000_000 / 100 * 365 / (1024 * 1024);
+pub const DEFAULT_EXEMPTION_THRESHOLD: f64 = 2.0;
+/// Bytes of account metadata charged for on top of the data
+pub const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+/// `Rent::minimum_balance(StakeStateV2::size_of())` under the default parameters
+pub const DEFAULT_STAKE_RENT_EXEMPT_RESERVE: u64 = (ACCOUNT_STORAGE_OVERHEAD + 200)
+    * DEFAULT_LAMPORTS_PER_BYTE_YEAR
+    * DEFAULT_EXEMPTION_THRESHOLD as u64;
+
 // Maximum number of votes to keep around, tightly coupled with epoch_schedule::MINIMUM_SLOTS_PER_EPOCH
 pub const MAX_LOCKOUT_HISTORY: usize = 31;
 pub const INITIAL_LOCKOUT: usize = 2;
@@ -19,7 +29,7 @@ pub const INITIAL_LOCKOUT: usize = 2;
 pub const MAX_EPOCH_CREDITS_HISTORY: usize = 64;
 
 // Offset of VoteState::prior_voters, for determining initialization status without deserialization
-const DEFAULT_PRIOR_VOTERS_OFFSET: usize = 114;
+pub const DEFAULT_PRIOR_VOTERS_OFFSET: usize = 114;
 
 // Number of slots of grace period for which maximum vote credits are awarded - votes landing within this number of slots of the slot that is being voted on are awarded full credits.
 pub const VOTE_CREDITS_GRACE_SLOTS: u8 = 2;
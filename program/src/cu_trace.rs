@@ -0,0 +1,35 @@
+//! Per-phase compute unit attribution, compiled in only with the `cu-trace`
+//! feature. The entrypoint marks the end of its own parse and validate phases, then
+//! processors mark the end of each of theirs (parse, validate, classify, mutate),
+//! so the units a phase took are the difference between two consecutive log lines:
+//!
+//! ```text
+//! Program log: cu entrypoint/validate: 199410
+//! Program log: cu merge/parse: 198732
+//! ...
+//! ```
+//!
+//! Each line also costs the log itself, a roughly constant overhead which lands on
+//! the phase after it.
+//!
+//! Without the feature `phase` compiles to nothing.
+
+/// Logs the compute units left when the phase `label` ends
+#[inline(always)]
+pub(crate) fn phase(_label: &str) {
+    #[cfg(feature = "cu-trace")]
+    log_remaining(_label);
+}
+
+#[cfg(feature = "cu-trace")]
+#[inline(never)]
+fn log_remaining(label: &str) {
+    #[cfg(target_os = "solana")]
+    // SAFETY: the syscall takes no arguments and only reads the meter
+    let remaining = unsafe { pinocchio::syscalls::sol_remaining_compute_units() };
+    // off-chain there is no meter to read
+    #[cfg(not(target_os = "solana"))]
+    let remaining = 0u64;
+
+    pinocchio_log::log!("cu {}: {}", label, remaining);
+}
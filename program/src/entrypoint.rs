@@ -1,12 +1,48 @@
 #![allow(unexpected_cfgs)]
 
-use crate::instruction::{self, StakeInstruction};
+use crate::{
+    features::FeatureSet,
+    instruction::{self, StakeInstruction},
+    validation::{validate_accounts, ValidatedAccounts, MAX_INSTRUCTION_ACCOUNTS},
+};
+use core::mem::MaybeUninit;
 use pinocchio::{
-    account_info::AccountInfo, default_panic_handler, default_allocator, program_entrypoint, program_error::ProgramError, pubkey::Pubkey, ProgramResult
+    account_info::AccountInfo, default_panic_handler, default_allocator, entrypoint::deserialize, program_error::ProgramError, pubkey::Pubkey, ProgramResult, SUCCESS
 };
 
-// This is the entrypoint for the program.
-program_entrypoint!(process_instruction);
+/// This is the entrypoint for the program, `program_entrypoint!` with one change:
+/// only the accounts an instruction can use are deserialized, anything past
+/// `MAX_INSTRUCTION_ACCOUNTS` is skipped like native skips trailing accounts. A
+/// signer past the cap goes unseen where native would count it. With the
+/// `strict-accounts` feature the instruction fails instead, as `validate_accounts`
+/// never gets to see the skipped accounts.
+///
+/// # Safety
+///
+/// `input` is the serialized program input the runtime passes to every program.
+#[no_mangle]
+pub unsafe extern "C" fn entrypoint(input: *mut u8) -> u64 {
+    // the input leads with the number of accounts
+    #[cfg(feature = "strict-accounts")]
+    if *(input as *const u64) > MAX_INSTRUCTION_ACCOUNTS as u64 {
+        return ProgramError::InvalidArgument.into();
+    }
+
+    const UNINIT: MaybeUninit<AccountInfo> = MaybeUninit::<AccountInfo>::uninit();
+    let mut accounts = [UNINIT; MAX_INSTRUCTION_ACCOUNTS];
+    let (program_id, count, instruction_data) =
+        deserialize::<MAX_INSTRUCTION_ACCOUNTS>(input, &mut accounts);
+
+    match process_instruction(
+        program_id,
+        core::slice::from_raw_parts(accounts.as_ptr() as _, count),
+        instruction_data,
+    ) {
+        Ok(()) => SUCCESS,
+        Err(error) => error.into(),
+    }
+}
+
 //Do not allocate memory.
 // no_allocator!();
 default_allocator!();
@@ -33,6 +69,10 @@ fn process_instruction(
     //     .ok_or(ProgramError::InvalidInstructionData)?;
 
     let instruction = StakeInstruction::try_from(ix_disc)?;
+    instruction.check_args_len(instruction_data)?;
+    crate::cu_trace::phase("entrypoint/parse");
+    let accounts = validate_accounts(&instruction, accounts)?;
+    crate::cu_trace::phase("entrypoint/validate");
 
     // TODO: add check for epoch_rewards_active
     // let epoch_rewards_active = EpochRewards::get()
@@ -42,12 +82,34 @@ fn process_instruction(
     //     return Err(StakeError::EpochRewardsActive.into());
     // }
 
+    #[cfg(feature = "paranoid")]
+    let lamports_before =
+        crate::paranoid::LamportSnapshot::take(&instruction, accounts.accounts());
+
+    let result = dispatch(instruction, accounts, instruction_data, &FeatureSet::BUILT_IN);
+
+    // a failed instruction is rolled back by the runtime, whatever it left behind
+    #[cfg(feature = "paranoid")]
+    if result.is_ok() {
+        lamports_before.assert_conserved(accounts.accounts());
+    }
+
+    result
+}
+
+#[inline(always)]
+fn dispatch(
+    instruction: StakeInstruction,
+    accounts: ValidatedAccounts,
+    instruction_data: &[u8],
+    features: &FeatureSet,
+) -> ProgramResult {
     match instruction {
         StakeInstruction::Initialize => {
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: Initialize");
 
-            todo!()
+            instruction::process_initialize(accounts, instruction_data)
         }
         StakeInstruction::Authorize => {
             #[cfg(feature = "logging")]
@@ -59,7 +121,7 @@ fn process_instruction(
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: DelegateStake");
 
-            todo!()
+            instruction::process_delegate(accounts, features)
         }
         StakeInstruction::Split => {
             #[cfg(feature = "logging")]
@@ -71,13 +133,13 @@ fn process_instruction(
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: Withdraw");
 
-            todo!()
+            instruction::process_withdraw(accounts, instruction_data, features)
         }
         StakeInstruction::Deactivate => {
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: Deactivate");
 
-            todo!()
+            instruction::process_deactivate(accounts, features)
         }
         StakeInstruction::SetLockup => {
             #[cfg(feature = "logging")]
@@ -88,8 +150,8 @@ fn process_instruction(
         StakeInstruction::Merge => {
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: Merge");
-            
-            todo!()
+
+            instruction::process_merge(accounts, features)
         }
         StakeInstruction::AuthorizeWithSeed => {
             #[cfg(feature = "logging")]
@@ -125,7 +187,7 @@ fn process_instruction(
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: GetMinimumDelegation");
 
-            todo!()
+            instruction::process_get_minimum_delegation(features)
         }
         StakeInstruction::DeactivateDelinquent => {
             #[cfg(feature = "logging")]
@@ -147,7 +209,7 @@ fn process_instruction(
             #[cfg(feature = "logging")]
             pinocchio::msg!("Instruction: MoveLamports");
 
-            // instruction::process_move_lamports(accounts, lamports)
+            // instruction::process_move_lamports(accounts, lamports, features)
             todo!()
         }
     }
@@ -76,6 +76,14 @@ pub enum StakeError {
     EpochRewardsActive,
 }
 
+/// The error of every failed authority or signer check. Kept out of line so the
+/// checks themselves inline down to the comparison on the happy path.
+#[cold]
+#[inline(never)]
+pub(crate) fn missing_required_signature() -> ProgramError {
+    ProgramError::MissingRequiredSignature
+}
+
 impl From<StakeError> for ProgramError {
     fn from(e: StakeError) -> Self {
         ProgramError::Custom(e as u32)
@@ -387,7 +395,3 @@ impl TryFrom<InstructionError> for ProgramError {
         }
     }
 }
-
-pub(crate) fn to_program_error(e: InstructionError) -> ProgramError {
-    ProgramError::try_from(e).unwrap_or(ProgramError::InvalidAccountData)
-}
@@ -0,0 +1,39 @@
+//! The cluster features the stake program depends on.
+//!
+//! A builtin reads feature activations from the bank, but a BPF program only sees
+//! the accounts an instruction passes, and the native instruction layouts have no
+//! position a feature account could take without clashing with the optional
+//! trailing accounts (custodians, stake authorities). The feature set is therefore
+//! fixed when the program is built, to its state on the clusters the program deploys
+//! to, and the entrypoint hands it to every processor that depends on it.
+
+use pinocchio::{pubkey::Pubkey, sysvars::clock::Epoch};
+use pinocchio_pubkey::pubkey;
+
+/// `stake_raise_minimum_delegation_to_1_sol`
+pub const STAKE_RAISE_MINIMUM_DELEGATION_TO_1_SOL_ID: Pubkey =
+    pubkey!("9onWzzvCzNC2jfhxxeqRgs5q7nFAAKpCUvkj6T6GJK9i");
+
+/// `reduce_stake_warmup_cooldown`
+pub const REDUCE_STAKE_WARMUP_COOLDOWN_ID: Pubkey =
+    pubkey!("GwtDQBghCTBgmX2cpEGNPxTEBUTQRaDMGTr5qychdGMj");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeatureSet {
+    /// whether `STAKE_RAISE_MINIMUM_DELEGATION_TO_1_SOL_ID` is active
+    pub stake_raise_minimum_delegation_to_1_sol: bool,
+    /// the epoch `REDUCE_STAKE_WARMUP_COOLDOWN_ID` took effect, `None` while pending
+    pub new_warmup_cooldown_rate_epoch: Option<Epoch>,
+}
+
+impl FeatureSet {
+    /// The features the program is built with. `reduce_stake_warmup_cooldown` is
+    /// active on every cluster and the program goes live after it, so the new rate
+    /// holds from epoch 0. `stake_raise_minimum_delegation_to_1_sol` is active
+    /// nowhere yet; a build for a cluster which activates it turns on the
+    /// `raise-minimum-delegation` feature.
+    pub const BUILT_IN: Self = Self {
+        stake_raise_minimum_delegation_to_1_sol: cfg!(feature = "raise-minimum-delegation"),
+        new_warmup_cooldown_rate_epoch: Some(0),
+    };
+}
@@ -0,0 +1,186 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+use super::{decode_option, decode_option_pubkey};
+use crate::state::StakeAuthorize;
+
+/// Bounds-checked reader over instruction data. Every read either consumes exactly
+/// the bytes it decodes or fails with `InvalidInstructionData`, leaving the position
+/// where it was.
+pub(crate) struct Cursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    #[inline(always)]
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    /// The bytes which have not been read yet
+    #[inline(always)]
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+
+    pub(crate) fn read_slice(&mut self, len: usize) -> Result<&'a [u8], ProgramError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    #[inline(always)]
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ProgramError> {
+        self.read_slice(N).map(|bytes| bytes.try_into().unwrap())
+    }
+
+    #[inline(always)]
+    pub(crate) fn read_u8(&mut self) -> Result<u8, ProgramError> {
+        self.read_array::<1>().map(|[byte]| byte)
+    }
+
+    #[inline(always)]
+    pub(crate) fn read_u32(&mut self) -> Result<u32, ProgramError> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    #[inline(always)]
+    pub(crate) fn read_u64(&mut self) -> Result<u64, ProgramError> {
+        self.read_array().map(u64::from_le_bytes)
+    }
+
+    #[inline(always)]
+    pub(crate) fn read_pubkey(&mut self) -> Result<Pubkey, ProgramError> {
+        self.read_array()
+    }
+
+    /// Reads a bincode `StakeAuthorize`: a `u32` variant index
+    pub(crate) fn read_stake_authorize(&mut self) -> Result<StakeAuthorize, ProgramError> {
+        let stake_authorize = match self.remaining().get(..4) {
+            Some([0, 0, 0, 0]) => StakeAuthorize::Staker,
+            Some([1, 0, 0, 0]) => StakeAuthorize::Withdrawer,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+        self.offset += 4;
+        Ok(stake_authorize)
+    }
+
+    /// Reads a bincode `String`: a `u64` length, then as many UTF-8 bytes
+    pub(crate) fn read_str(&mut self) -> Result<&'a str, ProgramError> {
+        let start = self.offset;
+        let str = self
+            .read_u64()
+            .and_then(|len| {
+                let len = usize::try_from(len).map_err(|_| ProgramError::InvalidInstructionData)?;
+                self.read_slice(len)
+            })
+            .and_then(|bytes| {
+                core::str::from_utf8(bytes).map_err(|_| ProgramError::InvalidInstructionData)
+            });
+        if str.is_err() {
+            self.offset = start;
+        }
+        str
+    }
+
+    /// Reads a bincode `Option<[u8; N]>`: a 1-byte tag, then `N` bytes when `Some`
+    pub(crate) fn read_option<const N: usize>(&mut self) -> Result<Option<[u8; N]>, ProgramError> {
+        let (value, len) = decode_option::<N>(self.remaining())?;
+        self.offset += len;
+        Ok(value)
+    }
+
+    /// Reads a bincode `Option<Pubkey>`, as custodians are encoded
+    #[inline(always)]
+    pub(crate) fn read_option_pubkey(&mut self) -> Result<Option<Pubkey>, ProgramError> {
+        let (value, len) = decode_option_pubkey(self.remaining())?;
+        self.offset += len;
+        Ok(value)
+    }
+
+    /// Fails unless every byte has been read
+    #[inline(always)]
+    pub(crate) fn finish(self) -> Result<(), ProgramError> {
+        if self.offset != self.data.len() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_reads() {
+        let mut data = [0u8; 1 + 4 + 8 + 32 + 9 + 1];
+        data[0] = 7;
+        data[1..5].copy_from_slice(&42u32.to_le_bytes());
+        data[5..13].copy_from_slice(&u64::MAX.to_le_bytes());
+        data[13..45].copy_from_slice(&[3; 32]);
+        data[45] = 1;
+        data[46..54].copy_from_slice(&9u64.to_le_bytes());
+
+        let mut cursor = Cursor::new(&data);
+        assert_eq!(cursor.read_u8().unwrap(), 7);
+        assert_eq!(cursor.read_u32().unwrap(), 42);
+        assert_eq!(cursor.read_u64().unwrap(), u64::MAX);
+        assert_eq!(cursor.read_pubkey().unwrap(), [3; 32]);
+        assert_eq!(cursor.read_option::<8>().unwrap(), Some(9u64.to_le_bytes()));
+        assert_eq!(cursor.read_option_pubkey().unwrap(), None);
+        cursor.finish().unwrap();
+    }
+
+    #[test]
+    fn test_cursor_reads_bincode_types() {
+        let mut data = [0u8; 4 + 8 + 4 + 4];
+        data[0] = 1;
+        data[4] = 4;
+        data[12..16].copy_from_slice(b"seed");
+
+        let mut cursor = Cursor::new(&data);
+        assert_eq!(cursor.read_stake_authorize().unwrap(), StakeAuthorize::Withdrawer);
+        assert_eq!(cursor.read_str().unwrap(), "seed");
+        assert_eq!(cursor.read_stake_authorize().unwrap(), StakeAuthorize::Staker);
+        cursor.finish().unwrap();
+
+        // an unknown role, a string past the end and one that is not UTF-8
+        let mut cursor = Cursor::new(&[2, 0, 0, 0]);
+        assert_eq!(
+            cursor.read_stake_authorize().unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        assert_eq!(cursor.remaining().len(), 4);
+        let mut cursor = Cursor::new(&[5, 0, 0, 0, 0, 0, 0, 0, b'a']);
+        assert_eq!(cursor.read_str().unwrap_err(), ProgramError::InvalidInstructionData);
+        assert_eq!(cursor.remaining().len(), 9);
+        let mut cursor = Cursor::new(&[1, 0, 0, 0, 0, 0, 0, 0, 0xff]);
+        assert_eq!(cursor.read_str().unwrap_err(), ProgramError::InvalidInstructionData);
+        assert_eq!(cursor.remaining().len(), 9);
+    }
+
+    #[test]
+    fn test_cursor_out_of_bounds() {
+        let mut cursor = Cursor::new(&[1, 2, 3]);
+        assert_eq!(
+            cursor.read_u32().unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        // a failed read consumes nothing
+        assert_eq!(cursor.remaining(), &[1, 2, 3]);
+        assert_eq!(cursor.read_slice(usize::MAX).unwrap_err(), ProgramError::InvalidInstructionData);
+        cursor.read_u8().unwrap();
+        assert_eq!(
+            cursor.finish().unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+}
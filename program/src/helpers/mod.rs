@@ -1,7 +1,91 @@
-pub(crate) mod merge;
-pub(crate) use merge::*;
-use pinocchio::program_error::ProgramError;
+pub(crate) mod cursor;
+pub(crate) mod option;
+pub mod return_data;
+pub mod seed;
+pub(crate) use cursor::*;
+pub(crate) use option::*;
+pub use return_data::*;
+pub use seed::*;
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+// Every helper reports a failed operation as `ArithmeticOverflow`, whichever way it
+// overflowed. Callers that need a domain error (e.g. `InsufficientFunds` for a lamport
+// debit) check for it explicitly before doing the math.
 
 pub(crate) fn checked_add(a: u64, b: u64) -> Result<u64, ProgramError> {
-    a.checked_add(b).ok_or(ProgramError::InsufficientFunds)
+    a.checked_add(b).ok_or(ProgramError::ArithmeticOverflow)
+}
+
+pub(crate) fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_sub(b).ok_or(ProgramError::ArithmeticOverflow)
+}
+
+/// Sums stake amounts in a u128, which cannot wrap for any number of u64 terms a
+/// processor adds, then narrows once: whichever term pushed the total past
+/// `u64::MAX`, the failure is the same `ArithmeticOverflow`.
+pub(crate) fn sum_lamports(amounts: &[u64]) -> Result<u64, ProgramError> {
+    let total = amounts
+        .iter()
+        .fold(0u128, |total, amount| total + u128::from(*amount));
+    u64::try_from(total).map_err(|_| ProgramError::ArithmeticOverflow)
+}
+
+/// Pubkey equality as four u64 comparisons when both keys are 8-byte aligned, as
+/// keys inside account data and the input buffer usually are. Falls back to the
+/// bytewise comparison otherwise.
+#[inline(always)]
+pub(crate) fn pubkey_eq(a: &Pubkey, b: &Pubkey) -> bool {
+    let (a_ptr, b_ptr) = (a.as_ptr(), b.as_ptr());
+    if (a_ptr as usize | b_ptr as usize) % core::mem::align_of::<u64>() != 0 {
+        return a == b;
+    }
+    // SAFETY: both point to 32 initialized bytes, just checked to be u64 aligned
+    let (a, b) = unsafe { (&*(a_ptr as *const [u64; 4]), &*(b_ptr as *const [u64; 4])) };
+    ((a[0] ^ b[0]) | (a[1] ^ b[1]) | (a[2] ^ b[2]) | (a[3] ^ b[3])) == 0
+}
+
+/// Whether `key` is one of `keys`, compared through `pubkey_eq`
+#[inline(always)]
+pub(crate) fn contains_pubkey(keys: &[Pubkey], key: &Pubkey) -> bool {
+    keys.iter().any(|k| pubkey_eq(k, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_lamports() {
+        assert_eq!(sum_lamports(&[]), Ok(0));
+        assert_eq!(sum_lamports(&[1, 2, 3]), Ok(6));
+        assert_eq!(sum_lamports(&[u64::MAX, 0]), Ok(u64::MAX));
+        assert_eq!(sum_lamports(&[u64::MAX, 1]), Err(ProgramError::ArithmeticOverflow));
+        assert_eq!(
+            sum_lamports(&[u64::MAX, u64::MAX, u64::MAX]),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_pubkey_eq() {
+        #[repr(C, align(8))]
+        struct Aligned([u8; 40]);
+
+        // the first key at every offset within a word, so both paths are taken
+        for offset in 0..8 {
+            for differing_byte in [None, Some(0), Some(17), Some(31)] {
+                let mut a_buffer = Aligned([0; 40]);
+                let a: &mut Pubkey = (&mut a_buffer.0[offset..offset + 32]).try_into().unwrap();
+                a.copy_from_slice(&[7; 32]);
+                let mut b = Aligned([7; 40]);
+                if let Some(i) = differing_byte {
+                    b.0[i] = 8;
+                }
+                let b: &Pubkey = b.0[..32].try_into().unwrap();
+
+                assert_eq!(pubkey_eq(a, b), differing_byte.is_none());
+                assert_eq!(contains_pubkey(&[[0; 32], *b], a), differing_byte.is_none());
+            }
+        }
+    }
 }
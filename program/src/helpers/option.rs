@@ -0,0 +1,89 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+/// bincode tag for `None`
+const NONE_TAG: u8 = 0;
+/// bincode tag for `Some`, followed by the value bytes
+const SOME_TAG: u8 = 1;
+
+/// Decodes a bincode `Option<[u8; N]>` from the start of `data`: a 1-byte tag, then
+/// `N` bytes when the tag is `Some`. Returns the value and the number of bytes consumed.
+pub(crate) fn decode_option<const N: usize>(
+    data: &[u8],
+) -> Result<(Option<[u8; N]>, usize), ProgramError> {
+    match data.first() {
+        Some(&NONE_TAG) => Ok((None, 1)),
+        Some(&SOME_TAG) => {
+            let value = data
+                .get(1..1 + N)
+                .ok_or(ProgramError::InvalidInstructionData)?;
+            Ok((Some(value.try_into().unwrap()), 1 + N))
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Decodes an `Option<Pubkey>` (1-byte tag + 32 bytes), as used for custodians
+#[inline(always)]
+pub(crate) fn decode_option_pubkey(data: &[u8]) -> Result<(Option<Pubkey>, usize), ProgramError> {
+    decode_option::<32>(data)
+}
+
+/// Encodes `value` as a bincode `Option<[u8; N]>` into the start of `out`, returning
+/// the number of bytes written.
+pub(crate) fn encode_option<const N: usize>(
+    value: &Option<[u8; N]>,
+    out: &mut [u8],
+) -> Result<usize, ProgramError> {
+    match value {
+        None => {
+            *out.first_mut().ok_or(ProgramError::AccountDataTooSmall)? = NONE_TAG;
+            Ok(1)
+        }
+        Some(value) => {
+            let dst = out
+                .get_mut(..1 + N)
+                .ok_or(ProgramError::AccountDataTooSmall)?;
+            dst[0] = SOME_TAG;
+            dst[1..].copy_from_slice(value);
+            Ok(1 + N)
+        }
+    }
+}
+
+/// Encodes an `Option<Pubkey>` (1-byte tag + 32 bytes)
+#[inline(always)]
+pub(crate) fn encode_option_pubkey(value: &Option<Pubkey>, out: &mut [u8]) -> Result<usize, ProgramError> {
+    encode_option::<32>(value, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_pubkey_round_trip() {
+        let mut buf = [0u8; 33];
+        for value in [None, Some([7u8; 32])] {
+            let written = encode_option_pubkey(&value, &mut buf).unwrap();
+            let (decoded, read) = decode_option_pubkey(&buf[..written]).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(read, written);
+        }
+    }
+
+    #[test]
+    fn test_decode_option_malformed() {
+        assert_eq!(
+            decode_option_pubkey(&[]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        assert_eq!(
+            decode_option_pubkey(&[2]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        assert_eq!(
+            decode_option_pubkey(&[1; 32]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use pinocchio::pubkey::Pubkey;
+
+/// Largest payload the runtime keeps as return data
+pub const MAX_RETURN_DATA: usize = 1024;
+
+/// Publishes `data` as this instruction's return data, replacing whatever an earlier
+/// instruction or CPI left behind. Payloads past `MAX_RETURN_DATA` make the runtime
+/// fail the instruction.
+#[inline(always)]
+pub fn set_return_data(data: &[u8]) {
+    #[cfg(target_os = "solana")]
+    unsafe {
+        pinocchio::syscalls::sol_set_return_data(data.as_ptr(), data.len() as u64)
+    };
+
+    // off chain there is no runtime to hand the data to
+    #[cfg(not(target_os = "solana"))]
+    core::hint::black_box(data);
+}
+
+/// Copies the current return data into `buf` and returns the program that set it
+/// together with the length of the data, which may exceed what fit into `buf`.
+/// `None` when no return data is set.
+pub fn get_return_data(buf: &mut [u8]) -> Option<(Pubkey, usize)> {
+    #[cfg(target_os = "solana")]
+    {
+        let mut program_id = Pubkey::default();
+        // SAFETY: the syscall writes at most `buf.len()` bytes into `buf` and a full
+        // pubkey into `program_id`
+        let len = unsafe {
+            pinocchio::syscalls::sol_get_return_data(
+                buf.as_mut_ptr(),
+                buf.len() as u64,
+                &mut program_id,
+            )
+        } as usize;
+
+        if len == 0 {
+            None
+        } else {
+            Some((program_id, len))
+        }
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        core::hint::black_box(buf);
+        None
+    }
+}
+
+/// Publishes a little-endian `u64`, e.g. the minimum delegation
+#[inline(always)]
+pub fn set_return_u64(value: u64) {
+    set_return_data(&value.to_le_bytes());
+}
+
+/// Reads back a `u64` published with `set_return_u64` by `program_id`, as a caller
+/// does after invoking `GetMinimumDelegation`. Return data from any other program,
+/// or of any other length, yields `None`.
+pub fn get_return_u64(program_id: &Pubkey) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    match get_return_data(&mut buf)? {
+        (setter, 8) if setter == *program_id => Some(u64::from_le_bytes(buf)),
+        _ => None,
+    }
+}
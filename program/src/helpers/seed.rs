@@ -0,0 +1,93 @@
+use pinocchio::{
+    program_error::ProgramError,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+};
+
+/// Suffix reserved for program derived addresses; an owner ending in it could make a
+/// seeded address collide with a PDA
+const PDA_MARKER: &[u8; 21] = b"ProgramDerivedAddress";
+
+/// `Pubkey::create_with_seed`: the sha256 of `base || seed || owner`, the address
+/// `SystemInstruction::CreateAccountWithSeed` creates. This is what seeded stake
+/// authorities are derived with, not the program-address derivation.
+pub fn create_with_seed(
+    base: &Pubkey,
+    seed: &[u8],
+    owner: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    if seed.len() > MAX_SEED_LEN {
+        return Err(ProgramError::MaxSeedLengthExceeded);
+    }
+    if owner[owner.len() - PDA_MARKER.len()..] == PDA_MARKER[..] {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(sha256(&[base.as_ref(), seed, owner.as_ref()]))
+}
+
+#[cfg(target_os = "solana")]
+fn sha256(vals: &[&[u8]]) -> Pubkey {
+    let mut hash = [0u8; 32];
+    // SAFETY: `vals` is laid out as the (pointer, length) pairs the syscall reads and
+    // `hash` has room for the 32-byte digest
+    unsafe {
+        pinocchio::syscalls::sol_sha256(
+            vals as *const _ as *const u8,
+            vals.len() as u64,
+            hash.as_mut_ptr(),
+        );
+    }
+    hash
+}
+
+// off chain there is no syscall to hash with
+#[cfg(not(target_os = "solana"))]
+fn sha256(vals: &[&[u8]]) -> Pubkey {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for val in vals {
+        hasher.update(val);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey as NativePubkey;
+
+    #[test]
+    fn test_create_with_seed_matches_native() {
+        let base = [7u8; 32];
+        let owner = crate::ID;
+        for seed in ["", "stake", "a seed of exactly thirty-two b.."] {
+            let expected = NativePubkey::create_with_seed(
+                &NativePubkey::new_from_array(base),
+                seed,
+                &NativePubkey::new_from_array(owner),
+            )
+            .unwrap();
+            assert_eq!(
+                create_with_seed(&base, seed.as_bytes(), &owner).unwrap(),
+                expected.to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_with_seed_errors() {
+        let base = [7u8; 32];
+        assert_eq!(
+            create_with_seed(&base, &[b'x'; MAX_SEED_LEN + 1], &crate::ID),
+            Err(ProgramError::MaxSeedLengthExceeded)
+        );
+
+        let mut owner = [1u8; 32];
+        owner[32 - PDA_MARKER.len()..].copy_from_slice(PDA_MARKER);
+        assert_eq!(
+            create_with_seed(&base, b"stake", &owner),
+            Err(ProgramError::IllegalOwner)
+        );
+    }
+}
@@ -1,14 +1,19 @@
 use pinocchio::{
-    account_info::AccountInfo,
-    program_error::ProgramError,
-    pubkey::{self, Pubkey},
-    ProgramResult,
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
 };
 
-use crate::state::{
-    add_signer, clock_from_account_info, collect_signers_checked, do_authorize, StakeAuthorize,
+use crate::{
+    helpers::{create_with_seed, Cursor},
+    parse_accounts,
+    state::{
+        add_signer_key, clock_from_account_info, collect_signers_checked, do_authorize,
+        StakeAuthorize,
+    },
 };
 
+// the seed is borrowed from instruction data, so borsh can only encode these args
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AuthorizeWithSeedArgs<'a> {
     pub new_authorized_pubkey: Pubkey,
@@ -17,7 +22,29 @@ pub struct AuthorizeWithSeedArgs<'a> {
     pub authority_owner: Pubkey,
 }
 
+impl<'a> AuthorizeWithSeedArgs<'a> {
+    /// Decodes the bincode encoding upstream sends: the new authority, the role as a
+    /// `u32` tag, the seed as a `u64`-length string, then its owner
+    pub fn from_data(data: &'a [u8]) -> Result<Self, ProgramError> {
+        let mut cursor = Cursor::new(data);
+        let new_authorized_pubkey = cursor.read_pubkey()?;
+        let stake_authorize = cursor.read_stake_authorize()?;
+        let authority_seed = cursor.read_str()?;
+        let authority_owner = cursor.read_pubkey()?;
+        cursor.finish()?;
+
+        Ok(AuthorizeWithSeedArgs {
+            new_authorized_pubkey,
+            stake_authorize,
+            authority_seed,
+            authority_owner,
+        })
+    }
+}
+
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AuthorizeCheckedWithSeedArgs<'a> {
     pub authority_owner: Pubkey,
@@ -38,11 +65,12 @@ pub fn process_authorize_with_seed(
     accounts: &[AccountInfo],
     authorize_args: AuthorizeWithSeedArgs,
 ) -> ProgramResult {
-    let [stake_account_info, stake_or_withdraw_authority_base_info, clock_info, remaining @ ..] =
-        accounts
-    else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    parse_accounts!(accounts => [
+        stake_account_info,
+        stake_or_withdraw_authority_base_info,
+        clock_info,
+        ..remaining
+    ]);
 
     let clock = clock_from_account_info(clock_info)?;
 
@@ -52,20 +80,20 @@ pub fn process_authorize_with_seed(
     let (mut signers, custodian, mut signers_count) =
         collect_signers_checked(None, option_lockup_authority_info)?;
 
-    let seeds = &[
-        stake_or_withdraw_authority_base_info.key().as_ref(),
+    let derived_key = create_with_seed(
+        stake_or_withdraw_authority_base_info.key(),
         authorize_args.authority_seed.as_bytes(),
-        authorize_args.authority_owner.as_ref(),
-    ];
-    let derived_key = pubkey::checked_create_program_address(seeds, &crate::id())?;
+        &authorize_args.authority_owner,
+    )?;
 
+    // the derived key never signs itself, the signature of its base stands in for it
     if stake_or_withdraw_authority_base_info.is_signer() {
-        add_signer(&mut signers, &mut signers_count, &derived_key)?;
+        add_signer_key(&mut signers, &mut signers_count, &derived_key)?;
     }
 
     do_authorize(
         stake_account_info,
-        &signers,
+        &signers[..signers_count],
         &authorize_args.new_authorized_pubkey,
         authorize_args.stake_authorize,
         custodian,
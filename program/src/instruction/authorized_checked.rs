@@ -2,27 +2,30 @@ use pinocchio::{
     account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
 };
 
-use crate::state::{clock_from_account_info, collect_signers, do_authorize, StakeAuthorize};
+use crate::{
+    consts::MAX_SIGNERS,
+    parse_accounts,
+    state::{clock_from_account_info, collect_signers, do_authorize, StakeAuthorize},
+};
 
 pub fn process_authorize_checked(
     accounts: &[AccountInfo],
     authority_type: StakeAuthorize,
 ) -> ProgramResult {
-    let mut signers = [Pubkey::default(); 32];
-    let _signers_len = collect_signers(accounts, &mut signers)?;
+    let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers_arr)?;
+    let signers = &signers_arr[..signers_len];
 
-    let [stake_account_info, clock_info, _old_stake_or_withdraw_authority_info, new_stake_or_withdraw_authority_info, rest @ ..] =
-        accounts
-    else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    parse_accounts!(accounts => [
+        stake_account_info,
+        clock_info,
+        _old_stake_or_withdraw_authority_info,
+        new_stake_or_withdraw_authority_info,
+        ..rest
+    ]);
 
     // other accounts
-    let option_lockup_authority_info = if !rest.is_empty() {
-        Some(&rest[0])
-    } else {
-        None
-    };
+    let option_lockup_authority_info = rest.first();
 
     let clock = clock_from_account_info(clock_info)?;
 
@@ -34,10 +37,10 @@ pub fn process_authorize_checked(
         .filter(|a| a.is_signer())
         .map(|a| a.key());
 
-    // `get_stake_state()` is called unconditionally, which checks owner
+    // `do_authorize()` guards the stake account, which checks owner
     do_authorize(
         stake_account_info,
-        &signers,
+        signers,
         new_stake_or_withdraw_authority_info.key(),
         authority_type,
         custodian,
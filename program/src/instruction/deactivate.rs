@@ -0,0 +1,51 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+
+use crate::{
+    consts::MAX_SIGNERS,
+    cu_trace,
+    features::FeatureSet,
+    parse_accounts,
+    state::{
+        clear_flags_if_fully_activated, clock_from_account_info, collect_signers,
+        StakeAuthorize, StakeHistorySysvar, StakeStateV2,
+    },
+    validation::ValidatedAccounts,
+};
+
+pub fn process_deactivate(validated: ValidatedAccounts, features: &FeatureSet) -> ProgramResult {
+    let accounts = validated.accounts();
+
+    // every signer counts, including the stake authority trailing the asserted accounts
+    let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers_arr)?;
+    let signers = &signers_arr[..signers_len];
+
+    // native asserts: 2 accounts (1 sysvar), the stake authority follows
+    parse_accounts!(accounts => [stake_account_info, clock_info]);
+
+    let clock = clock_from_account_info(clock_info)?;
+    cu_trace::phase("deactivate/parse");
+    let stake_account = validated.stake_account(stake_account_info)?;
+
+    let mut stake_state = stake_account.state_mut()?;
+    cu_trace::phase("deactivate/validate");
+    match *stake_state {
+        StakeStateV2::Stake(ref meta, ref mut stake, ref mut stake_flags) => {
+            // deactivating is the staker's call alone: neither the withdrawer nor the
+            // custodian can stand in for it
+            meta.authorized.check(signers, StakeAuthorize::Staker)?;
+            cu_trace::phase("deactivate/classify");
+            clear_flags_if_fully_activated(
+                stake,
+                stake_flags,
+                clock.epoch,
+                &StakeHistorySysvar(clock.epoch),
+                features,
+            );
+            stake.deactivate(clock.epoch)?;
+            cu_trace::phase("deactivate/mutate");
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
@@ -1,87 +1,117 @@
-use pinocchio::{
-    account_info::AccountInfo,
-    program_error::ProgramError,
-    pubkey::Pubkey,
-    ProgramResult,
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+use crate::{
+    consts::MAX_SIGNERS, features::FeatureSet, parse_accounts, validation::ValidatedAccounts,
 };
 use crate::state::{
-    bytes_to_u64,
+    clear_flags_if_fully_activated,
     clock_from_account_info,
+    expect_stake_config,
+    expect_stake_history,
     collect_signers,
-    get_stake_state,
     get_vote_state,
-    new_stake,
-    next_account_info,
+    is_vote_account,
+    new_stake_with_flags,
     redelegate_stake,
-    set_stake_state,
     to_program_error,
     validate_delegated_amount,
     StakeFlags,
     StakeHistorySysvar,
+    StakeStateField,
     StakeStateV2,
     ValidatedDelegatedInfo,
+    VoteStateReader,
 };
 
-pub fn process_delegate(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
-    let mut signers = [Pubkey::default(); 32];
-    let _signers_len = collect_signers(accounts, &mut signers)?;
+pub fn process_delegate(validated: ValidatedAccounts, features: &FeatureSet) -> ProgramResult {
+    let accounts = validated.accounts();
 
-    // native accounts -- asserted
-    let accounts_info_iter = &mut accounts.iter();
-    let stake_account_info = next_account_info(accounts_info_iter)?;
-    let vote_account_info = next_account_info(accounts_info_iter)?;
-    let clock_info = next_account_info(accounts_info_iter)?;
-    let _stake_history_info = next_account_info(accounts_info_iter)?;
-    let _stake_config_info = next_account_info(accounts_info_iter)?;
+    // every signer counts, including the stake authority trailing the asserted accounts
+    let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers_arr)?;
+    let signers = &signers_arr[..signers_len];
 
-    // for future refactors, after the bpf switchover we may assert them as well.
-    // other account info
-    // let _stake_authority_info = next_account_info(accounts_info_iter)?;
+    // native accounts -- asserted
+    // for future refactors, after the bpf switchover we may assert the trailing
+    // stake authority account as well
+    parse_accounts!(accounts => [
+        stake_account_info,
+        vote_account_info,
+        clock_info,
+        stake_history_info,
+        stake_config_info,
+    ]);
 
     let clock = clock_from_account_info(clock_info)?;
-    let stake_history = &StakeHistorySysvar(bytes_to_u64(clock.epoch.to_le_bytes()));
-    let vote_state = get_vote_state(vote_account_info)?;
+    // the history itself is read through the sysvar syscall
+    expect_stake_history(stake_history_info)?;
+    expect_stake_config(stake_config_info)?;
+    let stake_history = &StakeHistorySysvar(clock.epoch);
+
+    // owner and size of the stake account, and that the vote account is not the same
+    // account, were checked by the validation layer
+    let stake_account = validated.stake_account(stake_account_info)?;
+    if !is_vote_account(vote_account_info) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // credits observed as of the activation epoch
+    let vote_credits =
+        VoteStateReader::new(&get_vote_state(vote_account_info)?)?.credits_at_epoch(clock.epoch)?;
 
-    match *get_stake_state(stake_account_info)? {
+    // copied out, so the account can be written below
+    let stake_state = *stake_account.state()?;
+    match stake_state {
         crate::state::StakeStateV2::Initialized(meta) => {
             meta.authorized
-                .check(&signers, crate::state::StakeAuthorize::Staker)
+                .check(signers, crate::state::StakeAuthorize::Staker)
                 .map_err(to_program_error)?;
             let ValidatedDelegatedInfo { stake_amount } = validate_delegated_amount(
-                stake_account_info,
-                &meta
+                stake_account.lamports(),
+                &meta,
+                features
             )?;
-            let stake = new_stake(
+            let (stake, stake_flags) = new_stake_with_flags(
                 stake_amount,
                 vote_account_info.key(),
-                &vote_state,
-                clock.epoch.to_le_bytes()
+                vote_credits,
+                clock.epoch,
+                StakeFlags::empty()
             );
-            set_stake_state(
-                stake_account_info,
-                &StakeStateV2::Stake(meta, stake, StakeFlags::empty())
-            )?;
+            stake_account.set_state(&StakeStateV2::Stake(meta, stake, stake_flags))?;
         }
-        crate::state::StakeStateV2::Stake(meta, mut stake, flags) => {
+        crate::state::StakeStateV2::Stake(meta, mut stake, mut stake_flags) => {
             meta.authorized
-                .check(&signers, crate::state::StakeAuthorize::Staker)
+                .check(signers, crate::state::StakeAuthorize::Staker)
                 .map_err(to_program_error)?;
             let ValidatedDelegatedInfo { stake_amount } = validate_delegated_amount(
-                stake_account_info,
-                &meta
+                stake_account.lamports(),
+                &meta,
+                features
             )?;
 
+            // against the delegation being replaced, whose activation the flag guarded
+            let flags_cleared = clear_flags_if_fully_activated(
+                &stake,
+                &mut stake_flags,
+                clock.epoch,
+                stake_history,
+                features
+            );
             redelegate_stake(
                 &mut stake,
                 stake_amount,
                 vote_account_info.key(),
-                &vote_state,
-                clock.epoch.to_le_bytes(),
-                stake_history
+                vote_credits,
+                clock.epoch,
+                stake_history,
+                features
             )?;
-            set_stake_state(stake_account_info, &StakeStateV2::Stake(meta, stake, flags))?;
+            // meta is unchanged by a redelegation, and so are the flags unless cleared
+            stake_account.update_field(StakeStateField::Stake(&stake))?;
+            if flags_cleared {
+                stake_account.update_field(StakeStateField::StakeFlags(stake_flags))?;
+            }
         }
-        _ => {
+        StakeStateV2::Uninitialized | StakeStateV2::RewardsPool => {
             return Err(ProgramError::InvalidAccountData);
         }
     }
@@ -0,0 +1,9 @@
+use pinocchio::ProgramResult;
+
+use crate::{features::FeatureSet, helpers::set_return_u64, state::get_minimum_delegation};
+
+/// Publishes the current minimum delegation as little-endian `u64` return data
+pub fn process_get_minimum_delegation(features: &FeatureSet) -> ProgramResult {
+    set_return_u64(get_minimum_delegation(features));
+    Ok(())
+}
@@ -0,0 +1,329 @@
+//! Instruction data as the `solana-stake-interface` builders produce it, decoded by
+//! this crate's own parsers. Upstream leads with a bincode `u32` variant index where
+//! the entrypoint reads one discriminant byte: every index fits that byte with the
+//! other three zero, and once they are dropped the arguments must decode to exactly
+//! what the builder was given.
+
+use pinocchio::program_error::ProgramError;
+use solana_pubkey::Pubkey as SdkPubkey;
+use solana_stake_interface::{
+    instruction::{self as sdk, LockupArgs as SdkLockupArgs},
+    state::{
+        Authorized as SdkAuthorized, Lockup as SdkLockup, StakeAuthorize as SdkStakeAuthorize,
+    },
+};
+
+use super::*;
+use crate::state::{self, Authorized, StakeAuthorize};
+
+/// Upstream instruction data in this program's encoding: the variant index must fit
+/// the discriminant byte, the three bytes above it are dropped
+fn program_data(sdk_data: &[u8]) -> Vec<u8> {
+    let (index, args) = sdk_data.split_at(4);
+    assert_eq!(index[1..], [0; 3], "variant index {index:?} past one byte");
+    [&index[..1], args].concat()
+}
+
+/// The variant and its arguments as the entrypoint splits them, once the arguments
+/// have passed the length check
+fn split_variant(data: &[u8]) -> (StakeInstruction, &[u8]) {
+    let (discriminant, args) = data.split_first().unwrap();
+    let instruction = StakeInstruction::try_from(discriminant).unwrap();
+    instruction.check_args_len(args).unwrap();
+    (instruction, args)
+}
+
+/// The variant of upstream instruction data that carries no arguments
+fn no_args_variant(sdk_data: &[u8]) -> StakeInstruction {
+    let data = program_data(sdk_data);
+    let (instruction, args) = split_variant(&data);
+    assert!(args.is_empty());
+    instruction
+}
+
+fn key(pubkey: &SdkPubkey) -> pinocchio::pubkey::Pubkey {
+    pubkey.to_bytes()
+}
+
+fn expected_stake_authorize(stake_authorize: SdkStakeAuthorize) -> StakeAuthorize {
+    match stake_authorize {
+        SdkStakeAuthorize::Staker => StakeAuthorize::Staker,
+        SdkStakeAuthorize::Withdrawer => StakeAuthorize::Withdrawer,
+    }
+}
+
+/// Upstream's bytes as they are: the rest of the variant index reads as arguments,
+/// which fail the length check or the variant's parser
+#[test]
+fn test_variant_index_rejected() {
+    let withdraw = sdk::withdraw(
+        &SdkPubkey::new_unique(),
+        &SdkPubkey::new_unique(),
+        &SdkPubkey::new_unique(),
+        12,
+        None,
+    );
+    let (discriminant, args) = withdraw.data.split_first().unwrap();
+    let instruction = StakeInstruction::try_from(discriminant).unwrap();
+    assert!(matches!(instruction, StakeInstruction::Withdraw));
+    assert_eq!(instruction.check_args_len(args), Err(ProgramError::InvalidInstructionData));
+
+    let set_lockup = sdk::set_lockup(
+        &SdkPubkey::new_unique(),
+        &SdkLockupArgs::default(),
+        &SdkPubkey::new_unique(),
+    );
+    let (discriminant, args) = set_lockup.data.split_first().unwrap();
+    let instruction = StakeInstruction::try_from(discriminant).unwrap();
+    assert!(matches!(instruction, StakeInstruction::SetLockup));
+    instruction.check_args_len(args).unwrap();
+    assert_eq!(LockupArgs::from_data(args), Err(ProgramError::InvalidInstructionData));
+}
+
+#[test]
+fn test_initialize() {
+    let staker = SdkPubkey::new_unique();
+    let withdrawer = SdkPubkey::new_unique();
+    let custodian = SdkPubkey::new_unique();
+    let lockup = SdkLockup {
+        unix_timestamp: -3,
+        epoch: 7,
+        custodian,
+    };
+    let ix = sdk::initialize(
+        &SdkPubkey::new_unique(),
+        &SdkAuthorized { staker, withdrawer },
+        &lockup,
+    );
+
+    let data = program_data(&ix.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::Initialize));
+    let (authorized, decoded_lockup) = initialize_args_from_data(args).unwrap();
+
+    assert_eq!(
+        authorized,
+        Authorized {
+            staker: key(&staker),
+            withdrawer: key(&withdrawer),
+        }
+    );
+    assert_eq!(decoded_lockup.unix_timestamp(), -3);
+    assert_eq!(decoded_lockup.epoch(), 7);
+    assert_eq!(decoded_lockup.custodian, key(&custodian));
+}
+
+#[test]
+fn test_authorize() {
+    for sdk_authorize in [SdkStakeAuthorize::Staker, SdkStakeAuthorize::Withdrawer] {
+        let stake = SdkPubkey::new_unique();
+        let authority = SdkPubkey::new_unique();
+        let new_authority = SdkPubkey::new_unique();
+        let custodian = SdkPubkey::new_unique();
+
+        let ix =
+            sdk::authorize(&stake, &authority, &new_authority, sdk_authorize, Some(&custodian));
+        let data = program_data(&ix.data);
+        let (instruction, args) = split_variant(&data);
+        assert!(matches!(instruction, StakeInstruction::Authorize));
+        assert_eq!(
+            authorize_args_from_data(args).unwrap(),
+            (key(&new_authority), expected_stake_authorize(sdk_authorize))
+        );
+
+        // the new authority is an account, only the role is data
+        let ix = sdk::authorize_checked(
+            &stake,
+            &authority,
+            &new_authority,
+            sdk_authorize,
+            Some(&custodian),
+        );
+        let data = program_data(&ix.data);
+        let (instruction, args) = split_variant(&data);
+        assert!(matches!(instruction, StakeInstruction::AuthorizeChecked));
+        assert_eq!(
+            stake_authorize_from_data(args).unwrap(),
+            expected_stake_authorize(sdk_authorize)
+        );
+    }
+}
+
+#[test]
+fn test_authorize_with_seed() {
+    let new_authority = SdkPubkey::new_unique();
+    let owner = SdkPubkey::new_unique();
+    let ix = sdk::authorize_with_seed(
+        &SdkPubkey::new_unique(),
+        &SdkPubkey::new_unique(),
+        "golden seed".to_string(),
+        &owner,
+        &new_authority,
+        SdkStakeAuthorize::Withdrawer,
+        None,
+    );
+
+    let data = program_data(&ix.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::AuthorizeWithSeed));
+    assert_eq!(
+        AuthorizeWithSeedArgs::from_data(args).unwrap(),
+        AuthorizeWithSeedArgs {
+            new_authorized_pubkey: key(&new_authority),
+            stake_authorize: StakeAuthorize::Withdrawer,
+            authority_seed: "golden seed",
+            authority_owner: key(&owner),
+        }
+    );
+}
+
+#[test]
+fn test_authorize_checked_with_seed() {
+    let owner = SdkPubkey::new_unique();
+    let ix = sdk::authorize_checked_with_seed(
+        &SdkPubkey::new_unique(),
+        &SdkPubkey::new_unique(),
+        "golden seed".to_string(),
+        &owner,
+        &SdkPubkey::new_unique(),
+        SdkStakeAuthorize::Staker,
+        None,
+    );
+
+    let data = program_data(&ix.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::AuthorizeCheckedWithSeed));
+    assert_eq!(
+        state::AuthorizeCheckedWithSeedArgs::deserialize(args).unwrap(),
+        state::AuthorizeCheckedWithSeedArgs {
+            stake_authorize: StakeAuthorize::Staker,
+            authority_seed_len: 11,
+            authority_seed: "golden seed",
+            authority_owner: key(&owner),
+        }
+    );
+}
+
+#[test]
+fn test_lamport_arguments() {
+    let source = SdkPubkey::new_unique();
+    let destination = SdkPubkey::new_unique();
+    let authority = SdkPubkey::new_unique();
+
+    // Split creates the destination first, the stake instruction comes last
+    let split = sdk::split(&source, &authority, 11, &destination);
+    let data = program_data(&split.last().unwrap().data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::Split));
+    assert_eq!(lamports_from_data(args), Ok(11));
+
+    let withdraw = sdk::withdraw(&source, &authority, &destination, 12, None);
+    let data = program_data(&withdraw.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::Withdraw));
+    assert_eq!(lamports_from_data(args), Ok(12));
+
+    let move_stake = sdk::move_stake(&source, &destination, &authority, 13);
+    let data = program_data(&move_stake.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::MoveStake));
+    assert_eq!(lamports_from_data(args), Ok(13));
+
+    let move_lamports = sdk::move_lamports(&source, &destination, &authority, u64::MAX);
+    let data = program_data(&move_lamports.data);
+    let (instruction, args) = split_variant(&data);
+    assert!(matches!(instruction, StakeInstruction::MoveLamports));
+    assert_eq!(lamports_from_data(args), Ok(u64::MAX));
+}
+
+#[test]
+fn test_set_lockup() {
+    let custodian = SdkPubkey::new_unique();
+    let new_custodian = SdkPubkey::new_unique();
+    let sdk_args = [
+        SdkLockupArgs::default(),
+        SdkLockupArgs {
+            unix_timestamp: Some(i64::MIN),
+            epoch: None,
+            custodian: Some(new_custodian),
+        },
+        SdkLockupArgs {
+            unix_timestamp: Some(1),
+            epoch: Some(u64::MAX),
+            custodian: None,
+        },
+    ];
+    for sdk_args in sdk_args {
+        let expected = LockupArgs {
+            unix_timestamp: sdk_args.unix_timestamp.map(i64::to_le_bytes),
+            epoch: sdk_args.epoch.map(u64::to_le_bytes),
+            custodian: sdk_args.custodian.as_ref().map(key),
+        };
+
+        let ix = sdk::set_lockup(&SdkPubkey::new_unique(), &sdk_args, &custodian);
+        let data = program_data(&ix.data);
+        let (instruction, args) = split_variant(&data);
+        assert!(matches!(instruction, StakeInstruction::SetLockup));
+        assert_eq!(LockupArgs::from_data(args).unwrap(), expected);
+        // and back: the encoder reproduces the builder's bytes
+        let mut out = [0; 51];
+        let len = expected.write_data(&mut out).unwrap();
+        assert_eq!(&out[..len], args);
+
+        // the checked variant moves the new custodian into the accounts
+        let ix = sdk::set_lockup_checked(&SdkPubkey::new_unique(), &sdk_args, &custodian);
+        let data = program_data(&ix.data);
+        let (instruction, args) = split_variant(&data);
+        assert!(matches!(instruction, StakeInstruction::SetLockupChecked));
+        assert_eq!(
+            LockupArgs::from_checked_data(args).unwrap(),
+            LockupArgs {
+                custodian: None,
+                ..expected
+            }
+        );
+    }
+}
+
+#[test]
+fn test_no_arguments() {
+    let stake = SdkPubkey::new_unique();
+    let other = SdkPubkey::new_unique();
+    let authority = SdkPubkey::new_unique();
+
+    let instruction = no_args_variant(&sdk::delegate_stake(&stake, &authority, &other).data);
+    assert!(matches!(instruction, StakeInstruction::DelegateStake));
+
+    let instruction = no_args_variant(&sdk::deactivate_stake(&stake, &authority).data);
+    assert!(matches!(instruction, StakeInstruction::Deactivate));
+
+    let merge = sdk::merge(&stake, &other, &authority);
+    let instruction = no_args_variant(&merge.last().unwrap().data);
+    assert!(matches!(instruction, StakeInstruction::Merge));
+
+    let initialize_checked = sdk::initialize_checked(
+        &stake,
+        &SdkAuthorized {
+            staker: authority,
+            withdrawer: other,
+        },
+    );
+    let instruction = no_args_variant(&initialize_checked.data);
+    assert!(matches!(instruction, StakeInstruction::InitializeChecked));
+
+    let instruction = no_args_variant(&sdk::get_minimum_delegation().data);
+    assert!(matches!(instruction, StakeInstruction::GetMinimumDelegation));
+
+    let deactivate_delinquent =
+        sdk::deactivate_delinquent_stake(&stake, &other, &SdkPubkey::new_unique());
+    let instruction = no_args_variant(&deactivate_delinquent.data);
+    assert!(matches!(instruction, StakeInstruction::DeactivateDelinquent));
+
+    // no builder is left for it, the enum still encodes it
+    #[allow(deprecated)]
+    let redelegate = bincode::serialize(&sdk::StakeInstruction::Redelegate).unwrap();
+    let instruction = no_args_variant(&redelegate);
+    #[allow(deprecated)]
+    let is_redelegate = matches!(instruction, StakeInstruction::Redelegate);
+    assert!(is_redelegate);
+}
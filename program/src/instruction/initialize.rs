@@ -0,0 +1,97 @@
+use pinocchio::{
+    program_error::ProgramError,
+    sysvars::{rent::Rent, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    cu_trace,
+    helpers::Cursor,
+    parse_accounts,
+    state::{
+        expect_rent, stake_rent_exempt_reserve, Authorized, Lockup, Meta, StakeStateV2,
+    },
+    validation::ValidatedAccounts,
+};
+
+/// Decodes the bincode encoding of `(Authorized, Lockup)`: the staker and the
+/// withdrawer, then the lockup timestamp, epoch and custodian
+pub fn initialize_args_from_data(data: &[u8]) -> Result<(Authorized, Lockup), ProgramError> {
+    let mut cursor = Cursor::new(data);
+    let authorized = Authorized {
+        staker: cursor.read_pubkey()?,
+        withdrawer: cursor.read_pubkey()?,
+    };
+    let lockup = Lockup {
+        unix_timestamp: cursor.read_array()?,
+        epoch: cursor.read_array()?,
+        custodian: cursor.read_pubkey()?,
+    };
+    cursor.finish()?;
+
+    Ok((authorized, lockup))
+}
+
+pub fn process_initialize(validated: ValidatedAccounts, data: &[u8]) -> ProgramResult {
+    let accounts = validated.accounts();
+    let (authorized, lockup) = initialize_args_from_data(data)?;
+
+    // native asserts: 2 accounts (1 sysvar)
+    parse_accounts!(accounts => [stake_account_info, rent_info]);
+
+    // the rent itself is read through the sysvar syscall
+    expect_rent(rent_info)?;
+    let rent = Rent::get()?;
+    cu_trace::phase("initialize/parse");
+
+    let mut stake_account = validated.stake_account(stake_account_info)?.borrow_mut()?;
+    cu_trace::phase("initialize/validate");
+
+    if *stake_account.state() != StakeStateV2::Uninitialized {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // the reserve the account must hold is the one recorded for it
+    let rent_exempt_reserve = stake_rent_exempt_reserve(&rent, stake_account_info.data_len());
+    // Intended divergence: an underfunded account fails with `AccountNotRentExempt`,
+    // where native answers `InsufficientFunds`
+    if stake_account.lamports() < rent_exempt_reserve {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    cu_trace::phase("initialize/classify");
+
+    stake_account.set_state(&StakeStateV2::Initialized(Meta {
+        rent_exempt_reserve: rent_exempt_reserve.to_le_bytes(),
+        authorized,
+        lockup,
+    }))?;
+    cu_trace::phase("initialize/mutate");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialize_args_from_data() {
+        let mut data = [0u8; 112];
+        data[..32].fill(1);
+        data[32..64].fill(2);
+        data[64..72].copy_from_slice(&(-3i64).to_le_bytes());
+        data[72..80].copy_from_slice(&7u64.to_le_bytes());
+        data[80..].fill(3);
+
+        let (authorized, lockup) = initialize_args_from_data(&data).unwrap();
+        assert_eq!(authorized.staker, [1; 32]);
+        assert_eq!(authorized.withdrawer, [2; 32]);
+        assert_eq!(lockup.unix_timestamp(), -3);
+        assert_eq!(lockup.epoch(), 7);
+        assert_eq!(lockup.custodian, [3; 32]);
+
+        assert_eq!(
+            initialize_args_from_data(&data[..111]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
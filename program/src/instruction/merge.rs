@@ -1,79 +1,112 @@
 use crate::state::{
-    clock_from_account_info, get_stake_state, relocate_lamports, set_stake_state, MergeKind,
-    StakeAuthorize, StakeHistorySysvar, StakeStateV2,
-};
-use pinocchio::{
-    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
+    clear_flags_if_fully_activated, clock_from_account_info, collect_signers,
+    expect_stake_history, is_same_account, MergeKind, StakeAuthorize, StakeHistorySysvar,
+    StakeStateTag, StakeStateV2,
 };
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+#[cfg(feature = "logging")]
 use pinocchio_log::log;
 
 // const MAX_SIGNERS: usize = 32;
-use crate::consts::MAX_SIGNERS;
+use crate::{
+    consts::MAX_SIGNERS, cu_trace, features::FeatureSet, parse_accounts,
+    validation::ValidatedAccounts,
+};
 
-pub fn process_merge(accounts: &[AccountInfo]) -> ProgramResult {
-    let signers_arr = [Pubkey::default(); MAX_SIGNERS];
+/// Rejects the variants `MergeKind::get_if_mergeable` never accepts, from the
+/// discriminant alone and with the same error
+#[inline(always)]
+fn check_mergeable_tag(tag: StakeStateTag) -> ProgramResult {
+    match tag {
+        StakeStateTag::Uninitialized | StakeStateTag::RewardsPool => {
+            Err(ProgramError::InvalidAccountData)
+        }
+        StakeStateTag::Initialized | StakeStateTag::Stake => Ok(()),
+    }
+}
 
-    // native asserts: 4 accounts (2 sysvars)
-    // let destination_stake_account_info = next_account_info(account_info_iter)?;
-    // let source_stake_account_info = next_account_info(account_info_iter)?;
-    // let clock_info = next_account_info(account_info_iter)?;
-    // let _stake_history_info = next_account_info(account_info_iter)?;
+pub fn process_merge(validated: ValidatedAccounts, features: &FeatureSet) -> ProgramResult {
+    let accounts = validated.accounts();
 
-    let [destination_stake_account_info, source_stake_account_info, clock_info, _stake_history_info] =
-        accounts
-    else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    // every signer counts, including the stake authority trailing the asserted accounts
+    let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers_arr)?;
+    let signers = &signers_arr[..signers_len];
 
-    // other accounts
-    // let _stake_authority_info = next_account_info(account_info_iter)?;
+    // native asserts: 4 accounts (2 sysvars), the stake authority follows
+    parse_accounts!(accounts => [
+        destination_stake_account_info,
+        source_stake_account_info,
+        clock_info,
+        stake_history_info,
+    ]);
 
     let clock = clock_from_account_info(clock_info)?;
+    // the history itself is read through the sysvar syscall
+    expect_stake_history(stake_history_info)?;
     let stake_history = &StakeHistorySysvar(clock.epoch);
+    cu_trace::phase("merge/parse");
 
     // check source stake account and destination stake account are not having same key
-    if source_stake_account_info.key() == destination_stake_account_info.key() {
+    if is_same_account(source_stake_account_info, destination_stake_account_info) {
         return Err(ProgramError::InvalidArgument);
     }
 
+    // owner and size were checked by the validation layer, the tag is checked once
+    // the data is borrowed; each account's data is borrowed once, here, and held
+    // until the merge is done
+    let mut destination_stake_account =
+        validated.stake_account(destination_stake_account_info)?.borrow_mut()?;
+    let mut source_stake_account =
+        validated.stake_account(source_stake_account_info)?.borrow_mut()?;
+    cu_trace::phase("merge/validate");
+
+    #[cfg(feature = "logging")]
     log!("Checking if destination stake is mergeable");
+    check_mergeable_tag(destination_stake_account.tag())?;
     let destination_merge_kind = MergeKind::get_if_mergeable(
         // MergeKind is a enum
-        &*get_stake_state(destination_stake_account_info)?,
-        destination_stake_account_info.lamports(),
-        &clock,
+        destination_stake_account.state(),
+        destination_stake_account.lamports(),
+        clock.epoch,
         stake_history,
+        features,
     )?;
 
     // Authorized staker is allowed to split/merge accounts
     destination_merge_kind
         .meta() // implementation of state.rs
         .authorized
-        .check(&signers_arr, StakeAuthorize::Staker) // implementation of state.rs
+        .check(signers, StakeAuthorize::Staker) // implementation of state.rs
         .map_err(|_| ProgramError::MissingRequiredSignature)?;
 
+    #[cfg(feature = "logging")]
     log!("Checking if source stake is mergeable");
+    check_mergeable_tag(source_stake_account.tag())?;
     let source_merge_kind = MergeKind::get_if_mergeable(
-        &*get_stake_state(source_stake_account_info)?,
-        source_stake_account_info.lamports(),
-        &clock,
+        source_stake_account.state(),
+        source_stake_account.lamports(),
+        clock.epoch,
         stake_history,
+        features,
     )?;
+    cu_trace::phase("merge/classify");
 
+    #[cfg(feature = "logging")]
     log!("Merging stake accounts");
-    if let Some(merged_state) = destination_merge_kind.merge(source_merge_kind, &clock)? {
-        set_stake_state(destination_stake_account_info, &merged_state)?;
+    // `None` leaves the destination as it is, and so does a merged state equal to it
+    if let Some(mut merged_state) = destination_merge_kind.merge(&source_merge_kind, &clock)? {
+        if let StakeStateV2::Stake(_, ref stake, ref mut stake_flags) = merged_state {
+            clear_flags_if_fully_activated(stake, stake_flags, clock.epoch, stake_history, features);
+        }
+        if *destination_stake_account.state() != merged_state {
+            destination_stake_account.set_state(&merged_state)?;
+        }
     }
 
-    // Source is about to be drained, deinitialize it's state
-    set_stake_state(source_stake_account_info, &StakeStateV2::Uninitialized)?;
-
-    // Drain the source stake account and transfer the lamports to the destination stake account
-    relocate_lamports(
-        source_stake_account_info,
-        destination_stake_account_info,
-        source_stake_account_info.lamports(),
-    )?;
+    // Deinitialize and drain the source stake account into the destination stake account
+    source_stake_account.drain_and_deinitialize(destination_stake_account_info)?;
+    cu_trace::phase("merge/mutate");
 
     Ok(())
 }
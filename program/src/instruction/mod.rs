@@ -1,24 +1,38 @@
-use pinocchio::program_error::ProgramError;
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{helpers::Cursor, state::StakeAuthorize};
 
 pub mod authorize_with_seed;
 pub mod authorized_checked;
+pub mod deactivate;
+pub mod get_minimum_delegation;
+pub mod initialize;
 pub mod move_lamports;
 pub mod redelegate;
 pub mod set_lockup;
 pub mod split;
 pub mod delegate_stake;
 pub mod merge;
+pub mod withdraw;
 
 pub use authorize_with_seed::*;
 pub use authorized_checked::*;
+pub use deactivate::*;
+pub use get_minimum_delegation::*;
+pub use initialize::*;
 pub use move_lamports::*;
 pub use redelegate::*;
 pub use set_lockup::*;
 pub use split::*;
 pub use delegate_stake::*;
 pub use merge::*;
+pub use withdraw::*;
+
+#[cfg(test)]
+mod golden_vectors;
 
 #[repr(u8)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary, Debug))]
 pub enum StakeInstruction {
     Initialize,
     Authorize,
@@ -41,31 +55,160 @@ pub enum StakeInstruction {
     MoveLamports,
 }
 
+impl StakeInstruction {
+    /// The variant `discriminant` names, usable in constant expressions
+    pub const fn from_discriminant(discriminant: u8) -> Option<Self> {
+        match discriminant {
+            0 => Some(StakeInstruction::Initialize),
+            1 => Some(StakeInstruction::Authorize),
+            2 => Some(StakeInstruction::DelegateStake),
+            3 => Some(StakeInstruction::Split),
+            4 => Some(StakeInstruction::Withdraw),
+            5 => Some(StakeInstruction::Deactivate),
+            6 => Some(StakeInstruction::SetLockup),
+            7 => Some(StakeInstruction::Merge),
+            8 => Some(StakeInstruction::AuthorizeWithSeed),
+            9 => Some(StakeInstruction::InitializeChecked),
+            10 => Some(StakeInstruction::AuthorizeChecked),
+            11 => Some(StakeInstruction::AuthorizeCheckedWithSeed),
+            12 => Some(StakeInstruction::SetLockupChecked),
+            13 => Some(StakeInstruction::GetMinimumDelegation),
+            14 => Some(StakeInstruction::DeactivateDelinquent),
+            #[allow(deprecated)]
+            15 => Some(StakeInstruction::Redelegate),
+            16 => Some(StakeInstruction::MoveStake),
+            17 => Some(StakeInstruction::MoveLamports),
+            _ => None,
+        }
+    }
+}
+
 impl TryFrom<&u8> for StakeInstruction {
     type Error = ProgramError;
 
     fn try_from(value: &u8) -> Result<Self, Self::Error> {
-        match *value {
-            0 => Ok(StakeInstruction::Initialize),
-            1 => Ok(StakeInstruction::Authorize),
-            2 => Ok(StakeInstruction::DelegateStake),
-            3 => Ok(StakeInstruction::Split),
-            4 => Ok(StakeInstruction::Withdraw),
-            5 => Ok(StakeInstruction::Deactivate),
-            6 => Ok(StakeInstruction::SetLockup),
-            7 => Ok(StakeInstruction::Merge),
-            8 => Ok(StakeInstruction::AuthorizeWithSeed),
-            9 => Ok(StakeInstruction::InitializeChecked),
-            10 => Ok(StakeInstruction::AuthorizeChecked),
-            11 => Ok(StakeInstruction::AuthorizeCheckedWithSeed),
-            12 => Ok(StakeInstruction::SetLockupChecked),
-            13 => Ok(StakeInstruction::GetMinimumDelegation),
-            14 => Ok(StakeInstruction::DeactivateDelinquent),
+        StakeInstruction::from_discriminant(*value).ok_or(ProgramError::InvalidInstructionData)
+    }
+}
+
+/// How many bytes of arguments follow the discriminant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgsLen {
+    Fixed(usize),
+    /// bounded by the variant's own parser, which rejects trailing bytes
+    Variable,
+}
+
+impl StakeInstruction {
+    /// The length of the variant's arguments, as bincode encodes them for the native
+    /// program: pubkeys are 32 bytes, `u64`s 8 and `StakeAuthorize` a 4-byte tag
+    pub fn args_len(&self) -> ArgsLen {
+        match self {
+            // Authorized, Lockup
+            StakeInstruction::Initialize => ArgsLen::Fixed(64 + 48),
+            // new authority, StakeAuthorize
+            StakeInstruction::Authorize => ArgsLen::Fixed(32 + 4),
+            // lamports
+            StakeInstruction::Split
+            | StakeInstruction::Withdraw
+            | StakeInstruction::MoveStake
+            | StakeInstruction::MoveLamports => ArgsLen::Fixed(8),
+            // StakeAuthorize
+            StakeInstruction::AuthorizeChecked => ArgsLen::Fixed(4),
+            StakeInstruction::DelegateStake
+            | StakeInstruction::Deactivate
+            | StakeInstruction::Merge
+            | StakeInstruction::InitializeChecked
+            | StakeInstruction::GetMinimumDelegation
+            | StakeInstruction::DeactivateDelinquent => ArgsLen::Fixed(0),
             #[allow(deprecated)]
-            15 => Ok(StakeInstruction::Redelegate),
-            16 => Ok(StakeInstruction::MoveStake),
-            17 => Ok(StakeInstruction::MoveLamports),
-            _ => Err(ProgramError::InvalidInstructionData),
+            StakeInstruction::Redelegate => ArgsLen::Fixed(0),
+            // optional fields and seeds
+            StakeInstruction::SetLockup
+            | StakeInstruction::SetLockupChecked
+            | StakeInstruction::AuthorizeWithSeed
+            | StakeInstruction::AuthorizeCheckedWithSeed => ArgsLen::Variable,
+        }
+    }
+
+    /// Rejects arguments of the wrong length, trailing bytes included
+    pub fn check_args_len(&self, data: &[u8]) -> Result<(), ProgramError> {
+        match self.args_len() {
+            ArgsLen::Fixed(len) if data.len() != len => Err(ProgramError::InvalidInstructionData),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The lamports argument of Split, Withdraw, MoveStake and MoveLamports
+pub fn lamports_from_data(data: &[u8]) -> Result<u64, ProgramError> {
+    let mut cursor = Cursor::new(data);
+    let lamports = cursor.read_u64()?;
+    cursor.finish()?;
+    Ok(lamports)
+}
+
+/// The arguments of Authorize: the new authority, then its role
+pub fn authorize_args_from_data(data: &[u8]) -> Result<(Pubkey, StakeAuthorize), ProgramError> {
+    let mut cursor = Cursor::new(data);
+    let new_authority = cursor.read_pubkey()?;
+    let stake_authorize = cursor.read_stake_authorize()?;
+    cursor.finish()?;
+    Ok((new_authority, stake_authorize))
+}
+
+/// The argument of AuthorizeChecked, whose new authority is an account
+pub fn stake_authorize_from_data(data: &[u8]) -> Result<StakeAuthorize, ProgramError> {
+    let mut cursor = Cursor::new(data);
+    let stake_authorize = cursor.read_stake_authorize()?;
+    cursor.finish()?;
+    Ok(stake_authorize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args_len() {
+        let fixed = [
+            (0u8, 112),
+            (1, 36),
+            (2, 0),
+            (3, 8),
+            (4, 8),
+            (5, 0),
+            (7, 0),
+            (9, 0),
+            (10, 4),
+            (13, 0),
+            (14, 0),
+            (15, 0),
+            (16, 8),
+            (17, 8),
+        ];
+        let data = [0u8; 113];
+        for (discriminant, len) in fixed {
+            let instruction = StakeInstruction::try_from(&discriminant).unwrap();
+            assert_eq!(instruction.args_len(), ArgsLen::Fixed(len));
+            assert_eq!(instruction.check_args_len(&data[..len]), Ok(()));
+            // one trailing byte
+            assert_eq!(
+                instruction.check_args_len(&data[..len + 1]),
+                Err(ProgramError::InvalidInstructionData)
+            );
+            if len > 0 {
+                assert_eq!(
+                    instruction.check_args_len(&data[..len - 1]),
+                    Err(ProgramError::InvalidInstructionData)
+                );
+            }
+        }
+
+        for discriminant in [6u8, 8, 11, 12] {
+            let instruction = StakeInstruction::try_from(&discriminant).unwrap();
+            assert_eq!(instruction.args_len(), ArgsLen::Variable);
+            assert_eq!(instruction.check_args_len(&data), Ok(()));
         }
     }
 }
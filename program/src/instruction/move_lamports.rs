@@ -1,35 +1,58 @@
-use pinocchio::{account_info::AccountInfo, program_error::ProgramError, ProgramResult};
+use pinocchio::{
+    program_error::ProgramError,
+    sysvars::{clock::Clock, rent::Rent, Sysvar},
+    ProgramResult,
+};
 
 use crate::{
-    helpers::MergeKind,
-    state::{move_stake_or_lamports_shared_checks, relocate_lamports},
+    features::FeatureSet,
+    parse_accounts,
+    state::{
+        bytes_to_u64, clear_account_flags_if_fully_activated,
+        move_stake_or_lamports_shared_checks, relocate_lamports, verify_rent_exempt, MergeKind,
+        MoveInstruction, StakeHistorySysvar,
+    },
+    validation::ValidatedAccounts,
 };
 
-pub fn process_move_lamports(accounts: &[AccountInfo], lamports: u64) -> ProgramResult {
-    if lamports <= 0 {
-        return Err(ProgramError::InvalidArgument);
-    }
-    let [source_stake_account_info, destination_stake_account_info, stake_authority_info, _remaining @ ..] =
-        accounts
-    else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+pub fn process_move_lamports(
+    validated: ValidatedAccounts,
+    lamports: u64,
+    features: &FeatureSet,
+) -> ProgramResult {
+    let accounts = validated.accounts();
 
-    let (source_merge_kind, _) = move_stake_or_lamports_shared_checks(
+    parse_accounts!(accounts => [
         source_stake_account_info,
         destination_stake_account_info,
         stake_authority_info,
+    ]);
+
+    let clock = Clock::get()?;
+    // owner, size and writability of both were checked by the validation layer
+    let source_stake_account = validated.stake_account(source_stake_account_info)?;
+    let destination_stake_account = validated.stake_account(destination_stake_account_info)?;
+
+    // the source is fully active or inactive past this point
+    let (source_merge_kind, _) = move_stake_or_lamports_shared_checks(
+        &source_stake_account,
+        lamports,
+        &destination_stake_account,
+        stake_authority_info,
+        MoveInstruction::MoveLamports,
+        &clock,
+        features,
     )?;
 
     let source_free_lamports = match source_merge_kind {
-        MergeKind::FullyActive(source_meta, source_stake) => source_stake_account_info
+        MergeKind::FullyActive(source_meta, source_stake) => source_stake_account
             .lamports()
-            .saturating_sub(u64::from_le_bytes(source_stake.delegation.stake))
-            .saturating_sub(u64::from_le_bytes(source_meta.rent_exempt_reserve)),
+            .saturating_sub(bytes_to_u64(source_stake.delegation.stake))
+            .saturating_sub(source_meta.rent_exempt_reserve()),
         MergeKind::Inactive(source_meta, source_lamports, _) => {
-            source_lamports.saturating_sub(u64::from_le_bytes(source_meta.rent_exempt_reserve))
+            source_lamports.saturating_sub(source_meta.rent_exempt_reserve())
         }
-        _ => return Err(ProgramError::InvalidAccountData),
+        MergeKind::ActivationEpoch(..) => return Err(ProgramError::InvalidAccountData),
     };
 
     if lamports > source_free_lamports {
@@ -41,6 +64,18 @@ pub fn process_move_lamports(accounts: &[AccountInfo], lamports: u64) -> Program
         destination_stake_account_info,
         lamports,
     )?;
+    // neither account is activating, a flag either still carries has done its job
+    let stake_history = &StakeHistorySysvar(clock.epoch);
+    for stake_account in [&source_stake_account, &destination_stake_account] {
+        clear_account_flags_if_fully_activated(
+            stake_account,
+            clock.epoch,
+            stake_history,
+            features,
+        )?;
+    }
+    // the free lamports leave the recorded reserve behind, which may predate today's rent
+    verify_rent_exempt(source_stake_account_info, &Rent::get()?)?;
 
     Ok(())
 }
@@ -12,11 +12,14 @@ use pinocchio_token::{
 use crate::state::load_acc_mut_unchecked;
 
 use crate::state::{
-    utils::{load_ix_data, DataLen},
+    utils::DataLen,
+    zerocopy::load_ix_data,
     RedelegateState,
 };
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct StartRedelegationIxData {
     pub new_validator: Pubkey,
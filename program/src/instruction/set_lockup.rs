@@ -1,5 +1,4 @@
 use pinocchio::{
-    account_info::AccountInfo,
     program_error::ProgramError,
     pubkey::Pubkey,
     sysvars::{clock::Clock, Sysvar},
@@ -7,15 +6,21 @@ use pinocchio::{
 };
 
 use crate::{
-    error::to_program_error,
-    state::{
-        get_stake_state, try_get_stake_state_mut, Epoch, SetLockupSignerArgs, StakeStateV2,
-        UnixTimestamp,
-    },
+    consts::MAX_SIGNERS,
+    cu_trace,
+    helpers::{encode_option, encode_option_pubkey, Cursor},
+    parse_accounts,
+    state::{collect_signers, Epoch, StakeStateV2, UnixTimestamp},
+    validation::ValidatedAccounts,
 };
 
-#[cfg(not(test))]
 #[repr(C)]
+#[cfg_attr(
+    any(test, feature = "serde"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct LockupArgs {
     pub unix_timestamp: Option<UnixTimestamp>,
@@ -23,182 +28,84 @@ pub struct LockupArgs {
     pub custodian: Option<Pubkey>,
 }
 
-#[cfg(test)]
-#[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
-pub struct LockupArgs {
-    pub unix_timestamp: Option<UnixTimestamp>,
-    pub epoch: Option<Epoch>,
-    pub custodian: Option<Pubkey>,
-}
-
 impl LockupArgs {
+    /// Decodes the bincode encoding of `LockupArgs`: three `Option`s, each a 1-byte
+    /// tag optionally followed by its value. Trailing bytes are rejected.
     pub fn from_data(data: &[u8]) -> Result<Self, ProgramError> {
-        match data.len() {
-            // all none: 1 + 1 + 1
-            3 => {
-                if (data[0] == 1) || (data[1] == 1) || (data[2] == 1) {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                Ok(LockupArgs {
-                    unix_timestamp: None,
-                    epoch: None,
-                    custodian: None,
-                })
-            }
-            // (unix_timestamp - some, other - none) or (epoch - some, other none): 9 + 1 + 1
-            11 => {
-                if !(((data[0] == 1) && (data[9] == 0) && (data[10] == 0))
-                    || ((data[0] == 0) && (data[1] == 1) && (data[10] == 0)))
-                {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                if data[0] == 1 {
-                    Ok(LockupArgs {
-                        unix_timestamp: Some(unsafe {
-                            *(data[1..=8].as_ptr() as *const UnixTimestamp)
-                        }),
-                        epoch: None,
-                        custodian: None,
-                    })
-                } else {
-                    Ok(LockupArgs {
-                        unix_timestamp: None,
-                        epoch: Some(unsafe { *(data[2..=9].as_ptr() as *const Epoch) }),
-                        custodian: None,
-                    })
-                }
-            }
-            // (unix_timestamp and epoch - some, custodian - none): 9 + 9 + 1
-            19 => {
-                if !((data[0] == 1) && (data[9] == 1) && (data[18] == 0)) {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                Ok(LockupArgs {
-                    unix_timestamp: Some(unsafe {
-                        *(data[1..=8].as_ptr() as *const UnixTimestamp)
-                    }),
-                    epoch: Some(unsafe { *(data[10..=17].as_ptr() as *const Epoch) }),
-                    custodian: None,
-                })
-            }
-            // (custodian - some, other - none): 1 + 1 + 33
-            35 => {
-                if !((data[0] == 0) && (data[1] == 0) && (data[2] == 1)) {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                Ok(LockupArgs {
-                    unix_timestamp: None,
-                    epoch: None,
-                    custodian: Some(unsafe { *(data[3..=34].as_ptr() as *const Pubkey) }),
-                })
-            }
-            // (custodian - some, either unix_timestamp or epoch - none): 9 + 1 + 33
-            43 => {
-                if !(((data[0] == 0) && (data[1] == 1) && (data[10] == 1))
-                    || ((data[0] == 1) && (data[9] == 0) && (data[10] == 1)))
-                {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                if data[0] == 1 {
-                    Ok(LockupArgs {
-                        unix_timestamp: Some(unsafe {
-                            *(data[1..=8].as_ptr() as *const UnixTimestamp)
-                        }),
-                        epoch: None,
-                        custodian: Some(unsafe { *(data[11..=42].as_ptr() as *const Pubkey) }),
-                    })
-                } else {
-                    Ok(LockupArgs {
-                        unix_timestamp: None,
-                        epoch: Some(unsafe { *(data[2..=9].as_ptr() as *const Epoch) }),
-                        custodian: Some(unsafe { *(data[11..=42].as_ptr() as *const Pubkey) }),
-                    })
-                }
-            }
-            // all some: 9 + 9 + 33
-            51 => {
-                if !((data[0] == 1) && (data[9] == 1) && (data[18] == 1)) {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                Ok(unsafe { *(data.as_ptr() as *const Self) })
-            }
-            _ => return Err(ProgramError::InvalidInstructionData),
-        }
+        let mut cursor = Cursor::new(data);
+        let unix_timestamp = cursor.read_option()?;
+        let epoch = cursor.read_option()?;
+        let custodian = cursor.read_option_pubkey()?;
+        cursor.finish()?;
+
+        Ok(LockupArgs {
+            unix_timestamp,
+            epoch,
+            custodian,
+        })
+    }
+
+    /// Decodes the arguments of SetLockupChecked: the timestamp and epoch options
+    /// only, the new custodian signs as an account instead
+    pub fn from_checked_data(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut cursor = Cursor::new(data);
+        let unix_timestamp = cursor.read_option()?;
+        let epoch = cursor.read_option()?;
+        cursor.finish()?;
+
+        Ok(LockupArgs {
+            unix_timestamp,
+            epoch,
+            custodian: None,
+        })
+    }
+
+    /// Writes the bincode encoding of `self` into `out`, returning the number of
+    /// bytes written (at most 51).
+    pub fn write_data(&self, out: &mut [u8]) -> Result<usize, ProgramError> {
+        let mut offset = encode_option(&self.unix_timestamp, out)?;
+        offset += encode_option(&self.epoch, &mut out[offset..])?;
+        offset += encode_option_pubkey(&self.custodian, &mut out[offset..])?;
+        Ok(offset)
     }
 }
 
-pub fn process_set_lockup(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+pub fn process_set_lockup(validated: ValidatedAccounts, data: &[u8]) -> ProgramResult {
+    let accounts = validated.accounts();
     let lockup_args = LockupArgs::from_data(data)?;
 
-    let [stake_account_info, _remaining @ ..] = accounts else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    parse_accounts!(accounts => [stake_account_info]);
 
-    let signer_args = get_set_lockup_signer_args(stake_account_info, accounts)?;
+    let mut signers = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers)?;
 
     let clock = Clock::get()?;
-
-    do_set_lookup(stake_account_info, &lockup_args, signer_args, &clock)?;
-
-    Ok(())
-}
-
-fn do_set_lookup(
-    stake_account_info: &AccountInfo,
-    lockup: &LockupArgs,
-    signer_args: SetLockupSignerArgs,
-    clock: &Clock,
-) -> ProgramResult {
-    let mut stake_account: pinocchio::account_info::RefMut<'_, StakeStateV2> =
-        try_get_stake_state_mut(stake_account_info)?;
-    match *stake_account {
-        StakeStateV2::Initialized(ref mut meta) => meta
-            .set_lockup(lockup, signer_args, clock)
-            .map_err(to_program_error),
-        StakeStateV2::Stake(ref mut meta, _stake, _stake_flags) => meta
-            .set_lockup(lockup, signer_args, clock)
-            .map_err(to_program_error),
-        _ => Err(ProgramError::InvalidAccountData),
-    }
-}
-
-fn get_set_lockup_signer_args(
-    stake_account_info: &AccountInfo,
-    accounts: &[AccountInfo],
-) -> Result<SetLockupSignerArgs, ProgramError> {
-    let stake_account: pinocchio::account_info::Ref<'_, StakeStateV2> =
-        get_stake_state(stake_account_info)?;
-
-    let mut has_custodian_signer = false;
-    let mut has_withdrawer_signer = false;
-    match *stake_account {
-        StakeStateV2::Initialized(ref meta) | StakeStateV2::Stake(ref meta, _, _) => {
-            for account in accounts {
-                if account.is_signer() {
-                    if meta.lockup.custodian == *account.key() {
-                        has_custodian_signer = true;
-                    }
-                    if meta.authorized.withdrawer == *account.key() {
-                        has_withdrawer_signer = true;
-                    }
-                }
-            }
-        }
-        _ => {
-            return Err(ProgramError::InvalidAccountData);
+    cu_trace::phase("set_lockup/parse");
+
+    let mut stake_state = validated.stake_account(stake_account_info)?.state_mut()?;
+    cu_trace::phase("set_lockup/validate");
+    match *stake_state {
+        StakeStateV2::Initialized(ref mut meta) | StakeStateV2::Stake(ref mut meta, _, _) => {
+            cu_trace::phase("set_lockup/classify");
+            // the authority checks and the write are one step here
+            meta.set_lockup(&lockup_args, &signers[..signers_len], &clock)?;
+            cu_trace::phase("set_lockup/mutate");
+            Ok(())
         }
+        // nothing to lock up before Initialize sets the authorities. Intended divergence:
+        // the native program answers `InvalidAccountData` here as for the rewards pool;
+        // the conformance suite pins both codes
+        StakeStateV2::Uninitialized => Err(ProgramError::UninitializedAccount),
+        // the rewards pool carries no Meta, so it never has a lockup
+        StakeStateV2::RewardsPool => Err(ProgramError::InvalidAccountData),
     }
-    Ok(SetLockupSignerArgs {
-        has_custodian_signer,
-        has_withdrawer_signer,
-    })
 }
 
 #[cfg(test)]
 mod test {
     use super::LockupArgs;
     use bincode::serialize;
+    use pinocchio::program_error::ProgramError;
 
     #[test]
     fn test_instruction_data() {
@@ -262,6 +169,44 @@ mod test {
 
             let args_new = LockupArgs::from_data(data.as_ref()).unwrap();
             assert_eq!(args, args_new);
+
+            let mut buf = [0u8; 51];
+            let len = args.write_data(&mut buf).unwrap();
+            assert_eq!(&buf[..len], data.as_slice());
         }
     }
+
+    #[test]
+    fn test_instruction_data_malformed() {
+        let cases: [&[u8]; 7] = [
+            // empty
+            &[],
+            // unknown tags, in each position
+            &[2, 0, 0],
+            &[0, 2, 0],
+            &[0, 0, 2],
+            // missing the third option
+            &[0, 0],
+            // `Some` epoch without its value
+            &[0, 1, 1, 2, 3],
+            // trailing byte
+            &[0, 0, 0, 0],
+        ];
+
+        for data in cases {
+            assert_eq!(
+                LockupArgs::from_data(data).unwrap_err(),
+                ProgramError::InvalidInstructionData
+            );
+        }
+
+        // a custodian one byte short
+        let mut data = [0u8; 35];
+        data[2] = 1;
+        assert_eq!(
+            LockupArgs::from_data(&data[..34]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        assert!(LockupArgs::from_data(&data).unwrap().custodian.is_some());
+    }
 }
@@ -1,17 +1,21 @@
 use crate::{
-    consts::PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+    consts::MAX_SIGNERS,
+    cu_trace,
     error::StakeError,
+    features::FeatureSet,
+    parse_accounts,
     state::{
-        bytes_to_u64, get_minimum_delegation, relocate_lamports, to_program_error,
-        try_get_stake_state_mut, validate_split_amount, StakeAuthorize, StakeHistorySysvar,
-        StakeStateV2,
+        bytes_to_u64, check_uninitialized_self_signed, clear_flags_if_fully_activated,
+        get_clock_epoch, get_minimum_delegation, is_same_account, relocate_lamports,
+        to_program_error, validate_split_amount, verify_rent_exempt, StakeAuthorize,
+        StakeHistorySysvar, StakeStateTag, StakeStateV2,
     },
+    validation::ValidatedAccounts,
 };
 use pinocchio::{
-    account_info::AccountInfo,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvars::{clock::Clock, Sysvar},
+    sysvars::{rent::Rent, Sysvar},
     ProgramResult,
 };
 
@@ -23,21 +27,34 @@ use crate::state::utils::collect_signers;
 // to avoid breaking backwards compatibility, we do the same here
 // in the future, we may decide to tighten the interface and break badly formed transactions
 
-pub fn process_split(accounts: &[AccountInfo], split_lamports: u64) -> ProgramResult {
-    let mut signers_arr = [Pubkey::default(); 32];
-    let _signers = collect_signers(accounts, &mut signers_arr)?;
+pub fn process_split(
+    validated: ValidatedAccounts,
+    split_lamports: u64,
+    features: &FeatureSet,
+) -> ProgramResult {
+    let accounts = validated.accounts();
+    let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+    let signers_len = collect_signers(accounts, &mut signers_arr)?;
+    let signers = &signers_arr[..signers_len];
 
-    let [source_stake_account_info, destination_stake_account_info, _rest @ ..] = accounts else {
-        return Err(ProgramError::NotEnoughAccountKeys);
-    };
+    parse_accounts!(accounts => [source_stake_account_info, destination_stake_account_info]);
+
+    if is_same_account(source_stake_account_info, destination_stake_account_info) {
+        return Err(ProgramError::InvalidArgument);
+    }
 
-    let clock = Clock::get()?;
-    let stake_history = &StakeHistorySysvar(clock.epoch);
+    // both accounts are written, fail before any work if either is read-only
+    let source_stake_account = validated.stake_account(source_stake_account_info)?;
+    let destination_stake_account = validated.stake_account(destination_stake_account_info)?;
 
+    // only the epoch is needed, so the rest of the clock is never copied
+    let epoch = get_clock_epoch()?;
+    let stake_history = &StakeHistorySysvar(epoch);
+    let rent = Rent::get()?;
+    cu_trace::phase("split/parse");
+
+    // the guard has checked this is exactly the stake state size
     let destination_data_len = destination_stake_account_info.data_len();
-    if destination_data_len != StakeStateV2::size_of() {
-        return Err(ProgramError::InvalidAccountData);
-    }
 
     let source_lamport_balance = source_stake_account_info.lamports();
     let destination_lamport_balance = destination_stake_account_info.lamports();
@@ -46,43 +63,45 @@ pub fn process_split(accounts: &[AccountInfo], split_lamports: u64) -> ProgramRe
         return Err(ProgramError::InsufficientFunds);
     }
 
-    let mut source_stake_account: pinocchio::account_info::RefMut<'_, StakeStateV2> =
-        try_get_stake_state_mut(source_stake_account_info)?;
-    let mut dest_stake_account: pinocchio::account_info::RefMut<'_, StakeStateV2> =
-        try_get_stake_state_mut(destination_stake_account_info)?;
+    // each account's data is borrowed once and held to the end; the source state is
+    // computed on a copy and only what changed is written back
+    let mut source_stake_data = source_stake_account.borrow_mut()?;
+    let mut destination_stake_data = destination_stake_account.borrow_mut()?;
+    let source_stake_state = *source_stake_data.state();
 
-    if let StakeStateV2::Uninitialized = *dest_stake_account {
-        // we can split into this
-    } else {
+    // we can only split into an uninitialized account
+    if destination_stake_data.tag() != StakeStateTag::Uninitialized {
         return Err(ProgramError::InvalidAccountData);
     }
+    cu_trace::phase("split/validate");
 
-    match *source_stake_account {
-        StakeStateV2::Stake(source_meta, mut source_stake, stake_flags) => {
+    let (source_update, destination_update) = match source_stake_state {
+        StakeStateV2::Stake(source_meta, mut source_stake, mut stake_flags) => {
             source_meta
                 .authorized
-                .check(&signers_arr, StakeAuthorize::Staker)
+                .check(signers, StakeAuthorize::Staker)
                 .map_err(to_program_error)?;
 
-            let minimum_delegation = get_minimum_delegation();
+            let minimum_delegation = get_minimum_delegation(features);
 
             let status = source_stake.delegation.stake_activating_and_deactivating(
-                clock.epoch.to_be_bytes(),
+                epoch,
                 stake_history,
-                PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+                features.new_warmup_cooldown_rate_epoch,
             );
 
             let is_active = bytes_to_u64(status.effective) > 0;
 
-            // NOTE this function also internally summons Rent via syscall
             let validated_split_info = validate_split_amount(
                 source_lamport_balance,
                 destination_lamport_balance,
                 split_lamports,
                 &source_meta,
                 destination_data_len,
-                minimum_delegation,
+                minimum_delegation, // source_minimum_delegation
+                minimum_delegation, // destination_minimum_delegation
                 is_active,
+                &rent,
             )?;
 
             // split the stake, subtract rent_exempt_balance unless
@@ -130,58 +149,84 @@ pub fn process_split(accounts: &[AccountInfo], split_lamports: u64) -> ProgramRe
                 return Err(StakeError::InsufficientDelegation.into());
             }
 
+            // both halves inherit the flags, so drop a flag that has done its job first
+            clear_flags_if_fully_activated(
+                &source_stake,
+                &mut stake_flags,
+                epoch,
+                stake_history,
+                features,
+            );
+
             let destination_stake =
                 source_stake.split(remaining_stake_delta, split_stake_amount)?;
 
+            // the destination may be sized differently from the source, so its reserve
+            // is recomputed rather than inherited
             let mut destination_meta = source_meta;
-            destination_meta.rent_exempt_reserve = validated_split_info
-                .destination_rent_exempt_reserve
-                .to_be_bytes();
-
-            *source_stake_account = StakeStateV2::Stake(source_meta, source_stake, stake_flags);
+            destination_meta
+                .set_rent_exempt_reserve(validated_split_info.destination_rent_exempt_reserve);
 
-            *dest_stake_account =
-                StakeStateV2::Stake(destination_meta, destination_stake, stake_flags);
+            (
+                Some(StakeStateV2::Stake(source_meta, source_stake, stake_flags)),
+                Some(StakeStateV2::Stake(destination_meta, destination_stake, stake_flags)),
+            )
         }
         StakeStateV2::Initialized(source_meta) => {
             source_meta
                 .authorized
-                .check(&signers_arr, StakeAuthorize::Staker)
+                .check(signers, StakeAuthorize::Staker)
                 .map_err(to_program_error)?;
 
-            // NOTE this function also internally summons Rent via syscall
             let validated_split_info = validate_split_amount(
                 source_lamport_balance,
                 destination_lamport_balance,
                 split_lamports,
                 &source_meta,
                 destination_data_len,
-                0,     // additional_required_lamports
+                0,     // source_minimum_delegation
+                0,     // destination_minimum_delegation
                 false, // is_active
+                &rent,
             )?;
 
             let mut destination_meta = source_meta;
-            destination_meta.rent_exempt_reserve = validated_split_info
-                .destination_rent_exempt_reserve
-                .to_le_bytes();
+            destination_meta
+                .set_rent_exempt_reserve(validated_split_info.destination_rent_exempt_reserve);
 
-            *dest_stake_account = StakeStateV2::Initialized(destination_meta);
+            (None, Some(StakeStateV2::Initialized(destination_meta)))
         }
         StakeStateV2::Uninitialized => {
-            if !source_stake_account_info.is_signer() {
-                return Err(ProgramError::MissingRequiredSignature);
-            }
+            check_uninitialized_self_signed(source_stake_account_info, signers)?;
+            (None, None)
         }
-        _ => return Err(ProgramError::InvalidAccountData),
+        StakeStateV2::RewardsPool => return Err(ProgramError::InvalidAccountData),
+    };
+    cu_trace::phase("split/classify");
+
+    if let Some(destination_state) = destination_update {
+        destination_stake_data.set_state(&destination_state)?;
     }
+
     if split_lamports == source_lamport_balance {
-        *source_stake_account = StakeStateV2::Uninitialized;
+        // the source is closed, so whatever it would have kept is never written
+        source_stake_data.drain_and_deinitialize(destination_stake_account_info)?;
+    } else {
+        if let Some(source_state) = source_update {
+            source_stake_data.set_state(&source_state)?;
+        }
+        relocate_lamports(
+            source_stake_account_info,
+            destination_stake_account_info,
+            split_lamports,
+        )?;
+        // as with Withdraw, a reserve recorded under older rent does not let the source
+        // drop below what rent requires today; an uninitialized source has none to keep
+        if source_stake_data.tag() != StakeStateTag::Uninitialized {
+            verify_rent_exempt(source_stake_account_info, &rent)?;
+        }
     }
-    relocate_lamports(
-        source_stake_account_info,
-        destination_stake_account_info,
-        split_lamports,
-    )?;
+    cu_trace::phase("split/mutate");
 
     Ok(())
 }
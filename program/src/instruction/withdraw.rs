@@ -0,0 +1,91 @@
+use pinocchio::{
+    program_error::ProgramError,
+    sysvars::{rent::Rent, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    cu_trace,
+    error::StakeError,
+    features::FeatureSet,
+    instruction::lamports_from_data,
+    parse_accounts,
+    state::{
+        check_uninitialized_self_signed, clock_from_account_info, collect_signers_checked,
+        expect_stake_history, relocate_lamports, validate_withdraw_amount, verify_rent_exempt,
+        StakeAuthorize, StakeHistorySysvar, StakeStateV2,
+    },
+    validation::ValidatedAccounts,
+};
+
+pub fn process_withdraw(
+    validated: ValidatedAccounts,
+    data: &[u8],
+    features: &FeatureSet,
+) -> ProgramResult {
+    let accounts = validated.accounts();
+    let withdraw_lamports = lamports_from_data(data)?;
+
+    // native asserts: 5 accounts (2 sysvars), the custodian may follow
+    parse_accounts!(accounts => [
+        stake_account_info,
+        recipient_info,
+        clock_info,
+        stake_history_info,
+        withdraw_authority_info,
+        ..remaining
+    ]);
+
+    let clock = clock_from_account_info(clock_info)?;
+    // the history itself is read through the sysvar syscall
+    expect_stake_history(stake_history_info)?;
+    let stake_history = &StakeHistorySysvar(clock.epoch);
+
+    // only the withdraw authority and the custodian count as signers here
+    let (signers, custodian, signers_len) =
+        collect_signers_checked(Some(withdraw_authority_info), remaining.first())?;
+    let signers = &signers[..signers_len];
+    cu_trace::phase("withdraw/parse");
+
+    let mut stake_account = validated.stake_account(stake_account_info)?.borrow_mut()?;
+    cu_trace::phase("withdraw/validate");
+
+    match stake_account.state() {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            meta.authorized.check(signers, StakeAuthorize::Withdrawer)?;
+            // both the epoch and the timestamp must have passed, unless the custodian signed
+            if meta.lockup.is_in_force(&clock, custodian) {
+                return Err(StakeError::LockupInForce.into());
+            }
+        }
+        // no authorities before Initialize and no lockup either
+        StakeStateV2::Uninitialized => check_uninitialized_self_signed(stake_account_info, signers)?,
+        StakeStateV2::RewardsPool => return Err(ProgramError::InvalidAccountData),
+    }
+
+    validate_withdraw_amount(
+        stake_account.state(),
+        stake_account.lamports(),
+        withdraw_lamports,
+        &clock,
+        stake_history,
+        features,
+    )?;
+    cu_trace::phase("withdraw/classify");
+
+    if withdraw_lamports == stake_account.lamports() {
+        // nothing is staked once the whole balance may go, so the account closes
+        stake_account.drain_and_deinitialize(recipient_info)?;
+    } else {
+        relocate_lamports(stake_account_info, recipient_info, withdraw_lamports)?;
+        // a reserve recorded under older rent does not let the account drop below what
+        // rent requires today. Intended divergence: native only holds back the recorded
+        // reserve, and an uninitialized account has none to hold back either way
+        if *stake_account.state() != StakeStateV2::Uninitialized {
+            verify_rent_exempt(stake_account_info, &Rent::get()?)?;
+        }
+    }
+    cu_trace::phase("withdraw/mutate");
+
+    Ok(())
+}
@@ -8,9 +8,14 @@ extern crate std;
 extern crate alloc;
 
 pub mod consts;
+mod cu_trace;
 pub mod error;
+pub mod features;
 pub mod helpers;
 pub mod instruction;
+#[cfg(all(feature = "paranoid", not(feature = "no-entrypoint")))]
+mod paranoid;
 pub mod state;
+pub mod validation;
 
 pinocchio_pubkey::declare_id!("Stake11111111111111111111111111111111111111");
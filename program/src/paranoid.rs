@@ -0,0 +1,50 @@
+//! Lamport conservation checks, compiled in only with the `paranoid` feature. Stake
+//! instructions move lamports between the accounts they are given but never mint or
+//! burn any, so a changed total points at an accounting bug in a processor.
+
+use pinocchio::account_info::AccountInfo;
+
+use crate::instruction::StakeInstruction;
+
+/// Total lamports of an instruction's writable accounts, taken before it runs
+pub(crate) struct LamportSnapshot {
+    /// Withdraw's recipient may be any account, so it is left out of the total.
+    /// Everything else may then only lose lamports, to the recipient.
+    skip: Option<usize>,
+    total: u128,
+}
+
+impl LamportSnapshot {
+    pub(crate) fn take(instruction: &StakeInstruction, accounts: &[AccountInfo]) -> Self {
+        let skip = matches!(instruction, StakeInstruction::Withdraw).then_some(1);
+        Self {
+            skip,
+            total: writable_lamports(accounts, skip),
+        }
+    }
+
+    pub(crate) fn assert_conserved(&self, accounts: &[AccountInfo]) {
+        let total = writable_lamports(accounts, self.skip);
+        if self.skip.is_some() {
+            assert!(total <= self.total, "lamports created: {} -> {}", self.total, total);
+        } else {
+            assert_eq!(total, self.total, "lamports not conserved");
+        }
+    }
+}
+
+/// Sums the lamports of the writable accounts, counting an account passed in several
+/// positions once
+fn writable_lamports(accounts: &[AccountInfo], skip: Option<usize>) -> u128 {
+    accounts
+        .iter()
+        .enumerate()
+        .filter(|(index, account)| Some(*index) != skip && account.is_writable())
+        .filter(|(index, account)| {
+            !accounts[..*index]
+                .iter()
+                .any(|previous| previous.key() == account.key())
+        })
+        .map(|(_, account)| account.lamports() as u128)
+        .sum()
+}
@@ -1,10 +1,12 @@
 use pinocchio::{program_error::ProgramError, pubkey::Pubkey, sysvars::clock::Clock};
 
-use crate::error::StakeError;
-
 use super::{Lockup, StakeAuthorize};
+use crate::{error::missing_required_signature, helpers::contains_pubkey};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Authorized {
     pub staker: Pubkey,
@@ -19,6 +21,7 @@ impl Authorized {
         }
     }
 
+    #[inline]
     pub fn check(
         &self,
         signers: &[Pubkey],
@@ -28,10 +31,10 @@ impl Authorized {
             StakeAuthorize::Staker => &self.staker,
             StakeAuthorize::Withdrawer => &self.withdrawer,
         };
-        if signers.iter().any(|p| p == authorized_signer) {
+        if contains_pubkey(signers, authorized_signer) {
             Ok(())
         } else {
-            Err(ProgramError::MissingRequiredSignature)
+            Err(missing_required_signature())
         }
     }
 
@@ -45,29 +48,16 @@ impl Authorized {
         match stake_authorize {
             StakeAuthorize::Staker => {
                 // Allow either the staker or the withdrawer to change the staker key
-                if !signers.contains(&self.staker) && !signers.contains(&self.withdrawer) {
+                if !contains_pubkey(signers, &self.staker)
+                    && !contains_pubkey(signers, &self.withdrawer)
+                {
                     return Err(ProgramError::MissingRequiredSignature);
                 }
                 self.staker = *new_authorized
             }
             StakeAuthorize::Withdrawer => {
                 if let Some((lockup, clock, custodian)) = lockup_custodian_args {
-                    if lockup.is_in_force(clock, None) {
-                        match custodian {
-                            None => {
-                                return Err(StakeError::CustodianMissing.into());
-                            }
-                            Some(custodian) => {
-                                if !signers.contains(custodian) {
-                                    return Err(StakeError::CustodianSignatureMissing.into());
-                                }
-
-                                if lockup.is_in_force(clock, Some(custodian)) {
-                                    return Err(StakeError::LockupInForce.into());
-                                }
-                            }
-                        }
-                    }
+                    lockup.check_custodian(clock, signers, custodian)?;
                 }
                 self.check(signers, stake_authorize)?;
                 self.withdrawer = *new_authorized
@@ -76,3 +66,81 @@ impl Authorized {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts::MAX_SIGNERS, error::StakeError, state::add_signer_key};
+
+    /// One key holding every role: the signer set records it once, and every check
+    /// resolves exactly as with distinct keys each signing, as in native where the
+    /// signers are a `HashSet`
+    #[test]
+    fn test_authorize_with_shared_roles() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 1,
+            leader_schedule_epoch: 1,
+            unix_timestamp: 0,
+        };
+        let key = [5; 32];
+        let mut lockup = Lockup {
+            custodian: key,
+            ..Lockup::default()
+        };
+        lockup.set_epoch(2);
+
+        // the same key signing as staker, withdrawer and custodian
+        let mut signers_arr = [Pubkey::default(); MAX_SIGNERS];
+        let mut signers_len = 0;
+        for _ in 0..3 {
+            add_signer_key(&mut signers_arr, &mut signers_len, &key).unwrap();
+        }
+        assert_eq!(signers_len, 1);
+        let signers = &signers_arr[..signers_len];
+
+        let authorized = Authorized::auto(&key);
+        assert_eq!(authorized.check(signers, StakeAuthorize::Staker), Ok(()));
+        assert_eq!(authorized.check(signers, StakeAuthorize::Withdrawer), Ok(()));
+
+        let new_authority = [6; 32];
+        for stake_authorize in [StakeAuthorize::Staker, StakeAuthorize::Withdrawer] {
+            let mut updated = authorized;
+            assert_eq!(
+                updated.authorize(
+                    signers,
+                    &new_authority,
+                    stake_authorize,
+                    Some((&lockup, &clock, Some(&key)))
+                ),
+                Ok(())
+            );
+        }
+
+        // duplicates that did reach a check change nothing either
+        let duplicated = [key, key, key];
+        for stake_authorize in [StakeAuthorize::Staker, StakeAuthorize::Withdrawer] {
+            assert_eq!(
+                authorized.check(&duplicated, stake_authorize),
+                authorized.check(signers, stake_authorize)
+            );
+        }
+        assert_eq!(
+            lockup.check_custodian(&clock, &duplicated, Some(&key)),
+            lockup.check_custodian(&clock, signers, Some(&key))
+        );
+
+        // sharing a key with the custodian does not make an unsigned withdrawer pass
+        let mut updated = authorized;
+        assert_eq!(
+            updated.authorize(
+                &[new_authority],
+                &new_authority,
+                StakeAuthorize::Withdrawer,
+                Some((&lockup, &clock, Some(&key)))
+            ),
+            Err(StakeError::CustodianSignatureMissing.into())
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use alloc::vec::Vec;
 use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
-use crate::state::stake_authorize::StakeAuthorize;
+use crate::{helpers::Cursor, state::stake_authorize::StakeAuthorize};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AuthorizeCheckedWithSeedArgs <'a>{
     pub stake_authorize: StakeAuthorize,
@@ -12,17 +14,16 @@ pub struct AuthorizeCheckedWithSeedArgs <'a>{
 }
 
 impl <'a> AuthorizeCheckedWithSeedArgs<'a>{
+    /// Writes the bincode encoding upstream sends: the role as a `u32` variant
+    /// index, the seed as a `u64` length and its bytes, then the owner
     pub fn serialize(&self)->Vec<u8> {
-        
-        //can just use Vec::new() 
-        let mut buf= Vec::with_capacity(1+4+self.authority_seed.len() + 32);
-        
+        let mut buf= Vec::with_capacity(4 + 8 + self.authority_seed.len() + 32);
 
-        //serialize as a u8
-        buf.push(self.stake_authorize as u8);
+        //serialize StakeAuthorize as its u32 variant index
+        buf.extend_from_slice(&(self.stake_authorize as u32).to_le_bytes());
 
-        //serialize the authority_seed_len
-        buf.extend_from_slice(&(self.authority_seed_len).to_le_bytes());
+        //serialize the authority_seed_len as a bincode String length
+        buf.extend_from_slice(&(self.authority_seed_len as u64).to_le_bytes());
 
         //serialize the authority seed
         buf.extend_from_slice(self.authority_seed.as_bytes());
@@ -30,55 +31,25 @@ impl <'a> AuthorizeCheckedWithSeedArgs<'a>{
         buf.extend_from_slice(self.authority_owner.as_ref());
 
         buf
-        
     }
 
-    fn deserialize(input: &'a [u8])->Result<Self, ProgramError>{
-        if input.len() < 41{
-            return Err(ProgramError::AccountDataTooSmall);
-        }
+    /// Decodes what `serialize` writes. Trailing bytes are rejected.
+    pub fn deserialize(input: &'a [u8])->Result<Self, ProgramError>{
+        let mut cursor = Cursor::new(input);
 
-        let mut offset=0;
-
-        //deserialize StakeAuthorize
-        let stake_authorize= match input.get(offset){
-            Some(0)=>StakeAuthorize::Staker,
-            Some(1)=>StakeAuthorize::Withdrawer,
-            _ => return Err(ProgramError::InvalidInstructionData),
-        };
-        offset +=1;
-
-        //deserialize authority_seed_len
-        if input.len()< offset + 4{
-         return Err(ProgramError::InvalidInstructionData);
-        }
-        let authority_seed_len= u32::from_le_bytes(input[offset..offset + 4].try_into().unwrap());
-        offset +=4;
-
-        let seed_len=authority_seed_len as usize;
-        if input.len()< offset +seed_len{
-            return Err(ProgramError::InvalidInstructionData)
-        }
-
-        let authority_seed=core::str::from_utf8(&input[offset..offset+seed_len]).map_err(|_| ProgramError::InvalidInstructionData)?;
-        offset+=seed_len;
-
-        if input.len() < offset + 32 {
-            return Err(ProgramError::InvalidInstructionData);
-        }
-        
-        let mut authority_owner = [0u8; 32];
-        authority_owner.copy_from_slice(&input[offset..offset + 32]);
+        let stake_authorize = cursor.read_stake_authorize()?;
+        let authority_seed = cursor.read_str()?;
+        let authority_owner = cursor.read_pubkey()?;
+        cursor.finish()?;
 
-        offset +=32;
-        
         Ok(Self{
             stake_authorize,
-            authority_seed_len,
+            // a longer seed could never derive an address anyway
+            authority_seed_len: u32::try_from(authority_seed.len())
+                .map_err(|_| ProgramError::InvalidInstructionData)?,
             authority_seed,
             authority_owner
         })
-
     }
 
 }
@@ -86,7 +57,6 @@ impl <'a> AuthorizeCheckedWithSeedArgs<'a>{
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloc::vec;
 
     #[test]
     fn test_serialize_deserialize() {
@@ -118,4 +88,35 @@ mod tests {
         assert_eq!(deserialized_args.authority_seed_len, args.authority_seed_len);
         assert_eq!(deserialized_args.authority_owner, args.authority_owner);
     }
+
+    /// What the SDK builder puts after the instruction's `u32` variant index
+    #[test]
+    fn test_deserialize_sdk_bytes() {
+        use solana_pubkey::Pubkey as SdkPubkey;
+        use solana_stake_interface::{instruction, state::StakeAuthorize as SdkStakeAuthorize};
+
+        let owner = SdkPubkey::new_unique();
+        let ix = instruction::authorize_checked_with_seed(
+            &SdkPubkey::new_unique(),
+            &SdkPubkey::new_unique(),
+            "sdk seed".to_string(),
+            &owner,
+            &SdkPubkey::new_unique(),
+            SdkStakeAuthorize::Withdrawer,
+            None,
+        );
+        let args = &ix.data[4..];
+
+        let decoded = AuthorizeCheckedWithSeedArgs::deserialize(args).unwrap();
+        assert_eq!(
+            decoded,
+            AuthorizeCheckedWithSeedArgs {
+                stake_authorize: StakeAuthorize::Withdrawer,
+                authority_seed_len: 8,
+                authority_seed: "sdk seed",
+                authority_owner: owner.to_bytes(),
+            }
+        );
+        assert_eq!(decoded.serialize(), args);
+    }
 }
@@ -1,18 +1,24 @@
-use alloc::collections::BTreeMap;
 use pinocchio::{ pubkey::Pubkey, sysvars::clock::Epoch };
-use alloc::vec::Vec;
 
+use super::BoundedVec;
+
+/// Upper bound on the stored authorized voters. The vote program purges every entry
+/// older than the current epoch and can only schedule voters a few epochs ahead, so
+/// live accounts hold far fewer.
+pub const MAX_AUTHORIZED_VOTERS: usize = 32;
+
+/// `(epoch, voter)` entries kept sorted by epoch, in place of the native `BTreeMap`
 #[repr(C)]
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct AuthorizedVoters {
-    authorized_voters: BTreeMap<Epoch, Pubkey>,
+    authorized_voters: BoundedVec<(Epoch, Pubkey), MAX_AUTHORIZED_VOTERS>,
 }
 
 impl AuthorizedVoters {
     pub fn new(epoch: Epoch, pubkey: Pubkey) -> Self {
-        let mut authorized_voters = BTreeMap::new();
+        let mut authorized_voters = Self::default();
         authorized_voters.insert(epoch, pubkey);
-        Self { authorized_voters }
+        authorized_voters
     }
 
     pub fn get_authorized_voter(&self, epoch: Epoch) -> Option<Pubkey> {
@@ -24,26 +30,50 @@ impl AuthorizedVoters {
 
         res.map(|(pubkey, existed)| {
             if !existed {
-                self.authorized_voters.insert(epoch, pubkey);
+                self.insert(epoch, pubkey);
             }
             pubkey
         })
     }
 
+    /// Voter for `current_epoch`, caching it under that epoch and purging every older
+    /// entry, as `VoteState::get_and_update_authorized_voter` does natively
+    pub fn get_and_update_authorized_voter(&mut self, current_epoch: Epoch) -> Option<Pubkey> {
+        let pubkey = self.get_and_cache_authorized_voter_for_epoch(current_epoch)?;
+        self.purge_authorized_voters(current_epoch);
+        Some(pubkey)
+    }
+
+    /// Inserts or replaces the voter for `epoch`. When the map is full the oldest
+    /// entry, which a purge would drop first, makes room.
     pub fn insert(&mut self, epoch: Epoch, authorized_voter: Pubkey) {
-        self.authorized_voters.insert(epoch, authorized_voter);
+        match self.position(epoch) {
+            Ok(index) => {
+                self.authorized_voters.as_mut_slice()[index].1 = authorized_voter;
+            }
+            Err(index) => {
+                let index = if self.authorized_voters.is_full() {
+                    if index == 0 {
+                        // older than everything stored
+                        return;
+                    }
+                    self.authorized_voters.pop_front();
+                    index - 1
+                } else {
+                    index
+                };
+                // there is room for one more entry at this point
+                let _ = self.authorized_voters.push((epoch, authorized_voter));
+                self.authorized_voters.as_mut_slice()[index..].rotate_right(1);
+            }
+        }
     }
 
     pub fn purge_authorized_voters(&mut self, current_epoch: Epoch) -> bool {
-        // Iterate through the keys in order, filtering out the ones
-        // less than the current epoch
-        let expired_keys: Vec<_> = self.authorized_voters
-            .range(0..current_epoch)
-            .map(|(authorized_epoch, _)| *authorized_epoch)
-            .collect();
-
-        for key in expired_keys {
-            self.authorized_voters.remove(&key);
+        // Entries are sorted, so the expired ones are a prefix
+        let expired = self.position(current_epoch).unwrap_or_else(|index| index);
+        for _ in 0..expired {
+            self.authorized_voters.pop_front();
         }
 
         // Have to uphold this invariant b/c this is
@@ -59,11 +89,11 @@ impl AuthorizedVoters {
     }
 
     pub fn first(&self) -> Option<(&u64, &Pubkey)> {
-        self.authorized_voters.iter().next()
+        self.authorized_voters.first().map(|(epoch, pubkey)| (epoch, pubkey))
     }
 
     pub fn last(&self) -> Option<(&u64, &Pubkey)> {
-        self.authorized_voters.iter().next_back()
+        self.authorized_voters.last().map(|(epoch, pubkey)| (epoch, pubkey))
     }
 
     pub fn len(&self) -> usize {
@@ -71,36 +101,74 @@ impl AuthorizedVoters {
     }
 
     pub fn contains(&self, epoch: Epoch) -> bool {
-        self.authorized_voters.contains_key(&epoch)
+        self.position(epoch).is_ok()
     }
 
-    pub fn iter(&self) -> alloc::collections::btree_map::Iter<Epoch, Pubkey> {
+    pub fn iter(&self) -> core::slice::Iter<'_, (Epoch, Pubkey)> {
         self.authorized_voters.iter()
     }
 
+    #[inline(always)]
+    fn position(&self, epoch: Epoch) -> Result<usize, usize> {
+        self.authorized_voters
+            .as_slice()
+            .binary_search_by_key(&epoch, |(authorized_epoch, _)| *authorized_epoch)
+    }
+
     // Returns the authorized voter at the given epoch if the epoch is >= the
     // current epoch, and a bool indicating whether the entry for this epoch
     // exists in the self.authorized_voter map
     fn get_or_calculate_authorized_voter_for_epoch(&self, epoch: Epoch) -> Option<(Pubkey, bool)> {
-        let res = self.authorized_voters.get(&epoch);
-        if res.is_none() {
+        match self.position(epoch) {
+            Ok(index) => Some((self.authorized_voters.as_slice()[index].1, true)),
             // If no authorized voter has been set yet for this epoch,
             // this must mean the authorized voter remains unchanged
             // from the latest epoch before this one
-            let res = self.authorized_voters.range(0..epoch).next_back();
-
-            /*
-            if res.is_none() {
-                warn!(
-                    "Tried to query for the authorized voter of an epoch earlier
-                    than the current epoch. Earlier epochs have been purged"
-                );
-            }
-            */
+            Err(0) => None,
+            Err(index) => Some((self.authorized_voters.as_slice()[index - 1].1, false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_authorized_voter() {
+        let voter_1 = [1u8; 32];
+        let voter_5 = [5u8; 32];
+        let mut authorized_voters = AuthorizedVoters::new(5, voter_5);
+        authorized_voters.insert(1, voter_1);
+
+        assert_eq!(authorized_voters.first(), Some((&1, &voter_1)));
+        assert_eq!(authorized_voters.get_authorized_voter(0), None);
+        assert_eq!(authorized_voters.get_authorized_voter(1), Some(voter_1));
+        assert_eq!(authorized_voters.get_authorized_voter(4), Some(voter_1));
+        assert_eq!(authorized_voters.get_authorized_voter(9), Some(voter_5));
+        assert!(!authorized_voters.contains(4));
+
+        assert_eq!(authorized_voters.get_and_update_authorized_voter(7), Some(voter_5));
+        assert_eq!(authorized_voters.len(), 1);
+        assert_eq!(authorized_voters.first(), Some((&7, &voter_5)));
+    }
 
-            res.map(|(_, pubkey)| (*pubkey, false))
-        } else {
-            res.map(|pubkey| (*pubkey, true))
+    #[test]
+    fn test_insert_when_full() {
+        let mut authorized_voters = AuthorizedVoters::default();
+        for epoch in 1..=MAX_AUTHORIZED_VOTERS as u64 {
+            authorized_voters.insert(epoch, [epoch as u8; 32]);
         }
+        authorized_voters.insert(0, [0; 32]);
+        assert_eq!(authorized_voters.first(), Some((&1, &[1; 32])));
+
+        authorized_voters.insert(100, [100; 32]);
+        assert_eq!(authorized_voters.len(), MAX_AUTHORIZED_VOTERS);
+        assert_eq!(authorized_voters.first(), Some((&2, &[2; 32])));
+        assert_eq!(authorized_voters.last(), Some((&100, &[100; 32])));
+        assert!(authorized_voters
+            .iter()
+            .zip(authorized_voters.iter().skip(1))
+            .all(|(a, b)| a.0 < b.0));
     }
 }
@@ -1,10 +1,18 @@
-use pinocchio::pubkey::Pubkey;
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
 
-use super::{bytes_to_u64, warmup_cooldown_rate, Epoch, StakeHistoryEntry, StakeHistoryGetEntry};
+use crate::error::StakeError;
+
+use super::{
+    bytes_to_u64, stake_history::MAX_ENTRIES, warmup_cooldown_rate, Epoch, StakeHistoryEntry,
+    StakeHistoryGetEntry,
+};
 
 pub type StakeActivationStatus = StakeHistoryEntry;
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Delegation {
     /// to whom the stake is delegated
@@ -24,11 +32,11 @@ pub struct Delegation {
 }
 
 impl Delegation {
-    pub fn new(voter_pubkey: &Pubkey, stake: u64, activation_epoch: Epoch) -> Self {
+    pub fn new(voter_pubkey: &Pubkey, stake: u64, activation_epoch: u64) -> Self {
         Self {
             voter_pubkey: *voter_pubkey,
             stake: stake.to_le_bytes(),
-            activation_epoch,
+            activation_epoch: activation_epoch.to_le_bytes(),
             ..Delegation::default()
         }
     }
@@ -37,6 +45,17 @@ impl Delegation {
         u64::from_le_bytes(self.activation_epoch) == u64::MAX
     }
 
+    /// Fails with `VoteAddressMismatch` unless this stake is delegated to
+    /// `vote_address`: DeactivateDelinquent's delinquent vote account, or the
+    /// destination delegation of MoveStake
+    #[inline(always)]
+    pub fn check_voter(&self, vote_address: &Pubkey) -> Result<(), ProgramError> {
+        if self.voter_pubkey != *vote_address {
+            return Err(StakeError::VoteAddressMismatch.into());
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     pub fn set_stake(&mut self, stake: u64) {
         self.stake = stake.to_le_bytes();
@@ -53,29 +72,30 @@ impl Delegation {
 
     pub fn stake<T: StakeHistoryGetEntry>(
         &self,
-        epoch: Epoch,
+        epoch: u64,
         history: &T,
-        new_rate_activation_epoch: Option<Epoch>,
+        new_rate_activation_epoch: Option<u64>,
     ) -> u64 {
         let result = self
             .stake_activating_and_deactivating(epoch, history, new_rate_activation_epoch)
             .effective;
-        u64::from_be_bytes(result)
+        bytes_to_u64(result)
     }
 
     #[allow(clippy::comparison_chain)]
+    #[inline]
     pub fn stake_activating_and_deactivating<T: StakeHistoryGetEntry>(
         &self,
-        target_epoch: Epoch,
+        target_epoch: u64,
         history: &T,
-        new_rate_activation_epoch: Option<Epoch>,
+        new_rate_activation_epoch: Option<u64>,
     ) -> StakeActivationStatus {
         // first, calculate an effective and activating stake
         let (effective_stake, activating_stake) =
             self.stake_and_activating(target_epoch, history, new_rate_activation_epoch);
 
         // then de-activate some portion if necessary
-        if target_epoch < self.deactivation_epoch {
+        if target_epoch < self.deactivation_epoch() {
             // not deactivated
             if activating_stake == 0 {
                 StakeActivationStatus::with_effective(effective_stake.to_le_bytes())
@@ -85,15 +105,18 @@ impl Delegation {
                     activating_stake.to_le_bytes(),
                 )
             }
-        } else if target_epoch == self.deactivation_epoch {
+        } else if target_epoch == self.deactivation_epoch() {
             // can only deactivate what's activated
             StakeActivationStatus::with_deactivating(effective_stake)
+        } else if effective_stake == 0 {
+            // nothing took effect, so there is nothing to cool down: skip the walk
+            StakeActivationStatus::default()
         } else if let Some((history, mut prev_epoch, mut prev_cluster_stake)) = history
-            .get_entry(bytes_to_u64(self.deactivation_epoch))
+            .get_entry(self.deactivation_epoch())
             .map(|cluster_stake_at_deactivation_epoch| {
                 (
                     history,
-                    self.deactivation_epoch,
+                    self.deactivation_epoch(),
                     cluster_stake_at_deactivation_epoch,
                 )
             })
@@ -104,41 +127,39 @@ impl Delegation {
             // current effective stake is updated using its previous epoch's cluster stake
             let mut current_epoch;
             let mut current_effective_stake = effective_stake;
-            let prev_cluster_stake_deactivating = bytes_to_u64(prev_cluster_stake.deactivating);
-            let prev_cluster_stake_effective = bytes_to_u64(prev_cluster_stake.effective);
+            let mut epochs_walked = 0;
 
             loop {
-                current_epoch = bytes_to_u64(prev_epoch) + 1;
+                current_epoch = prev_epoch + 1;
                 // if there is no deactivating stake at prev epoch, we should have been
                 // fully undelegated at this moment
                 if bytes_to_u64(prev_cluster_stake.deactivating) == 0 {
                     break;
                 }
 
-                // I'm trying to get to zero, how much of the deactivation in stake
-                //   this account is entitled to take
-                let weight =
-                    current_effective_stake as f64 / prev_cluster_stake_deactivating as f64;
-                let warmup_cooldown_rate =
-                    warmup_cooldown_rate(current_epoch.to_be_bytes(), new_rate_activation_epoch);
-
-                // portion of newly not-effective cluster stake I'm entitled to at current epoch
-                let newly_not_effective_cluster_stake =
-                    prev_cluster_stake_effective as f64 * warmup_cooldown_rate;
-                let newly_not_effective_stake =
-                    ((weight * newly_not_effective_cluster_stake) as u64).max(1);
-
-                current_effective_stake =
-                    current_effective_stake.saturating_sub(newly_not_effective_stake);
+                current_effective_stake = current_effective_stake.saturating_sub(
+                    newly_not_effective_stake(
+                        current_effective_stake,
+                        &prev_cluster_stake,
+                        current_epoch,
+                        new_rate_activation_epoch,
+                    ),
+                );
                 if current_effective_stake == 0 {
                     break;
                 }
 
-                if current_epoch >= bytes_to_u64(target_epoch) {
+                if current_epoch >= target_epoch {
+                    break;
+                }
+                // stake history holds at most MAX_ENTRIES epochs, anything older
+                // has dropped out of the sysvar and walking further is wasted work
+                epochs_walked += 1;
+                if epochs_walked >= MAX_ENTRIES {
                     break;
                 }
                 if let Some(current_cluster_stake) = history.get_entry(current_epoch) {
-                    prev_epoch = current_epoch.to_le_bytes();
+                    prev_epoch = current_epoch;
                     prev_cluster_stake = current_cluster_stake;
                 } else {
                     break;
@@ -153,34 +174,100 @@ impl Delegation {
         }
     }
 
+    /// The first epoch at which this delegation has neither effective nor deactivating
+    /// stake left, as far as `history` can tell. `None` while it is not deactivating,
+    /// or when the history ends before its cooldown completes.
+    pub fn fully_deactivated_epoch<T: StakeHistoryGetEntry>(
+        &self,
+        history: &T,
+        new_rate_activation_epoch: Option<u64>,
+    ) -> Option<u64> {
+        let deactivation_epoch = self.deactivation_epoch();
+        if deactivation_epoch == u64::MAX {
+            return None;
+        }
+
+        // same walk as `stake_activating_and_deactivating`, run until the stake is gone
+        let (mut current_effective_stake, _) =
+            self.stake_and_activating(deactivation_epoch, history, new_rate_activation_epoch);
+        if current_effective_stake == 0 {
+            return Some(deactivation_epoch);
+        }
+        let Some(mut prev_cluster_stake) = history.get_entry(deactivation_epoch) else {
+            // no history or I've dropped out of history, so assume fully deactivated
+            return Some(deactivation_epoch + 1);
+        };
+
+        let mut prev_epoch = deactivation_epoch;
+        for _ in 0..MAX_ENTRIES {
+            let current_epoch = prev_epoch + 1;
+            if bytes_to_u64(prev_cluster_stake.deactivating) == 0 {
+                return None;
+            }
+
+            current_effective_stake = current_effective_stake.saturating_sub(
+                newly_not_effective_stake(
+                    current_effective_stake,
+                    &prev_cluster_stake,
+                    current_epoch,
+                    new_rate_activation_epoch,
+                ),
+            );
+            if current_effective_stake == 0 {
+                return Some(current_epoch);
+            }
+
+            prev_cluster_stake = history.get_entry(current_epoch)?;
+            prev_epoch = current_epoch;
+        }
+        None
+    }
+
+    /// Whether the delegation's cooldown has completed by `epoch`, so that none of its
+    /// stake is effective or deactivating any more
+    pub fn is_fully_deactivated<T: StakeHistoryGetEntry>(
+        &self,
+        epoch: u64,
+        history: &T,
+        new_rate_activation_epoch: Option<u64>,
+    ) -> bool {
+        matches!(
+            self.fully_deactivated_epoch(history, new_rate_activation_epoch),
+            Some(fully_deactivated_epoch) if epoch >= fully_deactivated_epoch
+        )
+    }
+
     // returned tuple is (effective, activating) stake
     fn stake_and_activating<T: StakeHistoryGetEntry>(
         &self,
-        target_epoch: Epoch,
+        target_epoch: u64,
         history: &T,
-        new_rate_activation_epoch: Option<Epoch>,
+        new_rate_activation_epoch: Option<u64>,
     ) -> (u64, u64) {
         let delegated_stake = self.stake;
 
         if self.is_bootstrap() {
             // fully effective immediately
             (bytes_to_u64(delegated_stake), 0)
-        } else if self.activation_epoch == self.deactivation_epoch {
+        } else if self.activation_epoch() == self.deactivation_epoch() {
             // activated but instantly deactivated; no stake at all regardless of target_epoch
             // this must be after the bootstrap check and before all-is-activating check
             (0, 0)
-        } else if target_epoch == self.activation_epoch {
+        } else if target_epoch == self.activation_epoch() {
             // all is activating
             (0, bytes_to_u64(delegated_stake))
-        } else if target_epoch < self.activation_epoch {
+        } else if target_epoch < self.activation_epoch() {
             // not yet enabled
             (0, 0)
+        } else if bytes_to_u64(delegated_stake) == 0 {
+            // nothing to warm up, whatever the history says
+            (0, 0)
         } else if let Some((history, mut prev_epoch, mut prev_cluster_stake)) = history
-            .get_entry(bytes_to_u64(self.activation_epoch))
+            .get_entry(self.activation_epoch())
             .map(|cluster_stake_at_activation_epoch| {
                 (
                     history,
-                    self.activation_epoch,
+                    self.activation_epoch(),
                     cluster_stake_at_activation_epoch,
                 )
             })
@@ -191,8 +278,9 @@ impl Delegation {
             // current effective stake is updated using its previous epoch's cluster stake
             let mut current_epoch;
             let mut current_effective_stake = 0;
+            let mut epochs_walked = 0;
             loop {
-                current_epoch = bytes_to_u64(prev_epoch) + 1;
+                current_epoch = prev_epoch + 1;
                 // if there is no activating stake at prev epoch, we should have been
                 // fully effective at this moment
                 if bytes_to_u64(prev_cluster_stake.activating) == 0 {
@@ -206,7 +294,7 @@ impl Delegation {
                 let weight = remaining_activating_stake as f64
                     / bytes_to_u64(prev_cluster_stake.activating) as f64;
                 let warmup_cooldown_rate =
-                    warmup_cooldown_rate(current_epoch.to_le_bytes(), new_rate_activation_epoch);
+                    warmup_cooldown_rate(current_epoch, new_rate_activation_epoch);
 
                 // portion of newly effective cluster stake I'm entitled to at current epoch
                 let newly_effective_cluster_stake =
@@ -220,13 +308,16 @@ impl Delegation {
                     break;
                 }
 
-                if current_epoch >= bytes_to_u64(target_epoch)
-                    || current_epoch >= bytes_to_u64(self.deactivation_epoch)
+                if current_epoch >= target_epoch || current_epoch >= self.deactivation_epoch()
                 {
                     break;
                 }
+                epochs_walked += 1;
+                if epochs_walked >= MAX_ENTRIES {
+                    break;
+                }
                 if let Some(current_cluster_stake) = history.get_entry(current_epoch) {
-                    prev_epoch = current_epoch.to_le_bytes();
+                    prev_epoch = current_epoch;
                     prev_cluster_stake = current_cluster_stake;
                 } else {
                     break;
@@ -264,6 +355,25 @@ impl Delegation {
     }
 }
 
+/// The part of `current_effective_stake` which cools down going into `current_epoch`
+fn newly_not_effective_stake(
+    current_effective_stake: u64,
+    prev_cluster_stake: &StakeHistoryEntry,
+    current_epoch: u64,
+    new_rate_activation_epoch: Option<u64>,
+) -> u64 {
+    // I'm trying to get to zero, how much of the deactivation in stake
+    //   this account is entitled to take
+    let weight = current_effective_stake as f64
+        / bytes_to_u64(prev_cluster_stake.deactivating) as f64;
+    let warmup_cooldown_rate = warmup_cooldown_rate(current_epoch, new_rate_activation_epoch);
+
+    // portion of newly not-effective cluster stake I'm entitled to at current epoch
+    let newly_not_effective_cluster_stake =
+        bytes_to_u64(prev_cluster_stake.effective) as f64 * warmup_cooldown_rate;
+    ((weight * newly_not_effective_cluster_stake) as u64).max(1)
+}
+
 pub const DEFAULT_WARMUP_COOLDOWN_RATE: f64 = 0.25;
 
 impl Default for Delegation {
@@ -278,3 +388,110 @@ impl Default for Delegation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::StakeHistory;
+
+    #[test]
+    fn test_epochs_compare_numerically() {
+        // 2 < 256, though 2's little-endian bytes sort after 256's
+        let delegation = Delegation {
+            deactivation_epoch: 256u64.to_le_bytes(),
+            ..Delegation::new(&[1; 32], 1_000, 1)
+        };
+        let status =
+            delegation.stake_activating_and_deactivating(2, &StakeHistory::default(), None);
+        assert_eq!(bytes_to_u64(status.effective), 1_000);
+        assert_eq!(bytes_to_u64(status.deactivating), 0);
+        assert_eq!(delegation.stake(2, &StakeHistory::default(), None), 1_000);
+    }
+
+    #[test]
+    fn test_check_voter() {
+        let delegation = Delegation::new(&[1; 32], 1_000, 0);
+        assert_eq!(delegation.check_voter(&[1; 32]), Ok(()));
+        assert_eq!(
+            delegation.check_voter(&[2; 32]),
+            Err(StakeError::VoteAddressMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_fully_deactivated_epoch() {
+        let delegation = Delegation {
+            deactivation_epoch: 10u64.to_le_bytes(),
+            ..Delegation::new(&[1; 32], 1_000, u64::MAX)
+        };
+        assert_eq!(
+            Delegation::new(&[1; 32], 1_000, u64::MAX)
+                .fully_deactivated_epoch(&StakeHistory::default(), None),
+            None
+        );
+        // out of history past the deactivation epoch counts as deactivated
+        assert_eq!(
+            delegation.fully_deactivated_epoch(&StakeHistory::default(), None),
+            Some(11)
+        );
+
+        // this delegation is the only one cooling down, so each epoch it loses a
+        // quarter of the cluster's effective stake: 1000 -> 500 -> 125 -> 0
+        let mut history = StakeHistory::default();
+        for (epoch, effective, deactivating) in
+            [(10, 2_000u64, 1_000u64), (11, 1_500, 500), (12, 1_125, 125)]
+        {
+            history.add(
+                epoch,
+                StakeHistoryEntry {
+                    effective: effective.to_le_bytes(),
+                    activating: [0; 8],
+                    deactivating: deactivating.to_le_bytes(),
+                },
+            );
+        }
+        assert_eq!(delegation.fully_deactivated_epoch(&history, None), Some(13));
+        assert!(!delegation.is_fully_deactivated(12, &history, None));
+        assert!(delegation.is_fully_deactivated(13, &history, None));
+
+        // agrees with the per-epoch status
+        let status = delegation.stake_activating_and_deactivating(12, &history, None);
+        assert_eq!(bytes_to_u64(status.deactivating), 125);
+        let status = delegation.stake_activating_and_deactivating(13, &history, None);
+        assert_eq!(status, StakeActivationStatus::default());
+
+        // the history ends mid-cooldown
+        let mut truncated = StakeHistory::default();
+        truncated.add(10, history.get_entry(10).unwrap());
+        assert_eq!(delegation.fully_deactivated_epoch(&truncated, None), None);
+    }
+
+    /// Fails the test on any lookup
+    struct NoHistory;
+
+    impl StakeHistoryGetEntry for NoHistory {
+        fn get_entry(&self, epoch: u64) -> Option<StakeHistoryEntry> {
+            panic!("unexpected stake history lookup for epoch {epoch}");
+        }
+    }
+
+    #[test]
+    fn test_walks_skipped_without_stake() {
+        // no stake to warm up
+        let empty = Delegation::new(&[1; 32], 0, 1);
+        assert_eq!(
+            empty.stake_activating_and_deactivating(100, &NoHistory, None),
+            StakeActivationStatus::default()
+        );
+
+        // deactivated in its activation epoch, so nothing ever took effect
+        let instant = Delegation {
+            deactivation_epoch: 1u64.to_le_bytes(),
+            ..Delegation::new(&[1; 32], 1_000, 1)
+        };
+        assert_eq!(
+            instant.stake_activating_and_deactivating(100, &NoHistory, None),
+            StakeActivationStatus::default()
+        );
+    }
+}
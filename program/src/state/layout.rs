@@ -0,0 +1,54 @@
+//! Compile-time checks pinning the state structs to the native stake program
+//! layout. Account data is reinterpreted in place, so a reordered or resized
+//! field must fail the build instead of silently corrupting accounts.
+
+use core::mem::{align_of, offset_of, size_of};
+
+use super::{Authorized, Delegation, Lockup, Meta, Stake, StakeFlags, StakeStateV2};
+
+// Authorized
+const _: () = assert!(size_of::<Authorized>() == 64);
+const _: () = assert!(align_of::<Authorized>() == 1);
+const _: () = assert!(offset_of!(Authorized, staker) == 0);
+const _: () = assert!(offset_of!(Authorized, withdrawer) == 32);
+
+// Lockup
+const _: () = assert!(size_of::<Lockup>() == 48);
+const _: () = assert!(align_of::<Lockup>() == 1);
+const _: () = assert!(offset_of!(Lockup, unix_timestamp) == 0);
+const _: () = assert!(offset_of!(Lockup, epoch) == 8);
+const _: () = assert!(offset_of!(Lockup, custodian) == 16);
+
+// Meta
+const _: () = assert!(size_of::<Meta>() == 120);
+const _: () = assert!(align_of::<Meta>() == 1);
+const _: () = assert!(offset_of!(Meta, rent_exempt_reserve) == 0);
+const _: () = assert!(offset_of!(Meta, authorized) == 8);
+const _: () = assert!(offset_of!(Meta, lockup) == 72);
+
+// Delegation
+const _: () = assert!(size_of::<Delegation>() == 64);
+const _: () = assert!(align_of::<Delegation>() == 1);
+const _: () = assert!(offset_of!(Delegation, voter_pubkey) == 0);
+const _: () = assert!(offset_of!(Delegation, stake) == 32);
+const _: () = assert!(offset_of!(Delegation, activation_epoch) == 40);
+const _: () = assert!(offset_of!(Delegation, deactivation_epoch) == 48);
+#[allow(deprecated)]
+const _: () = assert!(offset_of!(Delegation, warmup_cooldown_rate) == 56);
+
+// Stake
+const _: () = assert!(size_of::<Stake>() == 72);
+const _: () = assert!(align_of::<Stake>() == 1);
+const _: () = assert!(offset_of!(Stake, delegation) == 0);
+// `credits_observed` is private and pinned in `stake.rs`
+
+// StakeFlags
+const _: () = assert!(size_of::<StakeFlags>() == 1);
+const _: () = assert!(align_of::<StakeFlags>() == 1);
+
+// StakeStateV2: u32 tag followed by the payload, padded to 200 bytes
+const _: () = assert!(size_of::<StakeStateV2>() == StakeStateV2::size_of());
+const _: () = assert!(align_of::<StakeStateV2>() == 4);
+const _: () = assert!(StakeStateV2::META_OFFSET == 4);
+const _: () = assert!(StakeStateV2::STAKE_OFFSET == 124);
+const _: () = assert!(StakeStateV2::STAKE_FLAGS_OFFSET == 196);
@@ -1,8 +1,13 @@
-use pinocchio::{pubkey::Pubkey, sysvars::clock::Clock};
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, sysvars::clock::Clock};
+
+use crate::{error::StakeError, helpers::contains_pubkey};
 
 use super::{Epoch, UnixTimestamp};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Lockup {
     /// UnixTimestamp at which this stake will allow withdrawal, unless the
@@ -45,4 +50,70 @@ impl Lockup {
         i64::from_le_bytes(self.unix_timestamp) > clock.unix_timestamp
             || u64::from_le_bytes(self.epoch) > clock.epoch
     }
+
+    /// Checks that a lockup still in force is lifted by a signing custodian, telling
+    /// apart a custodian account that was not passed (`CustodianMissing`), one that
+    /// did not sign (`CustodianSignatureMissing`) and the wrong custodian
+    /// (`LockupInForce`)
+    pub fn check_custodian(
+        &self,
+        clock: &Clock,
+        signers: &[Pubkey],
+        custodian: Option<&Pubkey>,
+    ) -> Result<(), ProgramError> {
+        if !self.is_in_force(clock, None) {
+            return Ok(());
+        }
+
+        let custodian = custodian.ok_or(StakeError::CustodianMissing)?;
+        if !contains_pubkey(signers, custodian) {
+            return Err(StakeError::CustodianSignatureMissing.into());
+        }
+        if self.is_in_force(clock, Some(custodian)) {
+            return Err(StakeError::LockupInForce.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_custodian() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 1,
+            leader_schedule_epoch: 1,
+            unix_timestamp: 0,
+        };
+        let custodian = [7; 32];
+        let mut lockup = Lockup {
+            custodian,
+            ..Lockup::default()
+        };
+
+        // expired lockups need no custodian at all
+        assert_eq!(lockup.check_custodian(&clock, &[], None), Ok(()));
+
+        lockup.set_epoch(2);
+        assert_eq!(
+            lockup.check_custodian(&clock, &[], None),
+            Err(StakeError::CustodianMissing.into())
+        );
+        assert_eq!(
+            lockup.check_custodian(&clock, &[], Some(&custodian)),
+            Err(StakeError::CustodianSignatureMissing.into())
+        );
+        assert_eq!(
+            lockup.check_custodian(&clock, &[[8; 32]], Some(&[8; 32])),
+            Err(StakeError::LockupInForce.into())
+        );
+        assert_eq!(
+            lockup.check_custodian(&clock, &[custodian], Some(&custodian)),
+            Ok(())
+        );
+    }
 }
@@ -1,12 +1,30 @@
-use crate::{consts::PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH, error::StakeError};
+use crate::{
+    error::StakeError,
+    features::FeatureSet,
+    helpers::{checked_add, sum_lamports},
+};
 use pinocchio::{
     program_error::ProgramError,
     sysvars::clock::{Clock, Epoch},
     ProgramResult,
 };
+#[cfg(feature = "logging")]
 use pinocchio_log::log;
 
-use super::{checked_add, Delegation, Meta, Stake, StakeFlags, StakeHistoryGetEntry, StakeStateV2};
+use super::{
+    Delegation, Meta, Stake, StakeFlags, StakeHistoryGetEntry, StakeStateV2, StakeStatus,
+};
+
+/// Logs `reason` and builds the `MergeMismatch` error, out of line so the logging
+/// stays off the path of merges which succeed
+#[cold]
+#[inline(never)]
+#[cfg_attr(not(feature = "logging"), allow(unused_variables))]
+fn merge_mismatch(reason: &str) -> ProgramError {
+    #[cfg(feature = "logging")]
+    log!("{}", reason);
+    StakeError::MergeMismatch.into()
+}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum MergeKind {
@@ -32,34 +50,34 @@ impl MergeKind {
         }
     }
 
-    pub fn get_if_mergeable<T: StakeHistoryGetEntry>(
+    pub(crate) fn get_if_mergeable<T: StakeHistoryGetEntry>(
         stake_state: &StakeStateV2,
         stake_lamports: u64,
-        clock: &Clock,
+        epoch: Epoch,
         stake_history: &T,
+        features: &FeatureSet,
     ) -> Result<Self, ProgramError> {
         match stake_state {
             StakeStateV2::Stake(meta, stake, stake_flags) => {
-                // stake must not be in a transient state, Transient here meaning
-                // activating or deactivating with non-zero effective stake
-                let epoch_bytes = clock.epoch.to_le_bytes(); // converts u64 to [u8; 8]
+                // stake must not be in a transient state. Transient here meaning
+                // activating or deactivating with non-zero effective stake.
                 let status = stake.delegation.stake_activating_and_deactivating(
-                    epoch_bytes,
+                    epoch,
                     stake_history,
-                    PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+                    features.new_warmup_cooldown_rate_epoch,
                 );
 
-                let effective = u64::from_le_bytes(status.effective);
-                let activating = u64::from_le_bytes(status.activating);
-                let deactivating = u64::from_le_bytes(status.deactivating);
-
-                match (effective, activating, deactivating) {
-                    (0, 0, 0) => Ok(Self::Inactive(*meta, stake_lamports, *stake_flags)),
-                    (0, _, _) => Ok(Self::ActivationEpoch(*meta, *stake, *stake_flags)),
-                    (_, 0, 0) => Ok(Self::FullyActive(*meta, *stake)),
-                    _ => {
+                match StakeStatus::from_activation_status(&status) {
+                    StakeStatus::Inactive => {
+                        Ok(Self::Inactive(*meta, stake_lamports, *stake_flags))
+                    }
+                    // nothing has taken effect yet, i.e. this is the activation epoch
+                    StakeStatus::Activating if u64::from_le_bytes(status.effective) == 0 => {
+                        Ok(Self::ActivationEpoch(*meta, *stake, *stake_flags))
+                    }
+                    StakeStatus::Active => Ok(Self::FullyActive(*meta, *stake)),
+                    StakeStatus::Activating | StakeStatus::Deactivating => {
                         let err = StakeError::MergeTransientStake;
-                        // log!("{}", err.into());
                         Err(err.into())
                     }
                 }
@@ -67,7 +85,9 @@ impl MergeKind {
             StakeStateV2::Initialized(meta) => {
                 Ok(Self::Inactive(*meta, stake_lamports, StakeFlags::empty()))
             }
-            _ => Err(ProgramError::InvalidAccountData),
+            StakeStateV2::Uninitialized | StakeStateV2::RewardsPool => {
+                Err(ProgramError::InvalidAccountData)
+            }
         }
     }
 
@@ -83,78 +103,89 @@ impl MergeKind {
         if stake.authorized == source.authorized && can_merge_lockups {
             Ok(())
         } else {
-            log!("Unable to merge due to metadata mismatch");
-            Err(StakeError::MergeMismatch.into())
+            Err(merge_mismatch("Unable to merge due to metadata mismatch"))
         }
     }
 
-    pub fn active_delegation_can_merge(stake: &Delegation, source: &Delegation) -> ProgramResult {
+    pub fn active_delegations_can_merge(
+        stake: &Delegation,
+        source: &Delegation,
+    ) -> ProgramResult {
         if stake.voter_pubkey != source.voter_pubkey {
-            log!("Unable to merge due to voter mismatch");
-            Err(StakeError::MergeMismatch.into())
+            Err(merge_mismatch("Unable to merge due to voter mismatch"))
         } else if u64::from_le_bytes(stake.deactivation_epoch) == Epoch::MAX
             && u64::from_le_bytes(source.deactivation_epoch) == Epoch::MAX
         {
             Ok(())
         } else {
-            log!("Unable to merge due to stake deactivation");
-            Err(StakeError::MergeMismatch.into())
+            Err(merge_mismatch("Unable to merge due to stake deactivation"))
         }
     }
 
-    pub fn merge(self, source: Self, clock: &Clock) -> Result<Option<StakeStateV2>, ProgramError> {
+    /// Merges `source` into `self`. Both are taken by reference: a `MergeKind` holds a
+    /// whole `Meta` and `Stake`, and only the merged state needs building. `None`
+    /// means the destination state stays as it is, so it need not be rewritten.
+    pub fn merge(
+        &self,
+        source: &Self,
+        clock: &Clock,
+    ) -> Result<Option<StakeStateV2>, ProgramError> {
         Self::metas_can_merge(self.meta(), source.meta(), clock)?;
         self.active_stake()
             .zip(source.active_stake())
             .map(|(stake, source)| {
-                Self::active_delegation_can_merge(&stake.delegation, &source.delegation)
+                Self::active_delegations_can_merge(&stake.delegation, &source.delegation)
             })
             .unwrap_or(Ok(()))?;
         let merged_state = match (self, source) {
             (Self::Inactive(_, _, _), Self::Inactive(_, _, _)) => None,
             (Self::Inactive(_, _, _), Self::ActivationEpoch(_, _, _)) => None,
             (
-                Self::ActivationEpoch(meta, mut stake, stake_flags),
-                Self::Inactive(_, source_Lamports, source_stake_flags),
+                Self::ActivationEpoch(meta, stake, stake_flags),
+                Self::Inactive(_, source_lamports, source_stake_flags),
             ) => {
+                let mut stake = *stake;
                 stake.delegation.stake =
-                    checked_add(stake.delegation.stake, source_Lamports.to_le_bytes())?;
+                    sum_lamports(&[u64::from_le_bytes(stake.delegation.stake), *source_lamports])?
+                        .to_le_bytes();
                 Some(StakeStateV2::Stake(
-                    meta,
+                    *meta,
                     stake,
-                    stake_flags.union(source_stake_flags),
+                    stake_flags.union(*source_stake_flags),
                 ))
             }
             (
-                Self::ActivationEpoch(meta, mut stake, stake_flags),
+                Self::ActivationEpoch(meta, stake, stake_flags),
                 Self::ActivationEpoch(source_meta, source_stake, source_stake_flags),
             ) => {
-                let source_lamports = checked_add(
-                    source_meta.rent_exempt_reserve,
-                    source_stake.delegation.stake,
-                )?;
+                let mut stake = *stake;
+                let source_lamports = sum_lamports(&[
+                    u64::from_le_bytes(source_meta.rent_exempt_reserve),
+                    u64::from_le_bytes(source_stake.delegation.stake),
+                ])?;
                 merge_delegation_stake_and_credits_observed(
                     &mut stake,
                     source_lamports,
-                    source_stake.credits_observed().to_le_bytes(),
+                    source_stake.credits_observed(),
                 )?;
                 Some(StakeStateV2::Stake(
-                    meta,
+                    *meta,
                     stake,
-                    stake_flags.union(source_stake_flags),
+                    stake_flags.union(*source_stake_flags),
                 ))
             }
-            (Self::FullyActive(meta, mut stake), Self::FullyActive(_, source_stake)) => {
+            (Self::FullyActive(meta, stake), Self::FullyActive(_, source_stake)) => {
+                let mut stake = *stake;
                 // Don't stake the source account's `rent_exempt_reserve` to
                 // protect against the magic activation loophole. It will
                 // instead be moved into the destination account as extra,
                 // withdrawable `lamports`
                 merge_delegation_stake_and_credits_observed(
                     &mut stake,
-                    source_stake.delegation.stake,
-                    source_stake.credits_observed().to_le_bytes(),
+                    u64::from_le_bytes(source_stake.delegation.stake),
+                    source_stake.credits_observed(),
                 )?;
-                Some(StakeStateV2::Stake(meta, stake, StakeFlags::empty()))
+                Some(StakeStateV2::Stake(*meta, stake, StakeFlags::empty()))
             }
             _ => return Err(StakeError::MergeMismatch.into()),
         };
@@ -162,35 +193,20 @@ impl MergeKind {
     }
 }
 
-// pub fn merge_delegation_stake_and_credits_observed(
-//     stake: &mut Stake,
-//     absored_lamports: [u8; 8],
-//     absorbed_credits_observed: [u8; 8],
-// ) -> ProgramResult {
-//     let absorbed_lamports_u64 = u64::from_le_bytes(absored_lamports);
-//     let absorbed_credits_observed_u64 = u64::from_le_bytes(absorbed_credits_observed);
-
-//     stake.credits_observed =
-//         stake_weighted_credits_observed(
-//             stake,
-//             absorbed_lamports_u64
-//             absorbed_credits_observed_u64
-//         )
-//             .ok_or(ProgramError::ArithmeticOverflow)?;
-//     stake.delegation.stake = checked_add(stake.delegation.stake, absorbed_lamports_u64)?;
-//     Ok(())
-// }
-
 pub(crate) fn merge_delegation_stake_and_credits_observed(
     stake: &mut Stake,
-    absorbed_lamports: [u8; 8],
-    absorbed_credits_observed: [u8; 8],
+    absorbed_lamports: u64,
+    absorbed_credits_observed: u64,
 ) -> ProgramResult {
-    stake.credits_observed =
+    let credits_observed =
         stake_weighted_credits_observed(stake, absorbed_lamports, absorbed_credits_observed)
-            .ok_or(ProgramError::ArithmeticOverflow)?
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    stake.set_credits_observed(credits_observed);
+
+    stake.delegation.stake =
+        sum_lamports(&[u64::from_le_bytes(stake.delegation.stake), absorbed_lamports])?
             .to_le_bytes();
-    stake.delegation.stake = checked_add(stake.delegation.stake, absorbed_lamports)?;
     Ok(())
 }
 
@@ -221,21 +237,24 @@ pub(crate) fn merge_delegation_stake_and_credits_observed(
 ///  calculation against vote_account and point indirection.)
 pub(crate) fn stake_weighted_credits_observed(
     stake: &Stake,
-    absorbed_lamports: [u8; 8],
-    absorbed_credits_observed: [u8; 8],
+    absorbed_lamports: u64,
+    absorbed_credits_observed: u64,
 ) -> Option<u64> {
-    if stake.credits_observed == absorbed_credits_observed {
-        Some(u64::from_le_bytes(stake.credits_observed))
+    if stake.credits_observed() == absorbed_credits_observed {
+        Some(stake.credits_observed())
     } else {
         let total_stake = u128::from(
-            u64::from_le_bytes(stake.delegation.stake)
-                .checked_add(u64::from_le_bytes(absorbed_lamports))?,
+            checked_add(
+                u64::from_le_bytes(stake.delegation.stake),
+                absorbed_lamports,
+            )
+            .ok()?,
         );
-        let stake_weighted_credits = u128::from(u64::from_le_bytes(stake.credits_observed))
+
+        let stake_weighted_credits = u128::from(stake.credits_observed())
             .checked_mul(u128::from(u64::from_le_bytes(stake.delegation.stake)))?;
         let absorbed_weighted_credits =
-            u128::from(u64::from_le_bytes(absorbed_credits_observed))
-                .checked_mul(u128::from(u64::from_le_bytes(absorbed_lamports)))?;
+            u128::from(absorbed_credits_observed).checked_mul(u128::from(absorbed_lamports))?;
         // Discard fractional credits as a merge side-effect friction by taking
         // the ceiling, done by adding `denominator - 1` to the numerator.
         let total_weighted_credits = stake_weighted_credits
@@ -246,5 +265,197 @@ pub(crate) fn stake_weighted_credits_observed(
     }
 }
 
-// ================= tests ==========================
-// #[cfg(test)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Authorized, Lockup, StakeHistoryEntry};
+
+    struct EmptyStakeHistory;
+
+    impl StakeHistoryGetEntry for EmptyStakeHistory {
+        fn get_entry(&self, _epoch: u64) -> Option<StakeHistoryEntry> {
+            None
+        }
+    }
+
+    fn clock(epoch: u64) -> Clock {
+        Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch,
+            leader_schedule_epoch: epoch,
+            unix_timestamp: 0,
+        }
+    }
+
+    fn meta() -> Meta {
+        Meta {
+            rent_exempt_reserve: 42u64.to_le_bytes(),
+            authorized: Authorized { staker: [1; 32], withdrawer: [2; 32] },
+            lockup: Lockup::default(),
+        }
+    }
+
+    fn stake(voter: u8, lamports: u64, activation_epoch: u64, credits_observed: u64) -> Stake {
+        Stake::new(
+            Delegation::new(&[voter; 32], lamports, activation_epoch),
+            credits_observed,
+        )
+    }
+
+    #[test]
+    fn test_stake_weighted_credits_observed() {
+        let stake = stake(1, 1_000, 0, 10);
+        // same credits, nothing to weigh
+        assert_eq!(stake_weighted_credits_observed(&stake, 5_000, 10), Some(10));
+        // (1_000 * 10 + 1_000 * 20) / 2_000
+        assert_eq!(stake_weighted_credits_observed(&stake, 1_000, 20), Some(15));
+        // rounds up: (1_000 * 10 + 2_000 * 11) / 3_000 = 10.67
+        assert_eq!(stake_weighted_credits_observed(&stake, 2_000, 11), Some(11));
+        // total stake overflows
+        assert_eq!(stake_weighted_credits_observed(&stake, u64::MAX, 11), None);
+    }
+
+    #[test]
+    fn test_get_if_mergeable() {
+        let history = EmptyStakeHistory;
+        let features = &FeatureSet::BUILT_IN;
+
+        assert_eq!(
+            MergeKind::get_if_mergeable(
+                &StakeStateV2::Initialized(meta()),
+                100,
+                10,
+                &history,
+                features
+            ),
+            Ok(MergeKind::Inactive(meta(), 100, StakeFlags::empty()))
+        );
+
+        let activating = stake(1, 1_000, 10, 0);
+        assert_eq!(
+            MergeKind::get_if_mergeable(
+                &StakeStateV2::Stake(meta(), activating, StakeFlags::empty()),
+                1_042,
+                10,
+                &history,
+                features
+            ),
+            Ok(MergeKind::ActivationEpoch(meta(), activating, StakeFlags::empty()))
+        );
+
+        let active = stake(1, 1_000, u64::MAX, 0);
+        assert_eq!(
+            MergeKind::get_if_mergeable(
+                &StakeStateV2::Stake(meta(), active, StakeFlags::empty()),
+                1_042,
+                10,
+                &history,
+                features
+            ),
+            Ok(MergeKind::FullyActive(meta(), active))
+        );
+
+        for state in [StakeStateV2::Uninitialized, StakeStateV2::RewardsPool] {
+            assert_eq!(
+                MergeKind::get_if_mergeable(&state, 0, 10, &history, features),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_metas_can_merge() {
+        let clock = clock(10);
+        assert_eq!(MergeKind::metas_can_merge(&meta(), &meta(), &clock), Ok(()));
+
+        // rent_exempt_reserve is ignored
+        let mut other = meta();
+        other.rent_exempt_reserve = 7u64.to_le_bytes();
+        assert_eq!(MergeKind::metas_can_merge(&meta(), &other, &clock), Ok(()));
+
+        let mut other = meta();
+        other.authorized.staker = [3; 32];
+        assert_eq!(
+            MergeKind::metas_can_merge(&meta(), &other, &clock),
+            Err(StakeError::MergeMismatch.into())
+        );
+
+        // mismatched lockups only merge once both expired
+        let mut other = meta();
+        other.lockup.set_epoch(11);
+        assert_eq!(
+            MergeKind::metas_can_merge(&meta(), &other, &clock),
+            Err(StakeError::MergeMismatch.into())
+        );
+        other.lockup.set_epoch(10);
+        assert_eq!(MergeKind::metas_can_merge(&meta(), &other, &clock), Ok(()));
+    }
+
+    #[test]
+    fn test_merge() {
+        let clock = clock(10);
+        let inactive = MergeKind::Inactive(meta(), 500, StakeFlags::empty());
+        let activating = MergeKind::ActivationEpoch(meta(), stake(1, 1_000, 10, 0), StakeFlags::empty());
+
+        assert_eq!(inactive.merge(&inactive, &clock), Ok(None));
+        assert_eq!(inactive.merge(&activating, &clock), Ok(None));
+
+        // the inactive source is absorbed entirely, reserve included
+        let Some(StakeStateV2::Stake(_, merged, _)) = activating.merge(&inactive, &clock).unwrap()
+        else {
+            panic!("expected a stake");
+        };
+        assert_eq!(u64::from_le_bytes(merged.delegation.stake), 1_500);
+
+        // an activating source adds its reserve as well
+        let Some(StakeStateV2::Stake(_, merged, _)) = activating.merge(&activating, &clock).unwrap()
+        else {
+            panic!("expected a stake");
+        };
+        assert_eq!(u64::from_le_bytes(merged.delegation.stake), 2_042);
+
+        // a fully active source only contributes its delegation
+        let active = MergeKind::FullyActive(meta(), stake(1, 1_000, u64::MAX, 10));
+        let source = MergeKind::FullyActive(meta(), stake(1, 3_000, u64::MAX, 20));
+        let Some(StakeStateV2::Stake(_, merged, _)) = active.merge(&source, &clock).unwrap() else {
+            panic!("expected a stake");
+        };
+        assert_eq!(u64::from_le_bytes(merged.delegation.stake), 4_000);
+        assert_eq!(merged.credits_observed(), 18);
+
+        let other_voter = MergeKind::FullyActive(meta(), stake(2, 1_000, u64::MAX, 10));
+        assert_eq!(
+            active.merge(&other_voter, &clock),
+            Err(StakeError::MergeMismatch.into())
+        );
+        assert_eq!(active.merge(&inactive, &clock), Err(StakeError::MergeMismatch.into()));
+    }
+
+    #[test]
+    fn test_merge_overflow() {
+        let clock = clock(10);
+        let overflow = Err(ProgramError::ArithmeticOverflow);
+
+        let activating =
+            MergeKind::ActivationEpoch(meta(), stake(1, u64::MAX, 10, 0), StakeFlags::empty());
+        let inactive = MergeKind::Inactive(meta(), 1, StakeFlags::empty());
+        assert_eq!(activating.merge(&inactive, &clock), overflow);
+
+        // the source's reserve alone pushes the total over
+        let full_source = MergeKind::ActivationEpoch(
+            meta(),
+            stake(1, u64::MAX - 42, 10, 0),
+            StakeFlags::empty(),
+        );
+        let one = MergeKind::ActivationEpoch(meta(), stake(1, 1, 10, 0), StakeFlags::empty());
+        assert_eq!(one.merge(&full_source, &clock), overflow);
+
+        // same whether or not the credits need weighing
+        let active = MergeKind::FullyActive(meta(), stake(1, u64::MAX, u64::MAX, 10));
+        for credits_observed in [10, 20] {
+            let source = MergeKind::FullyActive(meta(), stake(1, 1, u64::MAX, credits_observed));
+            assert_eq!(active.merge(&source, &clock), overflow);
+        }
+    }
+}
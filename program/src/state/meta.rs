@@ -1,10 +1,15 @@
-use pinocchio::sysvars::clock::Clock;
+use pinocchio::{
+    program_error::ProgramError, pubkey::Pubkey, sysvars::clock::Clock, ProgramResult,
+};
 
-use crate::{error::InstructionError, instruction::LockupArgs};
+use crate::{helpers::pubkey_eq, instruction::LockupArgs};
 
-use super::{Authorized, Lockup};
+use super::{utils::DataLen, Authorized, Lockup};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Meta {
     pub rent_exempt_reserve: [u8; 8], // u64
@@ -12,9 +17,17 @@ pub struct Meta {
     pub lockup: Lockup,
 }
 
-pub struct SetLockupSignerArgs {
-    pub has_custodian_signer: bool,
-    pub has_withdrawer_signer: bool,
+impl DataLen for Meta {
+    const LEN: usize = core::mem::size_of::<Meta>();
+}
+
+/// Which of the roles recorded in a `Meta` are held by one of the signers.
+/// One key may hold several roles at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SignedRoles {
+    pub staker: bool,
+    pub withdrawer: bool,
+    pub custodian: bool,
 }
 
 impl Meta {
@@ -28,21 +41,36 @@ impl Meta {
         u64::from_le_bytes(self.rent_exempt_reserve)
     }
 
+    /// Resolves the staker, withdrawer and lockup custodian against `signers` in
+    /// a single pass
+    pub fn signed_roles(&self, signers: &[Pubkey]) -> SignedRoles {
+        let mut roles = SignedRoles::default();
+        for signer in signers {
+            roles.staker |= pubkey_eq(signer, &self.authorized.staker);
+            roles.withdrawer |= pubkey_eq(signer, &self.authorized.withdrawer);
+            roles.custodian |= pubkey_eq(signer, &self.lockup.custodian);
+        }
+        roles
+    }
+
+    /// Applies the fields set in `lockup`, authorized by whichever of `signers` the
+    /// current lockup requires
     pub fn set_lockup(
         &mut self,
         lockup: &LockupArgs,
-        signer_args: SetLockupSignerArgs,
+        signers: &[Pubkey],
         clock: &Clock,
-    ) -> Result<(), InstructionError> {
+    ) -> ProgramResult {
+        let signed_roles = self.signed_roles(signers);
         // post-stake_program_v4 behavior:
         // * custodian can update the lockup while in force
         // * withdraw authority can set a new lockup
         if self.lockup.is_in_force(clock, None) {
-            if !signer_args.has_custodian_signer {
-                return Err(InstructionError::MissingRequiredSignature);
+            if !signed_roles.custodian {
+                return Err(ProgramError::MissingRequiredSignature);
             }
-        } else if !signer_args.has_withdrawer_signer {
-            return Err(InstructionError::MissingRequiredSignature);
+        } else if !signed_roles.withdrawer {
+            return Err(ProgramError::MissingRequiredSignature);
         }
         if let Some(unix_timestamp) = lockup.unix_timestamp {
             self.lockup.unix_timestamp = unix_timestamp;
@@ -56,3 +84,64 @@ impl Meta {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_roles() {
+        let mut meta = Meta::default();
+        meta.authorized.staker = [1; 32];
+        meta.authorized.withdrawer = [2; 32];
+        meta.lockup.custodian = [2; 32];
+
+        assert_eq!(meta.signed_roles(&[]), SignedRoles::default());
+        assert_eq!(
+            meta.signed_roles(&[[9; 32], [1; 32]]),
+            SignedRoles { staker: true, withdrawer: false, custodian: false }
+        );
+        // the withdrawer doubles as custodian
+        assert_eq!(
+            meta.signed_roles(&[[2; 32]]),
+            SignedRoles { staker: false, withdrawer: true, custodian: true }
+        );
+    }
+
+    #[test]
+    fn test_set_lockup() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 10,
+            leader_schedule_epoch: 10,
+            unix_timestamp: 0,
+        };
+        let withdrawer = [2; 32];
+        let custodian = [3; 32];
+        let mut meta = Meta::default();
+        meta.authorized.withdrawer = withdrawer;
+        meta.lockup.custodian = custodian;
+
+        let args = LockupArgs {
+            unix_timestamp: None,
+            epoch: Some(20u64.to_le_bytes()),
+            custodian: None,
+        };
+
+        // no lockup in force: only the withdrawer may set one
+        assert_eq!(
+            meta.set_lockup(&args, &[custodian], &clock),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(meta.set_lockup(&args, &[withdrawer], &clock), Ok(()));
+        assert_eq!(meta.lockup.epoch(), 20);
+
+        // lockup in force: only the custodian may change it
+        assert_eq!(
+            meta.set_lockup(&args, &[withdrawer], &clock),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(meta.set_lockup(&args, &[custodian], &clock), Ok(()));
+    }
+}
@@ -2,22 +2,30 @@
 pub mod authorized;
 pub mod authorized_checked_with_seed;
 pub mod delegation;
+mod layout;
 pub mod lockup;
 pub mod merge;
 pub mod meta;
 pub mod redelegate_state;
 pub mod stake;
+pub mod stake_account_guard;
 pub mod stake_authorize;
 pub mod stake_flags;
 pub mod stake_history;
 pub mod stake_history_sysvar;
+pub mod stake_status;
 pub mod stake_state_v2;
+pub mod vote_state_reader;
+#[cfg(any(test, feature = "std"))]
+pub mod vote_state_deserialize;
 pub mod vote_state_v3;
 pub mod authorized_voters;
 pub mod utils;
+pub mod zerocopy;
 
 pub use authorized::*;
 pub use delegation::*;
+pub use vote_state_reader::*;
 pub use vote_state_v3::*;
 pub use authorized_voters::*;
 pub use lockup::*;
@@ -30,12 +38,18 @@ use pinocchio::{
     ProgramResult,
 };
 pub use stake::*;
+pub use stake_account_guard::*;
 pub use stake_authorize::*;
 pub use stake_flags::*;
 pub use stake_history::*;
 pub use stake_history_sysvar::*;
+pub use stake_status::*;
 pub use stake_state_v2::*;
 pub use utils::*;
+pub use zerocopy::{
+    is_aligned_for, load_acc, load_acc_mut, load_acc_mut_unchecked, load_acc_unchecked,
+    load_ix_data, to_bytes, to_mut_bytes,
+};
 
 use crate::consts::VOTE_PROGRAM_ID;
 pub use redelegate_state::*;
@@ -43,30 +57,104 @@ pub use redelegate_state::*;
 pub type Epoch = [u8; 8]; //u64
 pub type UnixTimestamp = [u8; 8]; //i64;
 
+/// Whether `account_info` looks like a stake account: owned by this program, exactly
+/// `StakeStateV2` sized and carrying a known discriminant. Processors check this
+/// before handing the data to any of the casting readers.
+pub fn is_stake_account(account_info: &AccountInfo) -> bool {
+    if !account_info.is_owned_by(&crate::ID)
+        || account_info.data_len() != StakeStateV2::size_of()
+    {
+        return false;
+    }
+
+    match account_info.try_borrow_data() {
+        Ok(data) => StakeStateV2::has_valid_discriminant(&data),
+        Err(_) => false,
+    }
+}
+
+/// Whether `account_info` is an initialized vote account of any supported version
+pub fn is_vote_account(account_info: &AccountInfo) -> bool {
+    if !account_info.is_owned_by(&VOTE_PROGRAM_ID) {
+        return false;
+    }
+
+    match account_info.try_borrow_data() {
+        Ok(data) => VoteState::check_account_data(&data).is_ok(),
+        Err(_) => false,
+    }
+}
+
 pub fn get_stake_state(
     stake_account_info: &AccountInfo
 ) -> Result<Ref<StakeStateV2>, ProgramError> {
-    if stake_account_info.is_owned_by(&crate::ID) {
-        return Err(ProgramError::InvalidAccountOwner);
-    }
+    StakeAccountGuard::new(stake_account_info)?.state()
+}
 
-    StakeStateV2::from_account_info(stake_account_info)
+/// The variant of a stake account's state, from its discriminant alone
+pub fn get_stake_state_tag(
+    stake_account_info: &AccountInfo
+) -> Result<StakeStateTag, ProgramError> {
+    StakeAccountGuard::new(stake_account_info)?.tag()
 }
 
 pub fn set_stake_state(
     stake_account_info: &AccountInfo,
     new_state: &StakeStateV2
 ) -> Result<(), ProgramError> {
-    let new_state_size = core::mem::size_of::<StakeStateV2>();
-    let data = stake_account_info.try_borrow_mut_data()?;
-    if data.len() < new_state_size {
-        return Err(ProgramError::AccountDataTooSmall);
-    }
-    let mut new_state_bytes = [0u8; core::mem::size_of::<StakeStateV2>()];
-    new_state_bytes.copy_from_slice(unsafe {
-        core::slice::from_raw_parts(new_state as *const StakeStateV2 as *const u8, new_state_size)
-    });
-    stake_account_info.try_borrow_mut_data()?.copy_from_slice(&new_state_bytes);
+    let mut data = stake_account_info.try_borrow_mut_data()?;
+    zerocopy::write_stake_state(&mut data, new_state)
+}
+
+/// A single section of the `StakeStateV2` payload which can be written
+/// without re-serializing the whole account
+pub enum StakeStateField<'a> {
+    Meta(&'a Meta),
+    Stake(&'a Stake),
+    StakeFlags(StakeFlags),
+}
+
+/// Writes only the bytes of `field` into the stake account, leaving the
+/// discriminant and the rest of the payload untouched.
+///
+/// The account must already hold a variant which carries the field: `Meta` may be
+/// written to `Initialized` and `Stake` accounts, `Stake` and `StakeFlags` only to
+/// `Stake` accounts.
+pub fn update_stake_state_field(
+    stake_account_info: &AccountInfo,
+    field: StakeStateField
+) -> Result<(), ProgramError> {
+    let mut data = stake_account_info.try_borrow_mut_data()?;
+    StakeStateV2::check_data_len(data.len())?;
+    StakeStateV2::upgrade_legacy_layout(&mut data);
+
+    let discriminant = u32::from_le_bytes(
+        data[..StakeStateV2::DISCRIMINANT_LEN].try_into().unwrap()
+    );
+    let (offset, bytes) = match field {
+        StakeStateField::Meta(meta) => {
+            if discriminant != StakeStateV2::INITIALIZED_DISCRIMINANT
+                && discriminant != StakeStateV2::STAKE_DISCRIMINANT
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            (StakeStateV2::META_OFFSET, zerocopy::bytes_of(meta))
+        }
+        StakeStateField::Stake(stake) => {
+            if discriminant != StakeStateV2::STAKE_DISCRIMINANT {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            (StakeStateV2::STAKE_OFFSET, zerocopy::bytes_of(stake))
+        }
+        StakeStateField::StakeFlags(ref stake_flags) => {
+            if discriminant != StakeStateV2::STAKE_DISCRIMINANT {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            (StakeStateV2::STAKE_FLAGS_OFFSET, zerocopy::bytes_of(stake_flags))
+        }
+    };
+
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
     Ok(())
 }
 
@@ -87,11 +175,7 @@ pub unsafe fn get_stake_state_unchecked(
 pub fn try_get_stake_state_mut(
     stake_account_info: &AccountInfo
 ) -> Result<RefMut<StakeStateV2>, ProgramError> {
-    if stake_account_info.is_owned_by(&crate::ID) {
-        return Err(ProgramError::InvalidAccountOwner);
-    }
-
-    StakeStateV2::try_from_account_info_mut(stake_account_info)
+    StakeAccountGuard::new_writable(stake_account_info)?.state_mut()
 }
 
 // dont call this "move" because we have an instruction MoveLamports
@@ -117,19 +201,14 @@ pub fn relocate_lamports(
     Ok(())
 }
 
-pub fn get_vote_state(vote_account_info: &AccountInfo) -> Result<Ref<VoteState>, ProgramError> {
-    if vote_account_info.is_owned_by(&VOTE_PROGRAM_ID) {
-        return Err(ProgramError::IncorrectProgramId);
+/// Returns the still serialized vote state, read through `VoteStateReader`
+pub fn get_vote_state(vote_account_info: &AccountInfo) -> Result<Ref<[u8]>, ProgramError> {
+    if !vote_account_info.is_owned_by(&VOTE_PROGRAM_ID) {
+        return Err(ProgramError::InvalidAccountOwner);
     }
 
-    let vote_state = VoteState::from_account_info(vote_account_info)?;
-    return Ok(vote_state);
-}
-
-pub fn checked_add(a: [u8; 8], b: [u8; 8]) -> Result<[u8; 8], ProgramError> {
-    let a_u64 = u64::from_le_bytes(a);
-    let b_u64 = u64::from_le_bytes(b);
-    a_u64.checked_add(b_u64)
-        .map(|result| result.to_le_bytes())
-        .ok_or(ProgramError::InsufficientFunds)
+    let data = vote_account_info.try_borrow_data()?;
+    // rejects wrong sizes, unknown version tags and uninitialized vote accounts
+    VoteState::check_account_data(&data)?;
+    Ok(data)
 }
@@ -1,4 +1,7 @@
-use super::utils::{load_acc_mut_unchecked, DataLen, Initialized};
+use super::{
+    utils::{DataLen, Initialized},
+    zerocopy::load_acc_mut_unchecked,
+};
 use pinocchio::{
     account_info::AccountInfo,
     program_error::ProgramError,
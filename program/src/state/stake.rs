@@ -1,17 +1,34 @@
 use crate::error::StakeError;
 
-use super::{bytes_to_u64, Delegation, Epoch, StakeHistoryGetEntry};
+use super::{bytes_to_u64, utils::DataLen, Delegation, StakeHistoryGetEntry};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct Stake {
     pub delegation: Delegation,
     /// credits observed is credits from vote account state when delegated or redeemed
-    pub credits_observed: [u8; 8], //u64
-    // changed to pub (as required in utils.rs L511 and L455)
+    /// little-endian u64, only accessed through `credits_observed()`/`set_credits_observed()`
+    credits_observed: [u8; 8],
+}
+
+// the field is private, so its offset is pinned here rather than in `layout`
+const _: () = assert!(core::mem::offset_of!(Stake, credits_observed) == 64);
+
+impl DataLen for Stake {
+    const LEN: usize = core::mem::size_of::<Stake>();
 }
 
 impl Stake {
+    pub fn new(delegation: Delegation, credits_observed: u64) -> Self {
+        Self {
+            delegation,
+            credits_observed: credits_observed.to_le_bytes(),
+        }
+    }
+
     #[inline(always)]
     pub fn set_credits_observed(&mut self, credits_observed: u64) {
         self.credits_observed = credits_observed.to_le_bytes();
@@ -24,9 +41,9 @@ impl Stake {
 
     pub fn stake<T: StakeHistoryGetEntry>(
         &self,
-        epoch: Epoch,
+        epoch: u64,
         history: &T,
-        new_rate_activation_epoch: Option<Epoch>,
+        new_rate_activation_epoch: Option<u64>,
     ) -> u64 {
         self.delegation
             .stake(epoch, history, new_rate_activation_epoch)
@@ -53,12 +70,38 @@ impl Stake {
         Ok(new)
     }
 
-    pub fn deactivate(&mut self, epoch: Epoch) -> Result<(), StakeError> {
-        if bytes_to_u64(self.delegation.deactivation_epoch) != u64::MAX {
+    pub fn deactivate(&mut self, epoch: u64) -> Result<(), StakeError> {
+        if self.delegation.deactivation_epoch() != u64::MAX {
             Err(StakeError::AlreadyDeactivated)
         } else {
-            self.delegation.deactivation_epoch = epoch;
+            self.delegation.set_deactivation_epoch(epoch);
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credits_observed_is_little_endian() {
+        // asymmetric value so a byte-order mix-up cannot go unnoticed
+        let credits = 0x0102_0304_0506_0708u64;
+        let mut stake = Stake::new(Delegation::default(), credits);
+        assert_eq!(stake.credits_observed(), credits);
+        assert_eq!(stake.credits_observed, credits.to_le_bytes());
+
+        stake.set_credits_observed(credits.swap_bytes());
+        assert_eq!(stake.credits_observed(), credits.swap_bytes());
+    }
+
+    #[test]
+    fn test_deactivate() {
+        let mut stake = Stake::new(Delegation::new(&[1; 32], 1_000, 0), 0);
+        assert_eq!(stake.deactivate(5), Ok(()));
+        assert_eq!(stake.delegation.deactivation_epoch(), 5);
+        assert_eq!(stake.deactivate(6), Err(StakeError::AlreadyDeactivated));
+        assert_eq!(stake.delegation.deactivation_epoch(), 5);
+    }
+}
@@ -0,0 +1,177 @@
+use pinocchio::{
+    account_info::{AccountInfo, Ref, RefMut},
+    program_error::ProgramError,
+    ProgramResult,
+};
+
+use super::{
+    relocate_lamports, set_stake_state, update_stake_state_field, zerocopy, StakeStateField,
+    StakeStateTag, StakeStateV2,
+};
+
+/// A stake account whose owner and size have been checked. Processors construct one
+/// per stake account up front and go through it for every read and write of the
+/// account's state, so the checks cannot be skipped or inverted at a call site.
+pub struct StakeAccountGuard<'a> {
+    account_info: &'a AccountInfo,
+    writable: bool,
+}
+
+impl<'a> StakeAccountGuard<'a> {
+    /// Guards a stake account the instruction only reads
+    pub fn new(account_info: &'a AccountInfo) -> Result<Self, ProgramError> {
+        if !account_info.is_owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+        StakeStateV2::check_data_len(account_info.data_len())?;
+
+        Ok(Self {
+            account_info,
+            writable: false,
+        })
+    }
+
+    /// Guards an account `validate_accounts` has already checked against a stake
+    /// account rule, writable if the rule required it, without checking it again.
+    /// Only `ValidatedAccounts` constructs guards this way.
+    #[inline(always)]
+    pub(crate) fn validated(account_info: &'a AccountInfo, writable: bool) -> Self {
+        Self {
+            account_info,
+            writable,
+        }
+    }
+
+    /// Guards a stake account the instruction writes to
+    pub fn new_writable(account_info: &'a AccountInfo) -> Result<Self, ProgramError> {
+        if !account_info.is_writable() {
+            return Err(ProgramError::Immutable);
+        }
+
+        Ok(Self {
+            writable: true,
+            ..Self::new(account_info)?
+        })
+    }
+
+    #[inline(always)]
+    pub fn account_info(&self) -> &'a AccountInfo {
+        self.account_info
+    }
+
+    #[inline(always)]
+    pub fn lamports(&self) -> u64 {
+        self.account_info.lamports()
+    }
+
+    /// The state variant, without mapping the whole state
+    pub fn tag(&self) -> Result<StakeStateTag, ProgramError> {
+        StakeStateV2::peek_tag(&self.account_info.try_borrow_data()?)
+    }
+
+    pub fn state(&self) -> Result<Ref<'a, StakeStateV2>, ProgramError> {
+        StakeStateV2::from_account_info(self.account_info)
+    }
+
+    pub fn state_mut(&self) -> Result<RefMut<'a, StakeStateV2>, ProgramError> {
+        self.check_writable()?;
+        StakeStateV2::try_from_account_info_mut(self.account_info)
+    }
+
+    pub fn set_state(&self, new_state: &StakeStateV2) -> ProgramResult {
+        self.check_writable()?;
+        set_stake_state(self.account_info, new_state)
+    }
+
+    pub fn update_field(&self, field: StakeStateField) -> ProgramResult {
+        self.check_writable()?;
+        update_stake_state_field(self.account_info, field)
+    }
+
+    /// Borrows the account data mutably for the rest of the instruction. Processors
+    /// touching the state more than once take this up front instead of borrowing
+    /// per access.
+    pub fn borrow_mut(&self) -> Result<StakeAccountData<'a>, ProgramError> {
+        self.check_writable()?;
+        let mut data = self.account_info.try_borrow_mut_data()?;
+        StakeStateV2::upgrade_legacy_layout(&mut data);
+        zerocopy::stake_state(&data)?;
+
+        Ok(StakeAccountData {
+            account_info: self.account_info,
+            data,
+        })
+    }
+
+    #[inline(always)]
+    fn check_writable(&self) -> ProgramResult {
+        if !self.writable {
+            return Err(ProgramError::Immutable);
+        }
+        Ok(())
+    }
+}
+
+/// The data of a writable stake account, held mutably borrowed from
+/// `StakeAccountGuard::borrow_mut` until dropped. Every read and write of the state
+/// goes through this one borrow, so no other borrow of the data can interleave.
+pub struct StakeAccountData<'a> {
+    account_info: &'a AccountInfo,
+    data: RefMut<'a, [u8]>,
+}
+
+impl<'a> StakeAccountData<'a> {
+    #[inline(always)]
+    pub fn account_info(&self) -> &'a AccountInfo {
+        self.account_info
+    }
+
+    #[inline(always)]
+    pub fn lamports(&self) -> u64 {
+        self.account_info.lamports()
+    }
+
+    #[inline(always)]
+    pub fn tag(&self) -> StakeStateTag {
+        self.state().tag()
+    }
+
+    #[inline(always)]
+    pub fn state(&self) -> &StakeStateV2 {
+        // SAFETY: size, alignment and tag were checked when the borrow was taken, and
+        // every write since went through a valid `StakeStateV2`
+        unsafe { StakeStateV2::from_bytes(&self.data) }
+    }
+
+    #[inline(always)]
+    pub fn state_mut(&mut self) -> &mut StakeStateV2 {
+        // SAFETY: as for `state`
+        unsafe { StakeStateV2::from_bytes_mut(&mut self.data) }
+    }
+
+    pub fn set_state(&mut self, new_state: &StakeStateV2) -> ProgramResult {
+        zerocopy::write_stake_state(&mut self.data, new_state)
+    }
+
+    /// Closes the account into `destination_account_info`: the state is set to
+    /// `Uninitialized` with the rest of the data zeroed, and all lamports are
+    /// relocated. Merge, full Split and full Withdraw close their account through this
+    /// so the cleanup cannot diverge between them. Lamports are borrowed apart from the data, so
+    /// the held borrow does not get in the way of moving them.
+    pub fn drain_and_deinitialize(
+        &mut self,
+        destination_account_info: &AccountInfo,
+    ) -> ProgramResult {
+        let lamports = self.lamports();
+        // the lamport move is checked before any data is touched, so a failure leaves
+        // the source exactly as it was
+        destination_account_info
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        self.set_state(&StakeStateV2::Uninitialized)?;
+
+        relocate_lamports(self.account_info, destination_account_info, lamports)
+    }
+}
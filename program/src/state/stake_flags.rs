@@ -1,14 +1,29 @@
+use super::utils::DataLen;
+
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Copy, PartialEq, Eq, Clone, PartialOrd, Ord, Hash, Debug)]
 pub struct StakeFlags {
     bits: u8,
 }
 
 impl StakeFlags {
+    /// Stake must be fully activated before deactivation is allowed (bit 0).
+    pub const MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED: Self = Self { bits: 0b0000_0001 };
+
+    /// Every flag bit currently defined by `StakeStateV2`
+    pub const ALL: Self = Self::MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED;
+
     pub const fn empty() -> Self {
         Self { bits: 0 }
     }
 
+    pub const fn bits(&self) -> u8 {
+        self.bits
+    }
+
     pub const fn contains(&self, other: Self) -> bool {
         (self.bits & other.bits) == other.bits
     }
@@ -28,6 +43,10 @@ impl StakeFlags {
     }
 }
 
+impl DataLen for StakeFlags {
+    const LEN: usize = core::mem::size_of::<StakeFlags>();
+}
+
 impl Default for StakeFlags {
     fn default() -> Self {
         StakeFlags::empty()
@@ -19,11 +19,10 @@ pub mod stake_history_id {
 }
 
 pub use stake_history_id::{check_id, id, ID};
-pub const MAX_ENTRIES: usize = 512; // it should never take as many as 512 epochs to warm up or cool down
 
 use crate::state::get_sysvar;
 
-use super::{StakeHistoryEntry, StakeHistoryGetEntry};
+use super::{stake_history::MAX_ENTRIES, StakeHistoryEntry, StakeHistoryGetEntry};
 
 // we do not provide Default because this requires the real current epoch
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -49,6 +48,11 @@ impl StakeHistoryGetEntry for StakeHistorySysvar {
         // None means target epoch is current or in the future; this is a user error
         let epoch_delta = newest_historical_epoch.checked_sub(target_epoch)?;
 
+        // never page past the last entry the sysvar can hold
+        if epoch_delta >= MAX_ENTRIES as u64 {
+            return None;
+        }
+
         // offset is the number of bytes to our desired entry, including eight for vector length
         let offset = epoch_delta
             .checked_mul(EPOCH_AND_ENTRY_SERIALIZED_SIZE)?
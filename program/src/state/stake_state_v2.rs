@@ -3,9 +3,12 @@ use pinocchio::{
     program_error::ProgramError,
 };
 
-use super::{Authorized, Delegation, Lockup, Meta, Stake, StakeFlags};
+use super::{zerocopy, Authorized, Delegation, Lockup, Meta, Stake, StakeFlags};
 
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum StakeStateV2 {
     Uninitialized,
@@ -14,26 +17,54 @@ pub enum StakeStateV2 {
     RewardsPool,
 }
 
+/// The variant of a `StakeStateV2`, read from the discriminant alone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StakeStateTag {
+    Uninitialized,
+    Initialized,
+    Stake,
+    RewardsPool,
+}
+
 impl<'a> StakeStateV2 {
     /// The fixed number of bytes used to serialize each stake account
     pub const fn size_of() -> usize {
         200
     }
 
+    /// Every accessor goes through this before casting: stake accounts are exactly
+    /// `size_of()` bytes, truncated and oversized data are both rejected
+    #[inline(always)]
+    pub fn check_data_len(len: usize) -> Result<(), ProgramError> {
+        if len != Self::size_of() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Variants are tagged with a little-endian u32, as bincode does
+    pub const DISCRIMINANT_LEN: usize = 4;
+    pub const UNINITIALIZED_DISCRIMINANT: u32 = 0;
+    pub const INITIALIZED_DISCRIMINANT: u32 = 1;
+    pub const STAKE_DISCRIMINANT: u32 = 2;
+    pub const REWARDS_POOL_DISCRIMINANT: u32 = 3;
+
+    /// Byte offsets of the variant payload fields within the account data
+    pub const META_OFFSET: usize = Self::DISCRIMINANT_LEN;
+    pub const STAKE_OFFSET: usize = Self::META_OFFSET + core::mem::size_of::<Meta>();
+    pub const STAKE_FLAGS_OFFSET: usize = Self::STAKE_OFFSET + core::mem::size_of::<Stake>();
+
     #[inline]
     pub fn from_account_info(
         account_info: &AccountInfo,
     ) -> Result<Ref<StakeStateV2>, ProgramError> {
-        if account_info.data_len() != Self::size_of() {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        Self::check_data_len(account_info.data_len())?;
 
         let data = account_info.try_borrow_data()?;
-        if !Self::is_aligned_to_4(&*data) || data[0] > 3 {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        zerocopy::stake_state(&data)?;
 
-        Ok(Ref::map(data, |data| unsafe { Self::from_bytes(data) }))
+        // checked just above, on the same borrow
+        Ok(Ref::map(data, |data| zerocopy::stake_state(data).unwrap()))
     }
 
     /// # Safety
@@ -44,33 +75,24 @@ impl<'a> StakeStateV2 {
     pub unsafe fn from_account_info_unchecked(
         account_info: &AccountInfo,
     ) -> Result<&StakeStateV2, ProgramError> {
-        if account_info.data_len() != Self::size_of() {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        let data = account_info.borrow_data_unchecked();
-        if !Self::is_aligned_to_4(data) || data[0] > 3 {
-            return Err(ProgramError::InvalidAccountData);
-        }
-
-        Ok(Self::from_bytes(data))
+        Self::check_data_len(account_info.data_len())?;
+        zerocopy::stake_state(account_info.borrow_data_unchecked())
     }
 
     #[inline]
     pub fn try_from_account_info_mut(
         account_info: &AccountInfo,
     ) -> Result<RefMut<StakeStateV2>, ProgramError> {
-        if account_info.data_len() != Self::size_of() {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        Self::check_data_len(account_info.data_len())?;
 
-        let data = account_info.try_borrow_mut_data()?;
-        if !Self::is_aligned_to_4(&*data) || data[0] > 3 {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        let mut data = account_info.try_borrow_mut_data()?;
+        zerocopy::stake_state(&data)?;
+
+        // accounts untouched since the flags byte was introduced are upgraded lazily,
+        // on their first mutable access
+        Self::upgrade_legacy_layout(&mut data);
 
-        Ok(RefMut::map(data, |data| unsafe {
-            Self::from_bytes_mut(data)
-        }))
+        Ok(RefMut::map(data, |data| zerocopy::stake_state_mut(data).unwrap()))
     }
 
     /// # Safety
@@ -81,35 +103,109 @@ impl<'a> StakeStateV2 {
     pub unsafe fn from_account_info_mut_unchecked(
         account_info: &AccountInfo,
     ) -> Result<&mut StakeStateV2, ProgramError> {
-        if account_info.data_len() != Self::size_of() {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        Self::check_data_len(account_info.data_len())?;
         let data = account_info.borrow_mut_data_unchecked();
-        if !Self::is_aligned_to_4(data) || data[0] > 3 {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        zerocopy::stake_state(data)?;
+        Self::upgrade_legacy_layout(data);
 
-        Ok(Self::from_bytes_mut(data))
+        zerocopy::stake_state_mut(data)
     }
     /// # Safety
     ///
-    /// The caller must ensure that `bytes` contains a valid representation of `StakeStateV2`.
+    /// The caller must ensure that `bytes` contains a valid representation of `StakeStateV2`
+    /// and is aligned for it (see `zerocopy::is_aligned_for`).
     #[inline(always)]
     pub unsafe fn from_bytes(bytes: &[u8]) -> &Self {
-        &*(bytes.as_ptr() as *const Self)
+        zerocopy::cast_unchecked(bytes)
     }
 
     /// # Safety
     ///
-    /// The caller must ensure that `bytes` contains a valid representation of `StakeStateV2`.
+    /// The caller must ensure that `bytes` contains a valid representation of `StakeStateV2`
+    /// and is aligned for it (see `zerocopy::is_aligned_for`).
     #[inline(always)]
     pub unsafe fn from_bytes_mut(bytes: &mut [u8]) -> &mut Self {
-        &mut *(bytes.as_mut_ptr() as *mut Self)
+        zerocopy::cast_mut_unchecked(bytes)
+    }
+
+    /// Returns `true` if `data` holds a `Stake` variant written with the pre-V2
+    /// `StakeState` layout. That layout had no `StakeFlags`, so the byte now holding
+    /// the flags (and the trailing padding) was never defined; any bit there outside
+    /// of `StakeFlags::ALL` means the account has not been rewritten since.
+    pub fn is_legacy_layout(data: &[u8]) -> bool {
+        if data.len() != Self::size_of() {
+            return false;
+        }
+        let discriminant = u32::from_le_bytes(data[..Self::DISCRIMINANT_LEN].try_into().unwrap());
+        if discriminant != Self::STAKE_DISCRIMINANT {
+            return false;
+        }
+
+        let flags_and_padding = &data[Self::STAKE_FLAGS_OFFSET..];
+        flags_and_padding[0] & !StakeFlags::ALL.bits() != 0
+            || flags_and_padding[1..].iter().any(|byte| *byte != 0)
+    }
+
+    /// Rewrites a legacy `StakeState` account in place into a canonical
+    /// `StakeStateV2`, keeping only the flag bits V2 understands and zeroing the padding.
+    /// Meta and Stake are byte-identical between both layouts and are left untouched.
+    pub fn upgrade_legacy_layout(data: &mut [u8]) {
+        if !Self::is_legacy_layout(data) {
+            return;
+        }
+        data[Self::STAKE_FLAGS_OFFSET] &= StakeFlags::ALL.bits();
+        data[Self::STAKE_FLAGS_OFFSET + 1..].fill(0);
     }
 
-    fn is_aligned_to_4(data: &[u8]) -> bool {
-        let ptr = data.as_ptr() as usize;
-        ptr % 4 == 0
+    /// The whole 4-byte tag must name a variant, anything else would be an invalid
+    /// enum value once cast
+    pub(crate) fn has_valid_discriminant(data: &[u8]) -> bool {
+        Self::peek_tag(data).is_ok()
+    }
+
+    /// Classifies `data` from its first 4 bytes only, so processors can reject a
+    /// variant before borrowing the whole state
+    #[inline(always)]
+    pub fn peek_tag(data: &[u8]) -> Result<StakeStateTag, ProgramError> {
+        let discriminant = data
+            .get(..Self::DISCRIMINANT_LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match u32::from_le_bytes(discriminant.try_into().unwrap()) {
+            Self::UNINITIALIZED_DISCRIMINANT => Ok(StakeStateTag::Uninitialized),
+            Self::INITIALIZED_DISCRIMINANT => Ok(StakeStateTag::Initialized),
+            Self::STAKE_DISCRIMINANT => Ok(StakeStateTag::Stake),
+            Self::REWARDS_POOL_DISCRIMINANT => Ok(StakeStateTag::RewardsPool),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn tag(&self) -> StakeStateTag {
+        match self {
+            Self::Uninitialized => StakeStateTag::Uninitialized,
+            Self::Initialized(_) => StakeStateTag::Initialized,
+            Self::Stake(_, _, _) => StakeStateTag::Stake,
+            Self::RewardsPool => StakeStateTag::RewardsPool,
+        }
+    }
+
+    /// Copies the state out of `data` without requiring any alignment, for callers
+    /// which cannot rely on the runtime's aligned account buffers
+    #[inline(always)]
+    pub fn read(data: &[u8]) -> Result<Self, ProgramError> {
+        zerocopy::read_stake_state(data)
+    }
+
+    pub fn is_uninitialized(&self) -> bool {
+        matches!(self, Self::Uninitialized)
+    }
+
+    /// `true` only for the `Initialized` variant; delegated accounts are `is_stake()`
+    pub fn is_initialized(&self) -> bool {
+        matches!(self, Self::Initialized(_))
+    }
+
+    pub fn is_stake(&self) -> bool {
+        matches!(self, Self::Stake(_, _, _))
     }
 
     pub fn stake(&self) -> Option<Stake> {
@@ -152,6 +248,21 @@ impl<'a> StakeStateV2 {
         self.meta().map(|meta| meta.lockup)
     }
 
+    pub fn meta_ref(&self) -> Option<&Meta> {
+        match self {
+            Self::Stake(meta, _stake, _stake_flags) => Some(meta),
+            Self::Initialized(meta) => Some(meta),
+            Self::Uninitialized | Self::RewardsPool => None,
+        }
+    }
+
+    pub fn stake_flags(&self) -> Option<StakeFlags> {
+        match self {
+            Self::Stake(_meta, _stake, stake_flags) => Some(*stake_flags),
+            Self::Uninitialized | Self::Initialized(_) | Self::RewardsPool => None,
+        }
+    }
+
     pub fn meta(&self) -> Option<Meta> {
         match self {
             Self::Stake(meta, _stake, _stake_flags) => Some(*meta),
@@ -162,7 +273,8 @@ impl<'a> StakeStateV2 {
 }
 #[cfg(test)]
 mod test {
-    use super::StakeStateV2;
+    use super::{zerocopy, StakeStateV2};
+    use pinocchio::program_error::ProgramError;
 
     #[test]
     fn test_from_initialized() {
@@ -179,7 +291,8 @@ mod test {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        let val = unsafe { &*(data.as_ptr() as *const StakeStateV2) };
+        // a stack array is only byte aligned, so the state is copied out
+        let val = StakeStateV2::read(&data).unwrap();
 
         println!("{:?}", val);
     }
@@ -199,8 +312,159 @@ mod test {
             255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 208, 63, 201, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        let val = unsafe { &*(data.as_ptr() as *const StakeStateV2) };
+        // a stack array is only byte aligned, so the state is copied out
+        let val = StakeStateV2::read(&data).unwrap();
 
         println!("{:?}", val);
     }
+
+    #[test]
+    fn test_field_offsets() {
+        use crate::state::{Meta, Stake, StakeFlags};
+
+        let mut meta = Meta::default();
+        meta.set_rent_exempt_reserve(2282880);
+        let mut stake = Stake::default();
+        stake.set_credits_observed(969);
+        let state = StakeStateV2::Stake(meta, stake, StakeFlags::empty());
+
+        let mut bytes = [0u8; 200];
+        zerocopy::write_stake_state(&mut bytes, &state).unwrap();
+
+        assert_eq!(core::mem::size_of::<StakeStateV2>(), StakeStateV2::size_of());
+        assert_eq!(
+            &bytes[..StakeStateV2::DISCRIMINANT_LEN],
+            &StakeStateV2::STAKE_DISCRIMINANT.to_le_bytes()
+        );
+        assert_eq!(
+            &bytes[StakeStateV2::META_OFFSET..StakeStateV2::META_OFFSET + 8],
+            &2282880u64.to_le_bytes()
+        );
+        let credits_offset = StakeStateV2::STAKE_FLAGS_OFFSET - 8;
+        assert_eq!(
+            &bytes[credits_offset..StakeStateV2::STAKE_FLAGS_OFFSET],
+            &969u64.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_upgrade_legacy_layout() {
+        let mut data = [0u8; 200];
+        data[..4].copy_from_slice(&StakeStateV2::STAKE_DISCRIMINANT.to_le_bytes());
+        assert!(!StakeStateV2::is_legacy_layout(&data));
+
+        // undefined flag bits and dirty padding
+        data[StakeStateV2::STAKE_FLAGS_OFFSET] = 0b1000_0001;
+        data[199] = 42;
+        assert!(StakeStateV2::is_legacy_layout(&data));
+
+        StakeStateV2::upgrade_legacy_layout(&mut data);
+        assert!(!StakeStateV2::is_legacy_layout(&data));
+        assert_eq!(data[StakeStateV2::STAKE_FLAGS_OFFSET], 0b0000_0001);
+        assert_eq!(data[199], 0);
+
+        // other variants are never treated as legacy
+        let mut initialized = [0xffu8; 200];
+        initialized[..4].copy_from_slice(&StakeStateV2::INITIALIZED_DISCRIMINANT.to_le_bytes());
+        assert!(!StakeStateV2::is_legacy_layout(&initialized));
+    }
+
+    #[test]
+    fn test_discriminants() {
+        use crate::state::{Meta, Stake, StakeFlags};
+
+        let cases = [
+            (StakeStateV2::Uninitialized, StakeStateV2::UNINITIALIZED_DISCRIMINANT),
+            (
+                StakeStateV2::Initialized(Meta::default()),
+                StakeStateV2::INITIALIZED_DISCRIMINANT,
+            ),
+            (
+                StakeStateV2::Stake(Meta::default(), Stake::default(), StakeFlags::empty()),
+                StakeStateV2::STAKE_DISCRIMINANT,
+            ),
+            (StakeStateV2::RewardsPool, StakeStateV2::REWARDS_POOL_DISCRIMINANT),
+        ];
+
+        for (state, discriminant) in cases {
+            let mut bytes = [0xffu8; 200];
+            zerocopy::write_stake_state(&mut bytes, &state).unwrap();
+            assert_eq!(&bytes[..StakeStateV2::DISCRIMINANT_LEN], &discriminant.to_le_bytes());
+            assert_eq!(StakeStateV2::peek_tag(&bytes), Ok(state.tag()));
+        }
+        assert_eq!(StakeStateV2::peek_tag(&[2, 0, 0]), Err(ProgramError::InvalidAccountData));
+
+        // RewardsPool must keep the native wire value of 3
+        let mut data = [0u8; 200];
+        data[0] = 3;
+        assert_eq!(StakeStateV2::read(&data).unwrap(), StakeStateV2::RewardsPool);
+        // only the first byte of the tag was ever checked
+        data[1] = 1;
+        assert_eq!(StakeStateV2::read(&data).unwrap_err(), ProgramError::InvalidAccountData);
+        assert_eq!(StakeStateV2::peek_tag(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn test_data_len() {
+        let size = StakeStateV2::size_of();
+        assert!(StakeStateV2::check_data_len(size).is_ok());
+        for len in [0, StakeStateV2::DISCRIMINANT_LEN, size - 1, size + 1, 2 * size] {
+            assert_eq!(
+                StakeStateV2::check_data_len(len).unwrap_err(),
+                ProgramError::InvalidAccountData
+            );
+        }
+
+        // an otherwise valid Uninitialized account, truncated or padded
+        let data = [0u8; 201];
+        assert_eq!(StakeStateV2::read(&data[..size]).unwrap(), StakeStateV2::Uninitialized);
+        assert_eq!(
+            StakeStateV2::read(&data[..size - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            StakeStateV2::read(&data).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_accessors() {
+        use crate::state::{Meta, Stake, StakeFlags};
+
+        let mut meta = Meta::default();
+        meta.lockup.set_epoch(7);
+        let stake = Stake::default();
+        let flags = StakeFlags::MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED;
+
+        let uninitialized = StakeStateV2::Uninitialized;
+        assert!(uninitialized.is_uninitialized());
+        assert!(!uninitialized.is_initialized());
+        assert!(!uninitialized.is_stake());
+        assert_eq!(uninitialized.meta(), None);
+        assert_eq!(uninitialized.lockup(), None);
+        assert_eq!(uninitialized.delegation(), None);
+
+        let initialized = StakeStateV2::Initialized(meta);
+        assert!(initialized.is_initialized());
+        assert!(!initialized.is_stake());
+        assert_eq!(initialized.meta(), Some(meta));
+        assert_eq!(initialized.meta_ref(), Some(&meta));
+        assert_eq!(initialized.lockup().map(|lockup| lockup.epoch()), Some(7));
+        assert_eq!(initialized.delegation(), None);
+        assert_eq!(initialized.stake_flags(), None);
+
+        let staked = StakeStateV2::Stake(meta, stake, flags);
+        assert!(staked.is_stake());
+        assert!(!staked.is_initialized());
+        assert_eq!(staked.meta(), Some(meta));
+        assert_eq!(staked.delegation(), Some(stake.delegation));
+        assert_eq!(staked.stake_flags(), Some(flags));
+
+        let rewards_pool = StakeStateV2::RewardsPool;
+        assert!(!rewards_pool.is_uninitialized());
+        assert!(!rewards_pool.is_initialized());
+        assert!(!rewards_pool.is_stake());
+        assert_eq!(rewards_pool.meta(), None);
+    }
 }
@@ -0,0 +1,67 @@
+use pinocchio::sysvars::clock::Clock;
+
+use super::{bytes_to_u64, Stake, StakeActivationStatus, StakeHistoryGetEntry};
+use crate::features::FeatureSet;
+
+/// Where a delegation is in its lifecycle at a given epoch
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StakeStatus {
+    /// nothing effective, activating or deactivating
+    Inactive,
+    /// some stake is still warming up; there may already be effective stake
+    Activating,
+    /// all of the delegation is effective
+    Active,
+    /// some stake is cooling down
+    Deactivating,
+}
+
+impl StakeStatus {
+    pub fn from_activation_status(status: &StakeActivationStatus) -> Self {
+        let effective = bytes_to_u64(status.effective);
+        let activating = bytes_to_u64(status.activating);
+        let deactivating = bytes_to_u64(status.deactivating);
+
+        match (effective, activating, deactivating) {
+            (0, 0, 0) => Self::Inactive,
+            (_, 0, 0) => Self::Active,
+            (_, _, 0) => Self::Activating,
+            _ => Self::Deactivating,
+        }
+    }
+}
+
+/// Classifies `stake` as of `clock.epoch`
+pub fn stake_status<T: StakeHistoryGetEntry>(
+    stake: &Stake,
+    clock: &Clock,
+    stake_history: &T,
+    features: &FeatureSet,
+) -> StakeStatus {
+    StakeStatus::from_activation_status(&stake.delegation.stake_activating_and_deactivating(
+        clock.epoch,
+        stake_history,
+        features.new_warmup_cooldown_rate_epoch,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_activation_status() {
+        let status = |effective: u64, activating: u64, deactivating: u64| StakeActivationStatus {
+            effective: effective.to_le_bytes(),
+            activating: activating.to_le_bytes(),
+            deactivating: deactivating.to_le_bytes(),
+        };
+
+        assert_eq!(StakeStatus::from_activation_status(&status(0, 0, 0)), StakeStatus::Inactive);
+        assert_eq!(StakeStatus::from_activation_status(&status(0, 7, 0)), StakeStatus::Activating);
+        assert_eq!(StakeStatus::from_activation_status(&status(3, 4, 0)), StakeStatus::Activating);
+        assert_eq!(StakeStatus::from_activation_status(&status(7, 0, 0)), StakeStatus::Active);
+        assert_eq!(StakeStatus::from_activation_status(&status(7, 0, 7)), StakeStatus::Deactivating);
+        assert_eq!(StakeStatus::from_activation_status(&status(7, 0, 2)), StakeStatus::Deactivating);
+    }
+}
@@ -1,27 +1,30 @@
 use pinocchio::{
-    account_info::{ AccountInfo, Ref },
+    account_info::AccountInfo,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvars::{clock::Clock, rent::Rent, Sysvar},
+    sysvars::{clock::Clock, rent::Rent},
     ProgramResult, SUCCESS,
 };
 
 extern crate alloc;
 use super::{
-    get_stake_state, try_get_stake_state_mut, Delegation, Meta, Stake, StakeAuthorize, StakeHistorySysvar, StakeStateV2, VoteState, DEFAULT_WARMUP_COOLDOWN_RATE
+    stake_status, Delegation, MergeKind, Meta, Stake, StakeAccountGuard, StakeAuthorize, StakeFlags, StakeHistoryGetEntry, StakeHistorySysvar, StakeStateV2, StakeStatus, DEFAULT_WARMUP_COOLDOWN_RATE
 };
 use crate::{
     consts::{
-        FEATURE_STAKE_RAISE_MINIMUM_DELEGATION_TO_1_SOL, LAMPORTS_PER_SOL, MAX_SIGNERS,
+        DEFAULT_EXEMPTION_THRESHOLD, DEFAULT_LAMPORTS_PER_BYTE_YEAR,
+        DEFAULT_STAKE_RENT_EXEMPT_RESERVE, LAMPORTS_PER_SOL, MAX_SIGNERS,
         NEW_WARMUP_COOLDOWN_RATE,
     },
-    helpers::MergeKind,
+    features::FeatureSet,
+    helpers::{checked_sub, contains_pubkey, sum_lamports},
 };
 use crate::{consts::{
-    CLOCK_ID, HASH_BYTES, MAX_BASE58_LEN, PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH
-}, error::StakeError};
-use alloc::boxed::Box;
-use core::{ cell::UnsafeCell, fmt, str::from_utf8 };
+    CLOCK_ID, HASH_BYTES, RENT_ID, STAKE_CONFIG_ID, SYSVAR
+}, error::{missing_required_signature, StakeError}};
+use core::fmt;
+#[cfg(feature = "base58")]
+use core::str::from_utf8;
 
 pub trait DataLen {
     const LEN: usize;
@@ -31,81 +34,58 @@ pub trait Initialized {
     fn is_initialized(&self) -> bool;
 }
 
-#[inline(always)]
-pub unsafe fn load_acc<T: DataLen + Initialized>(bytes: &[u8]) -> Result<&T, ProgramError> {
-    load_acc_unchecked::<T>(bytes).and_then(|acc| {
-        if acc.is_initialized() { Ok(acc) } else { Err(ProgramError::UninitializedAccount) }
-    })
-}
-
-#[inline(always)]
-pub unsafe fn load_acc_unchecked<T: DataLen>(bytes: &[u8]) -> Result<&T, ProgramError> {
-    if bytes.len() != T::LEN {
-        return Err(ProgramError::InvalidAccountData);
-    }
-    Ok(&*(bytes.as_ptr() as *const T))
-}
-
-#[inline(always)]
-pub unsafe fn load_acc_mut<T: DataLen + Initialized>(
-    bytes: &mut [u8]
-) -> Result<&mut T, ProgramError> {
-    load_acc_mut_unchecked::<T>(bytes).and_then(|acc| {
-        if acc.is_initialized() { Ok(acc) } else { Err(ProgramError::UninitializedAccount) }
-    })
-}
-
-#[inline(always)]
-pub unsafe fn load_acc_mut_unchecked<T: DataLen>(bytes: &mut [u8]) -> Result<&mut T, ProgramError> {
-    if bytes.len() != T::LEN {
-        return Err(ProgramError::InvalidAccountData);
-    }
-    Ok(&mut *(bytes.as_mut_ptr() as *mut T))
-}
-
-#[inline(always)]
-pub unsafe fn load_ix_data<T: DataLen>(bytes: &[u8]) -> Result<&T, ProgramError> {
-    if bytes.len() != T::LEN {
-        return Err(ProgramError::InvalidInstructionData.into());
-    }
-    Ok(&*(bytes.as_ptr() as *const T))
-}
-
-pub unsafe fn to_bytes<T: DataLen>(data: &T) -> &[u8] {
-    core::slice::from_raw_parts(data as *const T as *const u8, T::LEN)
-}
-
-pub unsafe fn to_mut_bytes<T: DataLen>(data: &mut T) -> &mut [u8] {
-    core::slice::from_raw_parts_mut(data as *mut T as *mut u8, T::LEN)
-}
-
 //---------- Stake Program Utils -------------
 
+#[inline]
 pub fn collect_signers(
     accounts: &[AccountInfo],
     signers_arr: &mut [Pubkey; MAX_SIGNERS]
 ) -> Result<usize, ProgramError> {
     let mut signer_len = 0;
 
-    for account in accounts {
-        if account.is_signer() {
-            if signer_len >= MAX_SIGNERS {
-                return Err(ProgramError::AccountDataTooSmall);
-            }
-            signers_arr[signer_len] = *account.key();
-            signer_len += 1;
-        }
+    // the same signer may appear in several account metas, it is only recorded once
+    for account in accounts.iter().filter(|account| account.is_signer()) {
+        add_signer(signers_arr, &mut signer_len, account)?;
     }
 
     Ok(signer_len)
 }
 
+/// Whether two account positions refer to the same account. The runtime passes a
+/// repeated account once per position, so processors holding separate borrows of
+/// accounts which must be distinct check this first.
+#[inline(always)]
+pub fn is_same_account(a: &AccountInfo, b: &AccountInfo) -> bool {
+    a.key() == b.key()
+}
+
 pub fn next_account_info<'a, I: Iterator<Item = &'a AccountInfo>>(
     iter: &mut I
 ) -> Result<&'a AccountInfo, ProgramError> {
     iter.next().ok_or(ProgramError::NotEnoughAccountKeys)
 }
 
+/// Binds the leading accounts of an instruction by name, returning
+/// `NotEnoughAccountKeys` when fewer are supplied. Any further accounts are
+/// ignored, or bound as a slice with a trailing `..rest`:
+///
+/// ```ignore
+/// parse_accounts!(accounts => [stake_account_info, clock_info, ..remaining]);
+/// ```
+#[macro_export]
+macro_rules! parse_accounts {
+    ($accounts:expr => [$($name:ident),+ $(,)?]) => {
+        let [$($name,)+ ..] = $accounts else {
+            return Err(pinocchio::program_error::ProgramError::NotEnoughAccountKeys);
+        };
+    };
+    ($accounts:expr => [$($name:ident),+, ..$rest:ident]) => {
+        let [$($name,)+ $rest @ ..] = $accounts else {
+            return Err(pinocchio::program_error::ProgramError::NotEnoughAccountKeys);
+        };
+    };
+}
+
 #[macro_export]
 macro_rules! impl_sysvar_id {
     ($type:ty) => {
@@ -129,6 +109,20 @@ macro_rules! declare_sysvar_id {
     );
 }
 
+/// `rent.minimum_balance(data_len)`, answered from `DEFAULT_STAKE_RENT_EXEMPT_RESERVE`
+/// for stake-sized accounts while the live parameters are the defaults
+#[inline(always)]
+pub fn stake_rent_exempt_reserve(rent: &Rent, data_len: usize) -> u64 {
+    if data_len == StakeStateV2::size_of()
+        && rent.lamports_per_byte_year == DEFAULT_LAMPORTS_PER_BYTE_YEAR
+        && rent.exemption_threshold == DEFAULT_EXEMPTION_THRESHOLD
+    {
+        DEFAULT_STAKE_RENT_EXEMPT_RESERVE
+    } else {
+        rent.minimum_balance(data_len)
+    }
+}
+
 /// After calling `validate_split_amount()`, this struct contains calculated
 /// values that are used by the caller.
 #[derive(Copy, Clone, Debug, Default)]
@@ -141,14 +135,23 @@ pub(crate) struct ValidatedSplitInfo {
 /// accounts meet the minimum balance requirements, which is the rent exempt
 /// reserve plus the minimum stake delegation, and that the source account has
 /// enough lamports for the request split amount.  If not, return an error.
+///
+/// Splits of delegated stake pass `get_minimum_delegation(features)` for both
+/// `source_minimum_delegation` and `destination_minimum_delegation`, so neither
+/// side can be left below the feature-gated minimum; undelegated splits pass 0.
+///
+/// `rent` is fetched once by the caller, so the destination reserve does not cost
+/// another syscall.
 pub(crate) fn validate_split_amount(
     source_lamports: u64,
     destination_lamports: u64,
     split_lamports: u64,
     source_meta: &Meta,
     destination_data_len: usize,
-    additional_required_lamports: u64,
-    source_is_active: bool
+    source_minimum_delegation: u64,
+    destination_minimum_delegation: u64,
+    source_is_active: bool,
+    rent: &Rent
 ) -> Result<ValidatedSplitInfo, ProgramError> {
     // Split amount has to be something
     if split_lamports == 0 {
@@ -164,14 +167,14 @@ pub(crate) fn validate_split_amount(
     // splitting: EITHER at least the minimum balance, OR zero (in this case the
     // source account is transferring all lamports to new destination account,
     // and the source account will be closed)
-    let source_minimum_balance = u64
-        ::from_le_bytes(source_meta.rent_exempt_reserve)
-        .saturating_add(additional_required_lamports);
-    let source_remaining_balance = source_lamports.saturating_sub(split_lamports);
+    // minimum balances are summed in a u128 so they cannot saturate or wrap
+    let source_minimum_balance = u128::from(u64::from_le_bytes(source_meta.rent_exempt_reserve))
+        + u128::from(source_minimum_delegation);
+    let source_remaining_balance = checked_sub(source_lamports, split_lamports)?;
     if source_remaining_balance == 0 {
         // full amount is a withdrawal
         // nothing to do here
-    } else if source_remaining_balance < source_minimum_balance {
+    } else if u128::from(source_remaining_balance) < source_minimum_balance {
         // the remaining balance is too low to do the split
         return Err(ProgramError::InsufficientFunds);
     } else {
@@ -179,8 +182,7 @@ pub(crate) fn validate_split_amount(
         // nothing to do here
     }
 
-    let rent = Rent::get()?;
-    let destination_rent_exempt_reserve = rent.minimum_balance(destination_data_len);
+    let destination_rent_exempt_reserve = stake_rent_exempt_reserve(rent, destination_data_len);
 
     // If the source is active stake, one of these criteria must be met:
     // 1. the destination account must be prefunded with at least the rent-exempt
@@ -200,12 +202,11 @@ pub(crate) fn validate_split_amount(
     //    size changes
     // 2. The destination account being prefunded, which would lower the minimum
     //    split amount
-    let destination_minimum_balance = destination_rent_exempt_reserve.saturating_add(
-        additional_required_lamports
-    );
+    let destination_minimum_balance =
+        u128::from(destination_rent_exempt_reserve) + u128::from(destination_minimum_delegation);
     let destination_balance_deficit =
-        destination_minimum_balance.saturating_sub(destination_lamports);
-    if split_lamports < destination_balance_deficit {
+        destination_minimum_balance.saturating_sub(u128::from(destination_lamports));
+    if u128::from(split_lamports) < destination_balance_deficit {
         return Err(ProgramError::InsufficientFunds);
     }
 
@@ -217,37 +218,6 @@ pub(crate) fn validate_split_amount(
 
 //-------------- Solana Program Sysvar Copies ---------------
 
-//---------------- This Get Sysvar was assisted by AI, needs to be checked ----------------------
-//For this syscall mock, unlike solana program we use single thread to mantain the no_std enviorement
-//Defining a generic Lazy<T> struct with interior mutability
-pub struct Lazy<T> {
-    value: UnsafeCell<Option<T>>,
-}
-
-impl<T> Lazy<T> {
-    pub const fn new() -> Self {
-        Self {
-            value: UnsafeCell::new(None),
-        }
-    }
-
-    pub fn get_or_init<F: FnOnce() -> T>(&self, init: F) -> &T {
-        // SAFETY: Only safe because Solana programs are single-threaded.
-        // So its ok to get mutable access (even though `self` is shared!)
-        unsafe {
-            let value = &mut *self.value.get();
-            if value.is_none() {
-                *value = Some(init());
-            }
-            value.as_ref().unwrap()
-        }
-    }
-}
-
-static SYSCALL_STUBS: Lazy<Box<dyn SyscallStubs>> = Lazy::new();
-
-unsafe impl<T> Sync for Lazy<T> {} //although this is telling that is available for multithreading, we know it wont happen
-
 /// Builtin return values occupy the upper 32 bits
 const BUILTIN_BIT_SHIFT: usize = 32;
 macro_rules! to_builtin {
@@ -258,39 +228,51 @@ macro_rules! to_builtin {
 
 pub const UNSUPPORTED_SYSVAR: u64 = to_builtin!(17);
 
-pub trait SyscallStubs: Sync + Send {
-    fn sol_get_sysvar(
-        &self,
-        _sysvar_id_addr: *const u8,
-        _var_addr: *mut u8,
-        _offset: u64,
-        _length: u64
-    ) -> u64 {
-        UNSUPPORTED_SYSVAR
-    }
+/// Off chain there is no `sol_get_sysvar` syscall; `get_sysvar` calls whatever stub
+/// sits in this slot instead, by default one failing with `UNSUPPORTED_SYSVAR`.
+/// The slot is a plain function pointer, so unlike a boxed trait object it needs no
+/// allocator, and none of it exists in the `target_os = "solana"` build.
+#[cfg(not(target_os = "solana"))]
+pub type SolGetSysvarStub =
+    fn(sysvar_id_addr: *const u8, var_addr: *mut u8, offset: u64, length: u64) -> u64;
+
+#[cfg(not(target_os = "solana"))]
+fn unsupported_sysvar(_: *const u8, _: *mut u8, _: u64, _: u64) -> u64 {
+    UNSUPPORTED_SYSVAR
 }
 
-pub struct DefaultSyscallStubs {}
+// null until a stub is installed, atomic since tests run on several threads
+#[cfg(not(target_os = "solana"))]
+static SOL_GET_SYSVAR_STUB: core::sync::atomic::AtomicPtr<()> =
+    core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
 
-impl SyscallStubs for DefaultSyscallStubs {}
+/// Installs `stub` in place of the off-chain `sol_get_sysvar`, returning the previous one
+#[cfg(not(target_os = "solana"))]
+pub fn set_sol_get_sysvar_stub(stub: SolGetSysvarStub) -> SolGetSysvarStub {
+    let previous = SOL_GET_SYSVAR_STUB.swap(stub as *mut (), core::sync::atomic::Ordering::AcqRel);
+    stub_from_ptr(previous)
+}
 
-#[allow(dead_code)]
+#[cfg(not(target_os = "solana"))]
+fn stub_from_ptr(ptr: *mut ()) -> SolGetSysvarStub {
+    if ptr.is_null() {
+        return unsupported_sysvar;
+    }
+    // SAFETY: the slot only ever holds null or a `SolGetSysvarStub` stored above
+    unsafe { core::mem::transmute::<*mut (), SolGetSysvarStub>(ptr) }
+}
+
+#[cfg(not(target_os = "solana"))]
 pub(crate) fn sol_get_sysvar(
     sysvar_id_addr: *const u8,
     var_addr: *mut u8,
     offset: u64,
     length: u64
 ) -> u64 {
-    SYSCALL_STUBS.get_or_init(|| Box::new(DefaultSyscallStubs {})).sol_get_sysvar(
-        sysvar_id_addr,
-        var_addr,
-        offset,
-        length
-    )
+    let stub = stub_from_ptr(SOL_GET_SYSVAR_STUB.load(core::sync::atomic::Ordering::Acquire));
+    stub(sysvar_id_addr, var_addr, offset, length)
 }
 
-//---------------- End of AI assistance ----------------------
-
 /// Handler for retrieving a slice of sysvar data from the `sol_get_sysvar`
 /// syscall.
 pub fn get_sysvar(
@@ -324,13 +306,42 @@ pub fn get_sysvar(
     }
 }
 
+// Byte offset of `Clock::epoch` within the sysvar's account data
+const CLOCK_EPOCH_OFFSET: u64 = 16;
+
+/// Fetches the `N` bytes at `offset` of a sysvar, without copying the rest of it
+fn get_sysvar_field<const N: usize>(
+    sysvar_id: &Pubkey,
+    offset: u64,
+) -> Result<[u8; N], ProgramError> {
+    let mut field = [0u8; N];
+    get_sysvar(&mut field, sysvar_id, offset, N as u64)?;
+    Ok(field)
+}
+
+/// `Clock::epoch`, for paths which need nothing else of the clock
+#[inline(always)]
+pub fn get_clock_epoch() -> Result<u64, ProgramError> {
+    get_sysvar_field(&CLOCK_ID, CLOCK_EPOCH_OFFSET).map(u64::from_le_bytes)
+}
+
+/// Fails with `AccountNotRentExempt` unless `account_info` holds at least the
+/// rent-exempt minimum for its current data length. Paths that create stake accounts
+/// or take lamports out of them check through this, so they all compare the same way.
+pub fn verify_rent_exempt(account_info: &AccountInfo, rent: &Rent) -> ProgramResult {
+    if account_info.lamports() < stake_rent_exempt_reserve(rent, account_info.data_len()) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    Ok(())
+}
+
 pub fn to_program_error(e: ProgramError) -> ProgramError {
     ProgramError::try_from(e).unwrap_or(ProgramError::InvalidAccountData)
 }
 
 #[inline(always)]
-pub fn get_minimum_delegation() -> u64 {
-    if FEATURE_STAKE_RAISE_MINIMUM_DELEGATION_TO_1_SOL {
+pub fn get_minimum_delegation(features: &FeatureSet) -> u64 {
+    if features.stake_raise_minimum_delegation_to_1_sol {
         const MINIMUM_DELEGATION_SOL: u64 = 1;
         MINIMUM_DELEGATION_SOL * LAMPORTS_PER_SOL
     } else {
@@ -338,6 +349,17 @@ pub fn get_minimum_delegation() -> u64 {
     }
 }
 
+/// With the `strict-authorities` feature, refuses `Pubkey::default()` as a new
+/// authority: nobody can sign for it, so the role would be lost for good, and it
+/// almost always comes from a field the caller forgot to set
+#[inline(always)]
+pub fn check_new_authority(new_authority: &Pubkey) -> ProgramResult {
+    if cfg!(feature = "strict-authorities") && *new_authority == Pubkey::default() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
 pub fn do_authorize(
     stake_account_info: &AccountInfo,
     signers: &[Pubkey],
@@ -346,49 +368,37 @@ pub fn do_authorize(
     custodian: Option<&Pubkey>,
     clock: &Clock,
 ) -> ProgramResult {
-    let mut stake_account: pinocchio::account_info::RefMut<'_, StakeStateV2> =
-        try_get_stake_state_mut(stake_account_info)?;
-    match *stake_account {
-        StakeStateV2::Initialized(mut meta) => {
-            meta.authorized
-                .authorize(
-                    signers,
-                    new_authority,
-                    authority_type,
-                    Some((&meta.lockup, clock, custodian)),
-                )
-                .map_err(to_program_error)?;
-            *stake_account = StakeStateV2::Initialized(meta);
-            Ok(())
+    check_new_authority(new_authority)?;
+
+    let mut stake_state = StakeAccountGuard::new_writable(stake_account_info)?.state_mut()?;
+    match *stake_state {
+        // the authorities are rewritten in place, nothing else of the account is touched
+        StakeStateV2::Initialized(ref mut meta) | StakeStateV2::Stake(ref mut meta, _, _) => meta
+            .authorized
+            .authorize(
+                signers,
+                new_authority,
+                authority_type,
+                Some((&meta.lockup, clock, custodian)),
+            )
+            .map_err(to_program_error),
+        // the account stands as its own authority, but there are no authorities to
+        // rewrite before Initialize. Intended divergence: without its signature this
+        // fails with `MissingRequiredSignature`, where native answers
+        // `InvalidAccountData` either way
+        StakeStateV2::Uninitialized => {
+            check_uninitialized_self_signed(stake_account_info, signers)?;
+            Err(ProgramError::InvalidAccountData)
         }
-        StakeStateV2::Stake(mut meta, stake, stake_flags) => {
-            meta.authorized
-                .authorize(
-                    signers,
-                    new_authority,
-                    authority_type,
-                    Some((&meta.lockup, clock, custodian)),
-                )
-                .map_err(to_program_error)?;
-
-            *stake_account = StakeStateV2::Stake(meta, stake, stake_flags);
-            Ok(())
-        }
-        _ => Err(ProgramError::InvalidAccountData),
+        StakeStateV2::RewardsPool => Err(ProgramError::InvalidAccountData),
     }
 }
 
 // Means that no more than RATE of current effective stake may be added or subtracted per
 // epoch.
 
-pub fn warmup_cooldown_rate(
-    current_epoch: [u8; 8],
-    new_rate_activation_epoch: Option<[u8; 8]>
-) -> f64 {
-    let current = bytes_to_u64(current_epoch);
-    let activation = new_rate_activation_epoch.map(bytes_to_u64).unwrap_or(u64::MAX);
-
-    if current < activation {
+pub fn warmup_cooldown_rate(current_epoch: u64, new_rate_activation_epoch: Option<u64>) -> f64 {
+    if current_epoch < new_rate_activation_epoch.unwrap_or(u64::MAX) {
         DEFAULT_WARMUP_COOLDOWN_RATE
     } else {
         NEW_WARMUP_COOLDOWN_RATE
@@ -412,17 +422,11 @@ pub fn collect_signers_checked<'a>(
     let mut signers_count = 0;
 
     if let Some(authority_info) = authority_info {
-        if !authority_info.is_signer() {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
-        add_signer(&mut signers, &mut signers_count, authority_info.key());
+        add_signer(&mut signers, &mut signers_count, authority_info)?;
     }
 
     let custodian = if let Some(custodian_info) = custodian_info {
-        if !custodian_info.is_signer() {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
-        add_signer(&mut signers, &mut signers_count, &custodian_info.key());
+        add_signer(&mut signers, &mut signers_count, custodian_info)?;
         Some(custodian_info.key())
     } else {
         None
@@ -431,49 +435,205 @@ pub fn collect_signers_checked<'a>(
     Ok((signers, custodian, signers_count))
 }
 
+/// Records the key of `account_info`, which must have signed the transaction, as a
+/// signer
+#[inline]
 pub fn add_signer(
     signers: &mut [Pubkey; MAX_SIGNERS],
     signers_count: &mut usize,
-    account_key: &Pubkey,
+    account_info: &AccountInfo,
 ) -> Result<(), ProgramError> {
+    if !account_info.is_signer() {
+        return Err(missing_required_signature());
+    }
+    add_signer_key(signers, signers_count, account_info.key())
+}
+
+/// Records `key` as a signer unless it already is. Unlike `add_signer` nothing is
+/// checked here: callers pass keys already proven some other way, such as an address
+/// derived from a base account that signed. Signers past `MAX_SIGNERS` could not be
+/// matched against any authority, so they are reported as a missing signature rather
+/// than silently dropped.
+#[inline]
+pub fn add_signer_key(
+    signers: &mut [Pubkey; MAX_SIGNERS],
+    signers_count: &mut usize,
+    key: &Pubkey,
+) -> Result<(), ProgramError> {
+    if contains_pubkey(&signers[..*signers_count], key) {
+        return Ok(());
+    }
     if *signers_count >= MAX_SIGNERS {
-        return Err(ProgramError::MaxAccountsDataAllocationsExceeded);
+        return Err(missing_required_signature());
     }
-    signers[*signers_count] = *account_key;
+    signers[*signers_count] = *key;
     *signers_count += 1;
     Ok(())
 }
 
+/// An `Uninitialized` stake account has no authorities yet and stands as its own:
+/// operating on it requires the signature of the account itself
+pub fn check_uninitialized_self_signed(
+    stake_account_info: &AccountInfo,
+    signers: &[Pubkey],
+) -> ProgramResult {
+    if contains_pubkey(signers, stake_account_info.key()) {
+        Ok(())
+    } else {
+        Err(ProgramError::MissingRequiredSignature)
+    }
+}
+
+/// Lamports of a stake account that neither back stake nor cover its rent-exempt
+/// reserve, i.e. what Withdraw may take out and MoveLamports may move:
+/// * uninitialized: the whole balance
+/// * initialized or inactive: balance - reserve
+/// * delegated: balance - stake - reserve, where a deactivating stake counts only
+///   what is still effective
+pub fn free_lamports<T: StakeHistoryGetEntry>(
+    stake_state: &StakeStateV2,
+    account_lamports: u64,
+    clock: &Clock,
+    stake_history: &T,
+    features: &FeatureSet,
+) -> Result<u64, ProgramError> {
+    let (staked, rent_exempt_reserve) =
+        locked_lamports(stake_state, clock, stake_history, features)?;
+    Ok(account_lamports.saturating_sub(staked).saturating_sub(rent_exempt_reserve))
+}
+
+/// Checks that Withdraw may take `withdraw_lamports` out of a stake account holding
+/// `account_lamports`. Taking the whole balance closes the account and is allowed
+/// once nothing is staked. Anything less must leave the stake plus the reserve
+/// recorded in its `Meta` behind; an uninitialized account records none, so any of
+/// its balance can go.
+pub fn validate_withdraw_amount<T: StakeHistoryGetEntry>(
+    stake_state: &StakeStateV2,
+    account_lamports: u64,
+    withdraw_lamports: u64,
+    clock: &Clock,
+    stake_history: &T,
+    features: &FeatureSet,
+) -> ProgramResult {
+    if withdraw_lamports > account_lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let (staked, rent_exempt_reserve) =
+        locked_lamports(stake_state, clock, stake_history, features)?;
+    let remaining = account_lamports - withdraw_lamports;
+    let required = if remaining == 0 {
+        staked
+    } else {
+        sum_lamports(&[staked, rent_exempt_reserve])?
+    };
+    if remaining < required {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    Ok(())
+}
+
+/// The lamports of a stake account backing stake, and its recorded rent-exempt reserve
+fn locked_lamports<T: StakeHistoryGetEntry>(
+    stake_state: &StakeStateV2,
+    clock: &Clock,
+    stake_history: &T,
+    features: &FeatureSet,
+) -> Result<(u64, u64), ProgramError> {
+    Ok(match stake_state {
+        StakeStateV2::Uninitialized => (0, 0),
+        StakeStateV2::Initialized(meta) => (0, meta.rent_exempt_reserve()),
+        StakeStateV2::Stake(meta, stake, _) => {
+            let staked = match stake_status(stake, clock, stake_history, features) {
+                // deactivated and fully cooled down, or never effective at all
+                StakeStatus::Inactive if clock.epoch >= stake.delegation.deactivation_epoch() => 0,
+                // cooling down: what is still effective stays locked
+                StakeStatus::Deactivating => stake.delegation.stake(
+                    clock.epoch,
+                    stake_history,
+                    features.new_warmup_cooldown_rate_epoch,
+                ),
+                // not deactivated, the whole delegation is locked whatever its activation
+                _ => bytes_to_u64(stake.delegation.stake),
+            };
+            (staked, meta.rent_exempt_reserve())
+        }
+        StakeStateV2::RewardsPool => return Err(ProgramError::InvalidAccountData),
+    })
+}
+
+/// The instruction running `move_stake_or_lamports_shared_checks`, selecting the
+/// account states it additionally requires
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveInstruction {
+    MoveStake,
+    MoveLamports,
+}
+
+impl MoveInstruction {
+    /// Per-instruction preconditions on the merge kinds of both accounts
+    pub fn check_merge_kinds(self, source: &MergeKind, destination: &MergeKind) -> ProgramResult {
+        match self {
+            // only fully active stake can be moved, and not onto stake that is still
+            // activating; active stake only joins a delegation to the same vote account
+            MoveInstruction::MoveStake => match (source, destination) {
+                (
+                    MergeKind::FullyActive(_, source_stake),
+                    MergeKind::FullyActive(_, destination_stake),
+                ) => source_stake
+                    .delegation
+                    .check_voter(&destination_stake.delegation.voter_pubkey),
+                (MergeKind::FullyActive(..), MergeKind::Inactive(..)) => Ok(()),
+                _ => Err(ProgramError::InvalidAccountData),
+            },
+            // lamports may come from an active or inactive account, never an activating one
+            MoveInstruction::MoveLamports => match source {
+                MergeKind::FullyActive(..) | MergeKind::Inactive(..) => Ok(()),
+                MergeKind::ActivationEpoch(..) => Err(ProgramError::InvalidAccountData),
+            },
+        }
+    }
+}
+
+/// Preconditions shared by MoveStake and MoveLamports, followed by the
+/// `instruction`-specific ones. Both accounts come guarded, the account rules of
+/// either instruction having checked that they are writable stake accounts. Returns
+/// the merge kinds of the source and destination accounts.
 pub fn move_stake_or_lamports_shared_checks(
-    source_stake_account_info: &AccountInfo,
-    destination_stake_account_info: &AccountInfo,
+    source_stake_account: &StakeAccountGuard,
+    lamports: u64,
+    destination_stake_account: &StakeAccountGuard,
     stake_authority_info: &AccountInfo,
+    instruction: MoveInstruction,
+    clock: &Clock,
+    features: &FeatureSet,
 ) -> Result<(MergeKind, MergeKind), ProgramError> {
     // authority must sign
     let (signers, _, _) = collect_signers_checked(Some(stake_authority_info), None)?;
 
     // confirm not the same account
-    if *source_stake_account_info.key() == *destination_stake_account_info.key() {
+    if is_same_account(
+        source_stake_account.account_info(),
+        destination_stake_account.account_info(),
+    ) {
         return Err(ProgramError::InvalidInstructionData);
     }
 
-    // source and destination must be writable
-    // runtime guards against unowned writes, but MoveStake and MoveLamports are defined by SIMD
-    // we check explicitly to avoid any possibility of a successful no-op that never attempts to write
-    if !source_stake_account_info.is_writable() || !destination_stake_account_info.is_writable() {
-        return Err(ProgramError::InvalidInstructionData);
+    // nothing to move
+    if lamports == 0 {
+        return Err(ProgramError::InvalidArgument);
     }
 
-    let clock = Clock::get()?;
     let stake_history = StakeHistorySysvar(clock.epoch);
 
     // get_if_mergeable ensures accounts are not partly activated or in any form of deactivating
     // we still need to exclude activating state ourselves
     let source_merge_kind = MergeKind::get_if_mergeable(
-        &*get_stake_state(source_stake_account_info)?,
-        source_stake_account_info.lamports(),
-        &clock,
+        &*source_stake_account.state()?,
+        source_stake_account.lamports(),
+        clock.epoch,
         &stake_history,
+        features,
     )?;
 
     // Authorized staker is allowed to move stake
@@ -485,89 +645,142 @@ pub fn move_stake_or_lamports_shared_checks(
 
     // same transient assurance as with source
     let destination_merge_kind = MergeKind::get_if_mergeable(
-        &*get_stake_state(destination_stake_account_info)?,
-        destination_stake_account_info.lamports(),
-        &clock,
+        &*destination_stake_account.state()?,
+        destination_stake_account.lamports(),
+        clock.epoch,
         &stake_history,
+        features,
     )?;
 
-    // ensure all authorities match and lockups match if lockup is in force
-    MergeKind::metas_can_merge(
-        source_merge_kind.meta(),
-        destination_merge_kind.meta(),
-        &clock,
-    )?;
+    // ensure all authorities match and lockups match if lockup is in force; identical
+    // metas always can, whatever the clock says
+    let (source_meta, destination_meta) = (source_merge_kind.meta(), destination_merge_kind.meta());
+    if source_meta.authorized != destination_meta.authorized
+        || source_meta.lockup != destination_meta.lockup
+    {
+        MergeKind::metas_can_merge(source_meta, destination_meta, clock)?;
+    }
+
+    instruction.check_merge_kinds(&source_merge_kind, &destination_merge_kind)?;
 
     Ok((source_merge_kind, destination_merge_kind))
 }
 
-//from_account_info helper for Clock while not implemente by Pinocchio
-pub fn clock_from_account_info(account_info: &AccountInfo) -> Result<Ref<Clock>, ProgramError> {
-    if account_info.data_len() != core::mem::size_of::<Clock>() {
-        return Err(ProgramError::InvalidAccountData);
+/// Reads the `Clock` sysvar out of a clock account passed to the instruction.
+///
+/// Checks that `account_info` is the sysvar at `address`. Any other key is an
+/// `InvalidArgument`, as with native's sysvar `from_account_info`; the key is only
+/// trusted when the account is also owned by the sysvar program.
+fn expect_sysvar(account_info: &AccountInfo, address: &Pubkey) -> ProgramResult {
+    if account_info.key() != address {
+        return Err(ProgramError::InvalidArgument);
     }
+    if !account_info.is_owned_by(&SYSVAR) {
+        return Err(ProgramError::InvalidAccountOwner);
+    }
+    Ok(())
+}
+
+#[inline(always)]
+pub fn expect_clock(account_info: &AccountInfo) -> ProgramResult {
+    expect_sysvar(account_info, &CLOCK_ID)
+}
+
+#[inline(always)]
+pub fn expect_rent(account_info: &AccountInfo) -> ProgramResult {
+    expect_sysvar(account_info, &RENT_ID)
+}
+
+#[inline(always)]
+pub fn expect_stake_history(account_info: &AccountInfo) -> ProgramResult {
+    expect_sysvar(account_info, &super::stake_history::ID)
+}
 
-    if account_info.key() != &CLOCK_ID {
-        return Err(ProgramError::InvalidAccountData);
+/// The deprecated stake config account is still passed to DelegateStake. Its
+/// contents are never read, only its address is checked.
+#[inline(always)]
+pub fn expect_stake_config(account_info: &AccountInfo) -> ProgramResult {
+    if account_info.key() != &STAKE_CONFIG_ID {
+        return Err(ProgramError::InvalidArgument);
     }
+    Ok(())
+}
 
-    let data = account_info.try_borrow_data()?;
+/// Processors get a `Clock` either from this or from `Clock::get()`; both hand out
+/// an owned `pinocchio` `Clock`, so everything downstream (authorize, merge, lockup
+/// checks) only ever takes `&Clock` and no borrow of the sysvar account is held.
+///
+/// This is the one place a clock account is accepted: its key, its owner (see
+/// `expect_sysvar`) and its length are all checked before it is read.
+pub fn clock_from_account_info(account_info: &AccountInfo) -> Result<Clock, ProgramError> {
+    expect_clock(account_info)?;
 
-    Ok(Ref::map(data, |data| unsafe {
-        &*(data.as_ptr() as *const Clock)
-    }))
+    let data = account_info.try_borrow_data()?;
+    super::zerocopy::read::<Clock>(&data).ok_or(ProgramError::InvalidAccountData)
 }
 
 /// After calling `validate_delegated_amount()`, this struct contains calculated
 /// values that are used by the caller.
 pub(crate) struct ValidatedDelegatedInfo {
-    pub stake_amount: [u8; 8],
+    pub stake_amount: u64,
 }
 
 pub(crate) fn new_stake(
-    stake: [u8; 8],
+    stake: u64,
     voter_pubkey: &Pubkey,
-    vote_state: &VoteState,
-    activation_epoch: [u8; 8]
+    credits: u64,
+    activation_epoch: u64
 ) -> Stake {
-    Stake {
-        delegation: Delegation::new(
-            voter_pubkey,
-            bytes_to_u64(stake),
-            activation_epoch
-        ),
-        credits_observed: vote_state.credits().to_le_bytes(),
-    }
+    Stake::new(
+        Delegation::new(voter_pubkey, stake, activation_epoch),
+        credits,
+    )
+}
+
+/// `new_stake` for callers that build a destination `StakeStateV2::Stake`, e.g.
+/// delegation or MoveStake into an inactive account. The credits are passed in
+/// rather than read from a vote account, and the flags come back alongside the
+/// stake so they are set together.
+pub(crate) fn new_stake_with_flags(
+    stake: u64,
+    voter_pubkey: &Pubkey,
+    credits_observed: u64,
+    activation_epoch: u64,
+    flags: StakeFlags
+) -> (Stake, StakeFlags) {
+    (new_stake(stake, voter_pubkey, credits_observed, activation_epoch), flags)
 }
 
 /// Ensure the stake delegation amount is valid.  This checks that the account
 /// meets the minimum balance requirements of delegated stake.  If not, return
 /// an error.
 pub(crate) fn validate_delegated_amount(
-    account: &AccountInfo,
-    meta: &Meta
+    account_lamports: u64,
+    meta: &Meta,
+    features: &FeatureSet
 ) -> Result<ValidatedDelegatedInfo, ProgramError> {
-    let stake_amount = account.lamports().saturating_sub(bytes_to_u64(meta.rent_exempt_reserve)); // can't stake the rent
+    let stake_amount = account_lamports.saturating_sub(bytes_to_u64(meta.rent_exempt_reserve)); // can't stake the rent
 
     // Stake accounts may be initialized with a stake amount below the minimum
     // delegation so check that the minimum is met before delegation.
-    if stake_amount < get_minimum_delegation() {
+    if stake_amount < get_minimum_delegation(features) {
         return Err(StakeError::InsufficientDelegation.into());
     }
-    Ok(ValidatedDelegatedInfo { stake_amount: stake_amount.to_be_bytes() })
+    Ok(ValidatedDelegatedInfo { stake_amount })
 }
 
 pub(crate) fn redelegate_stake(
     stake: &mut Stake,
-    stake_lamports: [u8; 8],
+    stake_lamports: u64,
     voter_pubkey: &Pubkey,
-    vote_state: &VoteState,
-    epoch: [u8;8],
-    stake_history: &StakeHistorySysvar
+    credits: u64,
+    epoch: u64,
+    stake_history: &StakeHistorySysvar,
+    features: &FeatureSet
 ) -> Result<(), ProgramError> {
     // If stake is currently active:
     if
-        stake.stake(epoch, stake_history, PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH) !=
+        stake.stake(epoch, stake_history, features.new_warmup_cooldown_rate_epoch) !=
         0
     {
         // If pubkey of new voter is the same as current,
@@ -575,9 +788,9 @@ pub(crate) fn redelegate_stake(
         // we rescind deactivation
         if
             stake.delegation.voter_pubkey == *voter_pubkey &&
-            epoch == stake.delegation.deactivation_epoch
+            epoch == stake.delegation.deactivation_epoch()
         {
-            stake.delegation.deactivation_epoch = u64::MAX.to_le_bytes();
+            stake.delegation.set_deactivation_epoch(u64::MAX);
             return Ok(());
         } else {
             // can't redelegate to another pubkey if stake is active.
@@ -588,17 +801,62 @@ pub(crate) fn redelegate_stake(
     // deactivated this epoch, or has fully de-activated.
     // Redelegation implies either re-activation or un-deactivation
 
-    stake.delegation.stake = stake_lamports;
-    stake.delegation.activation_epoch = epoch;
-    stake.delegation.deactivation_epoch = u64::MAX.to_le_bytes();
+    stake.delegation.set_stake(stake_lamports);
+    stake.delegation.set_activation_epoch(epoch);
+    stake.delegation.set_deactivation_epoch(u64::MAX);
     stake.delegation.voter_pubkey = *voter_pubkey;
-    stake.credits_observed = vote_state.credits().to_be_bytes();
+    stake.set_credits_observed(credits);
+    Ok(())
+}
+
+/// Clears `MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED` once the stake it
+/// was set on has no activating portion left at `epoch`, so the flag does not stick
+/// past the activation it was guarding. Returns whether `stake_flags` changed.
+pub(crate) fn clear_flags_if_fully_activated(
+    stake: &Stake,
+    stake_flags: &mut StakeFlags,
+    epoch: u64,
+    stake_history: &StakeHistorySysvar,
+    features: &FeatureSet
+) -> bool {
+    let flag = StakeFlags::MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED;
+    if !stake_flags.contains(flag) {
+        return false;
+    }
+
+    let status = stake.delegation.stake_activating_and_deactivating(
+        epoch,
+        stake_history,
+        features.new_warmup_cooldown_rate_epoch
+    );
+    if bytes_to_u64(status.activating) != 0 {
+        return false;
+    }
+
+    stake_flags.remove(flag);
+    true
+}
+
+/// `clear_flags_if_fully_activated` on the state of `stake_account`, for instructions
+/// which move lamports without otherwise rewriting it. The flags are written in
+/// place, and only when they change.
+pub(crate) fn clear_account_flags_if_fully_activated(
+    stake_account: &StakeAccountGuard,
+    epoch: u64,
+    stake_history: &StakeHistorySysvar,
+    features: &FeatureSet,
+) -> ProgramResult {
+    let mut stake_state = stake_account.state_mut()?;
+    if let StakeStateV2::Stake(_, ref stake, ref mut stake_flags) = *stake_state {
+        clear_flags_if_fully_activated(stake, stake_flags, epoch, stake_history, features);
+    }
     Ok(())
 }
 
 // --- Hash struct and impls ----
 
 #[cfg_attr(feature = "bytemuck", derive(Pod, Zeroable))]
+#[cfg_attr(all(target_arch = "wasm32", feature = "wasm"), wasm_bindgen)]
 #[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[repr(transparent)]
 pub struct Hash(pub(crate) [u8; HASH_BYTES]);
@@ -615,8 +873,9 @@ impl AsRef<[u8]> for Hash {
     }
 }
 
+#[cfg(feature = "base58")]
 fn write_as_base58(f: &mut fmt::Formatter, h: &Hash) -> fmt::Result {
-    let mut out = [0u8; MAX_BASE58_LEN];
+    let mut out = [0u8; crate::consts::MAX_BASE58_LEN];
     let out_slice: &mut [u8] = &mut out;
     // This will never fail because the only possible error is BufferTooSmall,
     // and we will never call it with too small a buffer.
@@ -625,12 +884,22 @@ fn write_as_base58(f: &mut fmt::Formatter, h: &Hash) -> fmt::Result {
     f.write_str(as_str)
 }
 
+#[cfg(feature = "base58")]
 impl fmt::Debug for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write_as_base58(f, self)
     }
 }
 
+// without the encoder, hashes still debug-print, as their raw bytes
+#[cfg(not(feature = "base58"))]
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Hash").field(&self.0).finish()
+    }
+}
+
+#[cfg(feature = "base58")]
 impl fmt::Display for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write_as_base58(f, self)
@@ -643,9 +912,10 @@ pub enum ParseHashError {
     Invalid,
 }
 
-// #[cfg(feature = "std")]
-// impl std::error::Error for ParseHashError {}
+#[cfg(feature = "std")]
+impl std::error::Error for ParseHashError {}
 
+#[cfg(feature = "std")]
 impl fmt::Display for ParseHashError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -655,25 +925,25 @@ impl fmt::Display for ParseHashError {
     }
 }
 
-// requires the solana_sdk::bs58 crate
-// impl FromStr for Hash {
-//     type Err = ParseHashError;
+#[cfg(feature = "base58")]
+impl core::str::FromStr for Hash {
+    type Err = ParseHashError;
 
-//     fn from_str(s: &str) -> Result<Self, Self::Err> {
-//         if s.len() > MAX_BASE58_LEN {
-//             return Err(ParseHashError::WrongSize);
-//         }
-//         let mut bytes = [0; HASH_BYTES];
-//         let decoded_size = bs58::decode(s)
-//             .onto(&mut bytes)
-//             .map_err(|_| ParseHashError::Invalid)?;
-//         if decoded_size != mem::size_of::<Hash>() {
-//             Err(ParseHashError::WrongSize)
-//         } else {
-//             Ok(bytes.into())
-//         }
-//     }
-// }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > crate::consts::MAX_BASE58_LEN {
+            return Err(ParseHashError::WrongSize);
+        }
+        let mut bytes = [0; HASH_BYTES];
+        let decoded_size = bs58::decode(s)
+            .onto(&mut bytes)
+            .map_err(|_| ParseHashError::Invalid)?;
+        if decoded_size != core::mem::size_of::<Hash>() {
+            Err(ParseHashError::WrongSize)
+        } else {
+            Ok(bytes.into())
+        }
+    }
+}
 
 impl Hash {
     #[deprecated(since = "2.2.0", note = "Use 'Hash::new_from_array' instead")]
@@ -701,7 +971,16 @@ impl Hash {
     // }
 }
 
-#[cfg(target_arch = "wasm32")]
+// the JS bindings exist only for wasm builds which ask for them, the on-chain
+// artifact never carries them
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+use {
+    alloc::{boxed::Box, format, string::{String, ToString}, vec},
+    js_sys::{Array, Uint8Array},
+    wasm_bindgen::{prelude::*, JsCast},
+};
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
 #[allow(non_snake_case)]
 #[wasm_bindgen]
 impl Hash {
@@ -756,4 +1035,249 @@ impl Hash {
     pub fn toBytes(&self) -> Box<[u8]> {
         self.0.clone().into()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_signer_key_skips_duplicates() {
+        let mut signers = [Pubkey::default(); MAX_SIGNERS];
+        let mut signers_count = 0;
+
+        // a transaction repeating the same two signer metas many times
+        for i in 0..(MAX_SIGNERS * 4) {
+            let key = [(i % 2) as u8 + 1; 32];
+            add_signer_key(&mut signers, &mut signers_count, &key).unwrap();
+        }
+        assert_eq!(signers_count, 2);
+        assert_eq!(&signers[..signers_count], &[[1; 32], [2; 32]]);
+    }
+
+    #[test]
+    fn test_move_instruction_preconditions() {
+        let meta = Meta::default();
+        let stake = Stake::default();
+        let inactive = MergeKind::Inactive(meta, 0, StakeFlags::empty());
+        let activating = MergeKind::ActivationEpoch(meta, stake, StakeFlags::empty());
+        let active = MergeKind::FullyActive(meta, stake);
+
+        let move_stake = MoveInstruction::MoveStake;
+        assert_eq!(move_stake.check_merge_kinds(&active, &active), Ok(()));
+        assert_eq!(move_stake.check_merge_kinds(&active, &inactive), Ok(()));
+        for (source, destination) in
+            [(&active, &activating), (&inactive, &active), (&activating, &inactive)]
+        {
+            assert_eq!(
+                move_stake.check_merge_kinds(source, destination),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+
+        let other_voter = MergeKind::FullyActive(
+            meta,
+            Stake::new(Delegation::new(&[7; 32], 0, 0), 0),
+        );
+        assert_eq!(
+            move_stake.check_merge_kinds(&active, &other_voter),
+            Err(StakeError::VoteAddressMismatch.into())
+        );
+
+        let move_lamports = MoveInstruction::MoveLamports;
+        assert_eq!(move_lamports.check_merge_kinds(&active, &activating), Ok(()));
+        assert_eq!(move_lamports.check_merge_kinds(&inactive, &active), Ok(()));
+        assert_eq!(
+            move_lamports.check_merge_kinds(&activating, &inactive),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_add_signer_key_cap() {
+        let mut signers = [Pubkey::default(); MAX_SIGNERS];
+        let mut signers_count = 0;
+
+        for i in 0..MAX_SIGNERS {
+            add_signer_key(&mut signers, &mut signers_count, &[i as u8; 32]).unwrap();
+        }
+        assert_eq!(signers_count, MAX_SIGNERS);
+
+        // known signers are still accepted once the cap is reached
+        assert_eq!(add_signer_key(&mut signers, &mut signers_count, &[0; 32]), Ok(()));
+        assert_eq!(
+            add_signer_key(&mut signers, &mut signers_count, &[u8::MAX; 32]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(signers_count, MAX_SIGNERS);
+    }
+
+    #[test]
+    fn test_validate_delegated_amount() {
+        // byte-asymmetric values so an endianness mix-up cannot round-trip
+        let rent_exempt_reserve = 0x0000_0000_0021_ff10u64;
+        let stake_amount = 0x0000_0102_0304_0506u64;
+        let meta = Meta {
+            rent_exempt_reserve: rent_exempt_reserve.to_le_bytes(),
+            ..Meta::default()
+        };
+
+        let ValidatedDelegatedInfo { stake_amount: validated } =
+            validate_delegated_amount(rent_exempt_reserve + stake_amount, &meta, &FeatureSet::BUILT_IN)
+                .unwrap();
+        assert_eq!(validated, stake_amount);
+
+        let stake = new_stake(validated, &[7; 32], 0x0a0b, 0x1122);
+        assert_eq!(stake.delegation.stake, stake_amount.to_le_bytes());
+        assert_eq!(bytes_to_u64(stake.delegation.stake), stake_amount);
+
+        // nothing left to delegate once the reserve is covered
+        assert_eq!(
+            validate_delegated_amount(rent_exempt_reserve, &meta, &FeatureSet::BUILT_IN).err(),
+            Some(StakeError::InsufficientDelegation.into())
+        );
+    }
+
+    #[test]
+    fn test_clear_flags_if_fully_activated() {
+        let flag = StakeFlags::MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED;
+        let epoch = 5u64;
+        let stake_history = StakeHistorySysvar(epoch);
+
+        // still activating, the flag stays
+        let activating = new_stake(1_000, &[1; 32], 0, epoch);
+        let mut stake_flags = flag;
+        assert!(!clear_flags_if_fully_activated(
+            &activating,
+            &mut stake_flags,
+            epoch,
+            &stake_history,
+            &FeatureSet::BUILT_IN
+        ));
+        assert_eq!(stake_flags, flag);
+
+        // bootstrap stake is fully effective without consulting history
+        let active = new_stake(1_000, &[1; 32], 0, u64::MAX);
+        assert!(clear_flags_if_fully_activated(
+            &active,
+            &mut stake_flags,
+            epoch,
+            &stake_history,
+            &FeatureSet::BUILT_IN
+        ));
+        assert_eq!(stake_flags, StakeFlags::empty());
+
+        // nothing to clear
+        assert!(!clear_flags_if_fully_activated(
+            &active,
+            &mut stake_flags,
+            epoch,
+            &stake_history,
+            &FeatureSet::BUILT_IN
+        ));
+    }
+
+    #[test]
+    fn test_free_lamports() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 10,
+            leader_schedule_epoch: 10,
+            unix_timestamp: 0,
+        };
+        let stake_history = crate::state::StakeHistory::default();
+        let meta = Meta { rent_exempt_reserve: 100u64.to_le_bytes(), ..Meta::default() };
+        let mut stake = new_stake(1_000, &[1; 32], 0, 1);
+        let free = |state: &StakeStateV2| {
+            free_lamports(state, 1_500, &clock, &stake_history, &FeatureSet::BUILT_IN)
+        };
+
+        assert_eq!(free(&StakeStateV2::Uninitialized), Ok(1_500));
+        assert_eq!(free(&StakeStateV2::Initialized(meta)), Ok(1_400));
+        assert_eq!(free(&StakeStateV2::Stake(meta, stake, StakeFlags::empty())), Ok(400));
+
+        // not activating before a later epoch, yet locked in full until deactivated
+        let pending = new_stake(1_000, &[1; 32], 0, 20);
+        assert_eq!(free(&StakeStateV2::Stake(meta, pending, StakeFlags::empty())), Ok(400));
+
+        // deactivated with no history left to walk: nothing is still effective
+        stake.delegation.set_deactivation_epoch(5);
+        assert_eq!(free(&StakeStateV2::Stake(meta, stake, StakeFlags::empty())), Ok(1_400));
+
+        assert_eq!(free(&StakeStateV2::RewardsPool), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn test_check_new_authority() {
+        assert_eq!(check_new_authority(&[1; 32]), Ok(()));
+        assert_eq!(
+            check_new_authority(&Pubkey::default()).is_err(),
+            cfg!(feature = "strict-authorities")
+        );
+    }
+
+    #[test]
+    fn test_validate_withdraw_amount() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 10,
+            leader_schedule_epoch: 10,
+            unix_timestamp: 0,
+        };
+        let stake_history = crate::state::StakeHistory::default();
+        let meta = Meta { rent_exempt_reserve: 100u64.to_le_bytes(), ..Meta::default() };
+        let initialized = StakeStateV2::Initialized(meta);
+        let staked =
+            StakeStateV2::Stake(meta, new_stake(1_000, &[1; 32], 0, 1), StakeFlags::empty());
+        let validate = |state: &StakeStateV2, lamports: u64| {
+            validate_withdraw_amount(
+                state,
+                1_500,
+                lamports,
+                &clock,
+                &stake_history,
+                &FeatureSet::BUILT_IN,
+            )
+        };
+        let insufficient = Err(ProgramError::InsufficientFunds);
+
+        assert_eq!(validate(&initialized, 1_400), Ok(()));
+        assert_eq!(validate(&initialized, 1_401), insufficient);
+        // closing the account takes the reserve along
+        assert_eq!(validate(&initialized, 1_500), Ok(()));
+        assert_eq!(validate(&initialized, 1_501), insufficient);
+
+        // no reserve is recorded before Initialize, as natively
+        assert_eq!(validate(&StakeStateV2::Uninitialized, 1_401), Ok(()));
+        assert_eq!(validate(&StakeStateV2::Uninitialized, 1_501), insufficient);
+
+        // active stake can be neither withdrawn nor closed
+        assert_eq!(validate(&staked, 400), Ok(()));
+        assert_eq!(validate(&staked, 401), insufficient);
+        assert_eq!(validate(&staked, 1_500), insufficient);
+    }
+
+    #[test]
+    fn test_stake_rent_exempt_reserve() {
+        let mut rent = Rent {
+            lamports_per_byte_year: DEFAULT_LAMPORTS_PER_BYTE_YEAR,
+            exemption_threshold: DEFAULT_EXEMPTION_THRESHOLD,
+            burn_percent: 50,
+        };
+        assert_eq!(DEFAULT_STAKE_RENT_EXEMPT_RESERVE, 2_282_880);
+        assert_eq!(
+            stake_rent_exempt_reserve(&rent, StakeStateV2::size_of()),
+            rent.minimum_balance(StakeStateV2::size_of())
+        );
+        assert_eq!(stake_rent_exempt_reserve(&rent, 100), rent.minimum_balance(100));
+
+        // other parameters skip the constant
+        rent.lamports_per_byte_year *= 2;
+        assert_eq!(
+            stake_rent_exempt_reserve(&rent, StakeStateV2::size_of()),
+            2 * DEFAULT_STAKE_RENT_EXEMPT_RESERVE
+        );
+    }
+}
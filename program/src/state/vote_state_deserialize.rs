@@ -0,0 +1,342 @@
+//! bincode-compatible decoding of serialized vote accounts into a `VoteState`, for
+//! off-chain users that fetch vote accounts over RPC. Every `VoteStateVersions` layout
+//! is accepted and converted to the current one, as the native `convert_to_current`
+//! does. On-chain code reads single fields through `VoteStateReader` instead.
+
+use core::mem::MaybeUninit;
+
+use pinocchio::{pubkey::Pubkey, sysvars::clock::{Epoch, Slot}};
+
+use crate::{
+    error::InstructionError,
+    state::{
+        AuthorizedVoters,
+        BlockTimestamp,
+        CircBuf,
+        LandedVote,
+        Lockout,
+        MAX_AUTHORIZED_VOTERS,
+        VoteState,
+        VoteStateVersions,
+    },
+};
+
+// see vote_state_0_23_5::MAX_ITEMS
+const V0_23_5_PRIOR_VOTERS_LEN: usize = 32 * 56 + 8;
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], InstructionError> {
+        let end = self.position.checked_add(len).ok_or(InstructionError::InvalidAccountData)?;
+        let bytes = self.data.get(self.position..end).ok_or(InstructionError::InvalidAccountData)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, InstructionError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, InstructionError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, InstructionError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, InstructionError> {
+        Ok(i64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_bool(&mut self) -> Result<bool, InstructionError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(InstructionError::InvalidAccountData),
+        }
+    }
+
+    fn read_pubkey(&mut self) -> Result<Pubkey, InstructionError> {
+        Ok(self.read_bytes(32)?.try_into().unwrap())
+    }
+
+    fn read_option_u64(&mut self) -> Result<Option<u64>, InstructionError> {
+        if self.read_bool()? {
+            self.read_u64().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// bincode encodes collection lengths as `u64`
+    fn read_len(&mut self) -> Result<usize, InstructionError> {
+        usize::try_from(self.read_u64()?).map_err(|_| InstructionError::InvalidAccountData)
+    }
+
+    fn read_votes(&mut self, vote_state: &mut VoteState, landed: bool) -> Result<(), InstructionError> {
+        for _ in 0..self.read_len()? {
+            let latency = if landed { self.read_u8()? } else { 0 };
+            let slot: Slot = self.read_u64()?;
+            let confirmation_count = self.read_u32()?;
+            let lockout = Lockout::new_with_confirmation_count(slot, confirmation_count);
+            // the vote program never keeps more than MAX_LOCKOUT_HISTORY votes
+            vote_state
+                .votes
+                .push(LandedVote { latency, lockout })
+                .map_err(|_| InstructionError::InvalidAccountData)?;
+        }
+        Ok(())
+    }
+
+    fn read_authorized_voters(&mut self) -> Result<AuthorizedVoters, InstructionError> {
+        let len = self.read_len()?;
+        if len > MAX_AUTHORIZED_VOTERS {
+            return Err(InstructionError::InvalidAccountData);
+        }
+
+        let mut authorized_voters = AuthorizedVoters::default();
+        for _ in 0..len {
+            let epoch: Epoch = self.read_u64()?;
+            let voter = self.read_pubkey()?;
+            authorized_voters.insert(epoch, voter);
+        }
+        Ok(authorized_voters)
+    }
+
+    fn read_prior_voters(&mut self) -> Result<CircBuf<(Pubkey, Epoch, Epoch)>, InstructionError> {
+        let mut buf = [(Pubkey::default(), 0, 0); 32];
+        let mut encountered_null_voter = false;
+        for item in buf.iter_mut() {
+            *item = (self.read_pubkey()?, self.read_u64()?, self.read_u64()?);
+            if *item == (Pubkey::default(), 0, 0) {
+                encountered_null_voter = true;
+            } else if encountered_null_voter {
+                // `prior_voters` should never be sparse
+                return Err(InstructionError::InvalidAccountData);
+            }
+        }
+        let idx = self.read_len()?;
+        let is_empty = self.read_bool()?;
+
+        if is_empty {
+            // nothing was ever appended, whatever the buffer holds is ignored
+            return Ok(CircBuf::default());
+        }
+        CircBuf::try_from_parts(buf, idx, is_empty).map_err(|_| InstructionError::InvalidAccountData)
+    }
+
+    fn read_epoch_credits(&mut self, vote_state: &mut VoteState) -> Result<(), InstructionError> {
+        for _ in 0..self.read_len()? {
+            let entry = (self.read_u64()?, self.read_u64()?, self.read_u64()?);
+            vote_state
+                .epoch_credits
+                .push(entry)
+                .map_err(|_| InstructionError::InvalidAccountData)?;
+        }
+        Ok(())
+    }
+
+    fn read_last_timestamp(&mut self) -> Result<BlockTimestamp, InstructionError> {
+        Ok(BlockTimestamp {
+            slot: self.read_u64()?,
+            timestamp: self.read_i64()?,
+        })
+    }
+}
+
+impl VoteState {
+    /// Decodes any `VoteStateVersions` encoding into the current `VoteState`.
+    ///
+    /// Unlike bincode, histories longer than the vote program ever writes
+    /// (`MAX_LOCKOUT_HISTORY` votes, `MAX_EPOCH_CREDITS_HISTORY` epoch credits,
+    /// `MAX_AUTHORIZED_VOTERS` voters) are rejected since they do not fit the
+    /// fixed-capacity fields.
+    pub fn deserialize(input: &[u8]) -> Result<Self, InstructionError> {
+        let version =
+            VoteStateVersions::from_data(input).map_err(|_| InstructionError::InvalidAccountData)?;
+        let mut cursor = Cursor { data: input, position: 4 };
+        let mut vote_state = VoteState::default();
+
+        match version {
+            VoteStateVersions::V0_23_5 => {
+                vote_state.node_pubkey = cursor.read_pubkey()?;
+                let authorized_voter = cursor.read_pubkey()?;
+                let authorized_voter_epoch = cursor.read_u64()?;
+                // prior voters of this layout are dropped by the conversion
+                cursor.read_bytes(V0_23_5_PRIOR_VOTERS_LEN)?;
+                vote_state.authorized_withdrawer = cursor.read_pubkey()?;
+                vote_state.commission = cursor.read_u8()?;
+                cursor.read_votes(&mut vote_state, false)?;
+                vote_state.root_slot = cursor.read_option_u64()?;
+                vote_state.authorized_voters =
+                    AuthorizedVoters::new(authorized_voter_epoch, authorized_voter);
+            }
+            VoteStateVersions::V1_14_11 | VoteStateVersions::V3 => {
+                vote_state.node_pubkey = cursor.read_pubkey()?;
+                vote_state.authorized_withdrawer = cursor.read_pubkey()?;
+                vote_state.commission = cursor.read_u8()?;
+                cursor.read_votes(&mut vote_state, version == VoteStateVersions::V3)?;
+                vote_state.root_slot = cursor.read_option_u64()?;
+                vote_state.authorized_voters = cursor.read_authorized_voters()?;
+                vote_state.prior_voters = cursor.read_prior_voters()?;
+            }
+        }
+
+        cursor.read_epoch_credits(&mut vote_state)?;
+        vote_state.last_timestamp = cursor.read_last_timestamp()?;
+
+        Ok(vote_state)
+    }
+
+    /// Decodes `input` into `vote_state`, which is reset to the default on error.
+    pub fn deserialize_into(
+        input: &[u8],
+        vote_state: &mut VoteState,
+    ) -> Result<(), InstructionError> {
+        match Self::deserialize(input) {
+            Ok(decoded) => {
+                *vote_state = decoded;
+                Ok(())
+            }
+            Err(err) => {
+                *vote_state = VoteState::default();
+                Err(err)
+            }
+        }
+    }
+
+    /// Decodes `input` into uninitialized memory. `vote_state` is only initialized
+    /// when `Ok` is returned.
+    pub fn deserialize_into_uninit(
+        input: &[u8],
+        vote_state: &mut MaybeUninit<VoteState>,
+    ) -> Result<(), InstructionError> {
+        vote_state.write(Self::deserialize(input)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        clock::Clock,
+        vote::state::{
+            vote_state_1_14_11::VoteState1_14_11,
+            VoteInit,
+            VoteState as NativeVoteState,
+            VoteStateVersions as NativeVoteStateVersions,
+        },
+    };
+
+    fn new_native_vote_state() -> NativeVoteState {
+        let vote_init = VoteInit {
+            node_pubkey: solana_sdk::pubkey::Pubkey::new_unique(),
+            authorized_voter: solana_sdk::pubkey::Pubkey::new_unique(),
+            authorized_withdrawer: solana_sdk::pubkey::Pubkey::new_unique(),
+            commission: 7,
+        };
+        let mut vote_state = NativeVoteState::new(&vote_init, &Clock::default());
+        for slot in 0..40 {
+            vote_state.process_next_vote_slot(slot, slot / 8, slot);
+        }
+        vote_state.increment_credits(6, 42);
+        vote_state.last_timestamp.slot = 39;
+        vote_state.last_timestamp.timestamp = 1_700_000_000;
+        vote_state
+    }
+
+    fn assert_matches_native(vote_state: &VoteState, native: &NativeVoteState, landed: bool) {
+        assert_eq!(vote_state.node_pubkey, native.node_pubkey.to_bytes());
+        assert_eq!(vote_state.authorized_withdrawer, native.authorized_withdrawer.to_bytes());
+        assert_eq!(vote_state.commission, native.commission);
+        assert_eq!(vote_state.votes.len(), native.votes.len());
+        for (vote, native_vote) in vote_state.votes.iter().zip(native.votes.iter()) {
+            assert_eq!(vote.slot(), native_vote.slot());
+            assert_eq!(vote.confirmation_count(), native_vote.confirmation_count());
+            if landed {
+                assert_eq!(vote.latency, native_vote.latency);
+            }
+        }
+        assert_eq!(vote_state.root_slot, native.root_slot);
+        assert_eq!(
+            vote_state.get_authorized_voter(0),
+            native.get_authorized_voter(0).map(|voter| voter.to_bytes())
+        );
+        assert_eq!(vote_state.epoch_credits(), native.epoch_credits().as_slice());
+        assert_eq!(vote_state.last_timestamp.slot, native.last_timestamp.slot);
+        assert_eq!(vote_state.last_timestamp.timestamp, native.last_timestamp.timestamp);
+    }
+
+    #[test]
+    fn test_vote_deserialize_into() {
+        let native = new_native_vote_state();
+        let data = bincode::serialize(&NativeVoteStateVersions::new_current(native.clone())).unwrap();
+
+        let mut vote_state = VoteState::default();
+        VoteState::deserialize_into(&data, &mut vote_state).unwrap();
+        assert_matches_native(&vote_state, &native, true);
+
+        let versioned = NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(native.clone())));
+        let data = bincode::serialize(&versioned).unwrap();
+        VoteState::deserialize_into(&data, &mut vote_state).unwrap();
+        assert_matches_native(&vote_state, &native, false);
+    }
+
+    #[test]
+    fn test_vote_deserialize_into_error() {
+        let native = new_native_vote_state();
+        let mut data =
+            bincode::serialize(&NativeVoteStateVersions::new_current(native)).unwrap();
+        data.truncate(data.len() - 1);
+
+        let mut vote_state = VoteState::default();
+        vote_state.commission = 1;
+        VoteState::deserialize_into(&data, &mut vote_state).unwrap_err();
+        assert_eq!(vote_state, VoteState::default());
+    }
+
+    #[test]
+    fn test_vote_deserialize_rejects_prior_voters_idx() {
+        let mut native = new_native_vote_state();
+        // moves the initial voter into prior_voters
+        native
+            .set_new_authorized_voter(&solana_sdk::pubkey::Pubkey::new_unique(), 0, 1, |_| Ok(()))
+            .unwrap();
+        let mut data =
+            bincode::serialize(&NativeVoteStateVersions::new_current(native.clone())).unwrap();
+        assert!(VoteState::deserialize(&data).is_ok());
+
+        // idx sits right after the 32 prior voter entries
+        let votes_end = 4 + 32 + 32 + 1 + 8 + native.votes.len() * 13;
+        let root_end = votes_end + 1 + if native.root_slot.is_some() { 8 } else { 0 };
+        let idx_offset = root_end + 8 + native.authorized_voters().len() * 40 + 32 * 48;
+        data[idx_offset..idx_offset + 8].copy_from_slice(&(32u64).to_le_bytes());
+        assert_eq!(
+            VoteState::deserialize(&data),
+            Err(InstructionError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_vote_deserialize_into_uninit() {
+        let native = new_native_vote_state();
+        let data = bincode::serialize(&NativeVoteStateVersions::new_current(native.clone())).unwrap();
+
+        let mut vote_state = MaybeUninit::uninit();
+        VoteState::deserialize_into_uninit(&data, &mut vote_state).unwrap();
+        let vote_state = unsafe { vote_state.assume_init() };
+        assert_matches_native(&vote_state, &native, true);
+
+        let mut vote_state = MaybeUninit::uninit();
+        assert_eq!(
+            VoteState::deserialize_into_uninit(&[], &mut vote_state),
+            Err(InstructionError::InvalidAccountData)
+        );
+    }
+}
@@ -0,0 +1,660 @@
+use pinocchio::{
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::clock::{Epoch, Slot, UnixTimestamp},
+};
+
+use crate::{
+    consts::MAX_LOCKOUT_HISTORY,
+    state::{BlockTimestamp, LandedVote, Lockout},
+};
+
+/// Serialized `Lockout`: `slot: u64` and `confirmation_count: u32`
+const LOCKOUT_LEN: usize = 12;
+/// Serialized `LandedVote`: latency byte followed by a `Lockout`
+const LANDED_VOTE_LEN: usize = 1 + LOCKOUT_LEN;
+/// `(Epoch, Pubkey)` entry of the `AuthorizedVoters` map
+const AUTHORIZED_VOTER_LEN: usize = 40;
+/// `CircBuf<(Pubkey, Epoch, Epoch)>`: 32 entries, `idx: u64` and `is_empty: bool`
+const PRIOR_VOTERS_LEN: usize = 32 * 48 + 8 + 1;
+/// `(Epoch, credits, prev_credits)`
+const EPOCH_CREDITS_ENTRY_LEN: usize = 24;
+
+/// Every layout starts with the node pubkey, right after the version tag
+const NODE_PUBKEY_OFFSET: usize = 4;
+/// version tag, node_pubkey
+const AUTHORIZED_WITHDRAWER_OFFSET: usize = 4 + 32;
+/// version tag, node_pubkey, authorized_withdrawer
+const COMMISSION_OFFSET: usize = 4 + 32 + 32;
+/// version tag, node_pubkey, authorized_withdrawer, commission
+const VOTES_OFFSET: usize = 4 + 32 + 32 + 1;
+
+/// 0.23.5 layout: version tag, node_pubkey, authorized_voter, authorized_voter_epoch
+const V0_23_5_AUTHORIZED_VOTER_OFFSET: usize = 4 + 32;
+/// `CircBuf<(Pubkey, Epoch, Epoch, Slot)>` without the `is_empty` flag
+const V0_23_5_PRIOR_VOTERS_LEN: usize = 32 * 56 + 8;
+/// 0.23.5 layout: authorized_withdrawer follows prior_voters
+const V0_23_5_AUTHORIZED_WITHDRAWER_OFFSET: usize = 4 + 32 + 32 + 8 + V0_23_5_PRIOR_VOTERS_LEN;
+const V0_23_5_COMMISSION_OFFSET: usize = V0_23_5_AUTHORIZED_WITHDRAWER_OFFSET + 32;
+const V0_23_5_VOTES_OFFSET: usize = V0_23_5_COMMISSION_OFFSET + 1;
+
+/// Version tag of serialized vote account data, mirroring the variants of the native
+/// `VoteStateVersions`
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoteStateVersions {
+    V0_23_5 = 0,
+    V1_14_11 = 1,
+    V3 = 2,
+}
+
+impl VoteStateVersions {
+    /// Dispatches on the leading `u32` tag of the account data
+    pub fn from_data(data: &[u8]) -> Result<Self, ProgramError> {
+        let tag = data.get(..4).ok_or(ProgramError::InvalidAccountData)?;
+        match u32::from_le_bytes(tag.try_into().unwrap()) {
+            0 => Ok(Self::V0_23_5),
+            1 => Ok(Self::V1_14_11),
+            2 => Ok(Self::V3),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Size vote accounts of this version are allocated with, see native
+    /// `VoteStateVersions::vote_state_size_of`. Layouts older than V3 share the
+    /// pre-1.14.11 account size.
+    pub const fn account_size(self) -> usize {
+        match self {
+            Self::V0_23_5 | Self::V1_14_11 => 3731,
+            Self::V3 => 3762,
+        }
+    }
+
+    /// Offset of the `votes` length prefix and the serialized length of each vote
+    #[inline(always)]
+    const fn votes_layout(self) -> (usize, usize) {
+        match self {
+            Self::V0_23_5 => (V0_23_5_VOTES_OFFSET, LOCKOUT_LEN),
+            Self::V1_14_11 => (VOTES_OFFSET, LOCKOUT_LEN),
+            Self::V3 => (VOTES_OFFSET, LANDED_VOTE_LEN),
+        }
+    }
+}
+
+/// Reads fields straight out of serialized vote account data, without building a
+/// `VoteState` and the heap collections it carries. Every `VoteStateVersions` layout
+/// is supported.
+pub struct VoteStateReader<'a> {
+    data: &'a [u8],
+    version: VoteStateVersions,
+}
+
+impl<'a> VoteStateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ProgramError> {
+        let version = VoteStateVersions::from_data(data)?;
+        Ok(Self { data, version })
+    }
+
+    #[inline(always)]
+    pub fn version(&self) -> VoteStateVersions {
+        self.version
+    }
+
+    #[inline(always)]
+    fn read_pubkey(&self, offset: usize) -> Result<&'a Pubkey, ProgramError> {
+        self.data
+            .get(offset..offset + 32)
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    #[inline(always)]
+    fn read_u64(&self, offset: usize) -> Result<u64, ProgramError> {
+        let bytes = offset
+            .checked_add(8)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Offset of the `root_slot` option, right after the `votes` deque
+    fn root_slot_offset(&self) -> Result<usize, ProgramError> {
+        let (votes_offset, vote_len) = self.version.votes_layout();
+        let votes_len = self.read_u64(votes_offset)? as usize;
+        votes_len
+            .checked_mul(vote_len)
+            .and_then(|len| len.checked_add(votes_offset + 8))
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    /// Offset of the `authorized_voters` length prefix (not present in 0.23.5)
+    fn authorized_voters_offset(&self) -> Result<usize, ProgramError> {
+        let offset = self.root_slot_offset()?;
+        // root_slot: Option<Slot>
+        match self.data.get(offset) {
+            Some(0) => Ok(offset + 1),
+            Some(1) => Ok(offset + 1 + 8),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Skips the variable-length fields preceding `epoch_credits` and returns the
+    /// offset of its length prefix.
+    fn epoch_credits_offset(&self) -> Result<usize, ProgramError> {
+        let offset = self.authorized_voters_offset()?;
+        if self.version == VoteStateVersions::V0_23_5 {
+            return Ok(offset);
+        }
+
+        let authorized_voters_len = self.read_u64(offset)? as usize;
+        authorized_voters_len
+            .checked_mul(AUTHORIZED_VOTER_LEN)
+            .and_then(|len| len.checked_add(offset + 8 + PRIOR_VOTERS_LEN))
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    /// The node that votes in this account
+    pub fn node_pubkey(&self) -> Result<&'a Pubkey, ProgramError> {
+        self.read_pubkey(NODE_PUBKEY_OFFSET)
+    }
+
+    /// The signer for withdrawals
+    pub fn authorized_withdrawer(&self) -> Result<&'a Pubkey, ProgramError> {
+        match self.version {
+            VoteStateVersions::V0_23_5 => self.read_pubkey(V0_23_5_AUTHORIZED_WITHDRAWER_OFFSET),
+            _ => self.read_pubkey(AUTHORIZED_WITHDRAWER_OFFSET),
+        }
+    }
+
+    /// Percentage (0-100) of rewards paid to the validator
+    pub fn commission(&self) -> Result<u8, ProgramError> {
+        let offset = match self.version {
+            VoteStateVersions::V0_23_5 => V0_23_5_COMMISSION_OFFSET,
+            _ => COMMISSION_OFFSET,
+        };
+        self.data.get(offset).copied().ok_or(ProgramError::InvalidAccountData)
+    }
+
+    /// Native `is_uninitialized`: no authorized voter has been set
+    pub fn is_uninitialized(&self) -> Result<bool, ProgramError> {
+        match self.version {
+            VoteStateVersions::V0_23_5 => {
+                let authorized_voter = self
+                    .data
+                    .get(V0_23_5_AUTHORIZED_VOTER_OFFSET..V0_23_5_AUTHORIZED_VOTER_OFFSET + 32)
+                    .ok_or(ProgramError::InvalidAccountData)?;
+                Ok(authorized_voter == [0u8; 32])
+            }
+            _ => Ok(self.read_u64(self.authorized_voters_offset()?)? == 0),
+        }
+    }
+
+    /// Number of `epoch_credits` entries
+    pub fn epoch_credits_len(&self) -> Result<usize, ProgramError> {
+        let offset = self.epoch_credits_offset()?;
+        Ok(self.read_u64(offset)? as usize)
+    }
+
+    /// Every `epoch_credits` entry, oldest first
+    pub fn epoch_credits(&self) -> Result<EpochCreditsIter<'a>, ProgramError> {
+        let offset = self.epoch_credits_offset()?;
+        let len = self.read_u64(offset)? as usize;
+        let entries = len
+            .checked_mul(EPOCH_CREDITS_ENTRY_LEN)
+            .and_then(|entries_len| entries_len.checked_add(offset + 8))
+            .and_then(|end| self.data.get(offset + 8..end))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        Ok(EpochCreditsIter { entries })
+    }
+
+    /// The last `n` (or fewer, if the history is shorter) `(epoch, credits, prev_credits)`
+    /// entries, oldest first
+    pub fn last_epoch_credits(&self, n: usize) -> Result<EpochCreditsIter<'a>, ProgramError> {
+        let mut iter = self.epoch_credits()?;
+        let skip = iter.len().saturating_sub(n) * EPOCH_CREDITS_ENTRY_LEN;
+        iter.entries = &iter.entries[skip..];
+        Ok(iter)
+    }
+
+    /// The tower, oldest vote first. Legacy layouts store bare `Lockout`s, which are
+    /// reported with a latency of 0 as the native conversion does.
+    ///
+    /// Vote accounts are bincode encoded, so the deque carries a `u64` length prefix
+    /// (the short_vec prefix only appears in compact vote instructions). The declared
+    /// length is checked against `MAX_LOCKOUT_HISTORY` and the account data.
+    pub fn votes(&self) -> Result<VotesIter<'a>, ProgramError> {
+        let (votes_offset, vote_len) = self.version.votes_layout();
+        let votes_len = self.read_u64(votes_offset)?;
+        if votes_len > MAX_LOCKOUT_HISTORY as u64 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let start = votes_offset + 8;
+        let entries = self
+            .data
+            .get(start..start + votes_len as usize * vote_len)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        Ok(VotesIter { entries, vote_len })
+    }
+
+    /// Slot of the most recent vote in the tower, `None` if there are no votes
+    pub fn last_voted_slot(&self) -> Result<Option<Slot>, ProgramError> {
+        let (votes_offset, vote_len) = self.version.votes_layout();
+        let votes_len = self.read_u64(votes_offset)? as usize;
+        if votes_len == 0 {
+            return Ok(None);
+        }
+
+        // a `LandedVote` starts with its latency byte, the slot follows
+        let slot_in_vote = vote_len - LOCKOUT_LEN;
+        let last_slot = (votes_len - 1)
+            .checked_mul(vote_len)
+            .and_then(|vote| vote.checked_add(votes_offset + 8 + slot_in_vote))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        self.read_u64(last_slot).map(Some)
+    }
+
+    /// Most recent timestamp submitted with a vote, the last field of every layout
+    pub fn last_timestamp(&self) -> Result<BlockTimestamp, ProgramError> {
+        let offset = self.epoch_credits_offset()?;
+        let len = self.read_u64(offset)? as usize;
+        let timestamp_offset = len
+            .checked_mul(EPOCH_CREDITS_ENTRY_LEN)
+            .and_then(|entries_len| entries_len.checked_add(offset + 8))
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        Ok(BlockTimestamp {
+            slot: self.read_u64(timestamp_offset)?,
+            timestamp: self.read_u64(timestamp_offset + 8)? as UnixTimestamp,
+        })
+    }
+
+    /// Total credits earned by the end of `epoch`: the credits of the latest entry not
+    /// after `epoch`, or the starting balance of the history if `epoch` predates it.
+    /// For the current epoch this is the same value as `credits`.
+    pub fn credits_at_epoch(&self, epoch: Epoch) -> Result<u64, ProgramError> {
+        let mut credits_at_epoch = None;
+        let mut first_prev_credits = 0;
+        for (index, (entry_epoch, credits, prev_credits)) in self.epoch_credits()?.enumerate() {
+            if index == 0 {
+                first_prev_credits = prev_credits;
+            }
+            if entry_epoch > epoch {
+                break;
+            }
+            credits_at_epoch = Some(credits);
+        }
+        Ok(credits_at_epoch.unwrap_or(first_prev_credits))
+    }
+
+    /// `VoteState::credits`: the credits of the last `epoch_credits` entry, reading only
+    /// the vec length and that entry.
+    pub fn credits(&self) -> Result<u64, ProgramError> {
+        let offset = self.epoch_credits_offset()?;
+        let len = self.read_u64(offset)? as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        // past the length prefix and the epoch of the last entry
+        let last_credits = (len - 1)
+            .checked_mul(EPOCH_CREDITS_ENTRY_LEN)
+            .and_then(|entry| entry.checked_add(offset + 8 + 8))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        self.read_u64(last_credits)
+    }
+}
+
+/// Iterator over serialized `(Epoch, credits, prev_credits)` entries
+#[derive(Clone)]
+pub struct EpochCreditsIter<'a> {
+    entries: &'a [u8],
+}
+
+impl Iterator for EpochCreditsIter<'_> {
+    type Item = (Epoch, u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.len() < EPOCH_CREDITS_ENTRY_LEN {
+            return None;
+        }
+        let (entry, rest) = self.entries.split_at(EPOCH_CREDITS_ENTRY_LEN);
+        self.entries = rest;
+
+        let read = |i: usize| u64::from_le_bytes(entry[i * 8..(i + 1) * 8].try_into().unwrap());
+        Some((read(0), read(1), read(2)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.entries.len() / EPOCH_CREDITS_ENTRY_LEN;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for EpochCreditsIter<'_> {}
+
+/// Iterator over the serialized votes of a vote account
+#[derive(Clone)]
+pub struct VotesIter<'a> {
+    entries: &'a [u8],
+    vote_len: usize,
+}
+
+impl Iterator for VotesIter<'_> {
+    type Item = LandedVote;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.len() < self.vote_len {
+            return None;
+        }
+        let (vote, rest) = self.entries.split_at(self.vote_len);
+        self.entries = rest;
+
+        // a `LandedVote` starts with its latency byte
+        let (latency, lockout) = match self.vote_len {
+            LANDED_VOTE_LEN => (vote[0], &vote[1..]),
+            _ => (0, vote),
+        };
+        let slot = u64::from_le_bytes(lockout[..8].try_into().unwrap());
+        let confirmation_count = u32::from_le_bytes(lockout[8..12].try_into().unwrap());
+        Some(LandedVote {
+            latency,
+            lockout: Lockout::new_with_confirmation_count(slot, confirmation_count),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.entries.len() / self.vote_len;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for VotesIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        clock::Clock,
+        pubkey::Pubkey,
+        vote::state::{
+            vote_state_1_14_11::VoteState1_14_11,
+            VoteInit,
+            VoteState as NativeVoteState,
+            VoteStateVersions as NativeVoteStateVersions,
+        },
+    };
+
+    fn serialize(vote_state: NativeVoteState) -> Vec<u8> {
+        let mut data = vec![0; NativeVoteState::size_of()];
+        NativeVoteState::serialize(&NativeVoteStateVersions::new_current(vote_state), &mut data)
+            .unwrap();
+        data
+    }
+
+    fn new_vote_state() -> NativeVoteState {
+        let vote_init = VoteInit {
+            node_pubkey: Pubkey::new_unique(),
+            authorized_voter: Pubkey::new_unique(),
+            authorized_withdrawer: Pubkey::new_unique(),
+            commission: 5,
+        };
+        NativeVoteState::new(&vote_init, &Clock::default())
+    }
+
+    #[test]
+    fn test_credits_empty() {
+        let data = serialize(new_vote_state());
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.epoch_credits_len().unwrap(), 0);
+        assert_eq!(reader.credits().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_credits_matches_native() {
+        let mut vote_state = new_vote_state();
+        for slot in 0..40 {
+            vote_state.process_next_vote_slot(slot, slot / 8, 0);
+        }
+        for epoch in 5..9 {
+            vote_state.increment_credits(epoch, epoch * 100);
+        }
+        let expected_len = vote_state.epoch_credits().len();
+        let expected = vote_state.credits();
+        let data = serialize(vote_state);
+
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.epoch_credits_len().unwrap(), expected_len);
+        assert_eq!(reader.credits().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_last_epoch_credits() {
+        let mut vote_state = new_vote_state();
+        for epoch in 0..10 {
+            vote_state.increment_credits(epoch, epoch + 1);
+        }
+        let expected = vote_state.epoch_credits().clone();
+        let data = serialize(vote_state);
+        let reader = VoteStateReader::new(&data).unwrap();
+
+        assert!(reader.epoch_credits().unwrap().eq(expected.iter().copied()));
+        assert!(reader
+            .last_epoch_credits(3)
+            .unwrap()
+            .eq(expected[expected.len() - 3..].iter().copied()));
+        assert!(reader.last_epoch_credits(100).unwrap().eq(expected.iter().copied()));
+        assert_eq!(reader.last_epoch_credits(0).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_last_voted_slot_and_timestamp() {
+        let mut vote_state = new_vote_state();
+        let data = serialize(vote_state.clone());
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.last_voted_slot().unwrap(), None);
+        assert_eq!(reader.last_timestamp().unwrap(), BlockTimestamp::default());
+
+        for slot in 0..40 {
+            vote_state.process_next_vote_slot(slot, slot / 8, 0);
+        }
+        vote_state.increment_credits(5, 10);
+        vote_state.last_timestamp.slot = 39;
+        vote_state.last_timestamp.timestamp = 1_700_000_000;
+        let data = serialize(vote_state.clone());
+        let reader = VoteStateReader::new(&data).unwrap();
+
+        assert_eq!(reader.last_voted_slot().unwrap(), vote_state.last_voted_slot());
+        assert_eq!(
+            reader.last_timestamp().unwrap(),
+            BlockTimestamp { slot: 39, timestamp: 1_700_000_000 }
+        );
+
+        let versioned =
+            NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(vote_state.clone())));
+        let data = bincode::serialize(&versioned).unwrap();
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.last_voted_slot().unwrap(), vote_state.last_voted_slot());
+        assert_eq!(reader.last_timestamp().unwrap().slot, 39);
+    }
+
+    #[test]
+    fn test_credits_at_epoch() {
+        let mut vote_state = new_vote_state();
+        let data = serialize(vote_state.clone());
+        assert_eq!(VoteStateReader::new(&data).unwrap().credits_at_epoch(3).unwrap(), 0);
+
+        vote_state.increment_credits(4, 10);
+        vote_state.increment_credits(5, 5);
+        vote_state.increment_credits(8, 7);
+        // (4, 10, 0), (5, 15, 10), (8, 22, 15)
+        let data = serialize(vote_state.clone());
+        let reader = VoteStateReader::new(&data).unwrap();
+
+        assert_eq!(reader.credits_at_epoch(3).unwrap(), 0);
+        assert_eq!(reader.credits_at_epoch(4).unwrap(), 10);
+        assert_eq!(reader.credits_at_epoch(5).unwrap(), 15);
+        assert_eq!(reader.credits_at_epoch(7).unwrap(), 15);
+        assert_eq!(reader.credits_at_epoch(8).unwrap(), 22);
+        assert_eq!(reader.credits_at_epoch(100).unwrap(), vote_state.credits());
+    }
+
+    #[test]
+    fn test_votes() {
+        let mut vote_state = new_vote_state();
+        for slot in 0..40 {
+            vote_state.process_next_vote_slot(slot, slot / 8, slot + 1);
+        }
+        let data = serialize(vote_state.clone());
+        let reader = VoteStateReader::new(&data).unwrap();
+        let votes = reader.votes().unwrap();
+        assert_eq!(votes.len(), vote_state.votes.len());
+        for (vote, native_vote) in votes.zip(vote_state.votes.iter()) {
+            assert_eq!(vote.latency, native_vote.latency);
+            assert_eq!(vote.slot(), native_vote.slot());
+            assert_eq!(vote.confirmation_count(), native_vote.confirmation_count());
+        }
+
+        let versioned =
+            NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(vote_state.clone())));
+        let data = bincode::serialize(&versioned).unwrap();
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert!(reader
+            .votes()
+            .unwrap()
+            .map(|vote| (vote.latency, vote.slot()))
+            .eq(vote_state.votes.iter().map(|vote| (0, vote.slot()))));
+
+        // declared length beyond the tower bound, or beyond the data
+        let mut data = serialize(vote_state);
+        data[VOTES_OFFSET..VOTES_OFFSET + 8]
+            .copy_from_slice(&(MAX_LOCKOUT_HISTORY as u64 + 1).to_le_bytes());
+        assert_eq!(
+            VoteStateReader::new(&data).unwrap().votes().err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+        data[VOTES_OFFSET..VOTES_OFFSET + 8].copy_from_slice(&2u64.to_le_bytes());
+        assert_eq!(
+            VoteStateReader::new(&data[..VOTES_OFFSET + 8 + LANDED_VOTE_LEN]).unwrap().votes().err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_credits_rejects_bad_lengths() {
+        let mut data = serialize(new_vote_state());
+        data[VOTES_OFFSET..VOTES_OFFSET + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            VoteStateReader::new(&data).unwrap().credits(),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            VoteStateReader::new(&data[..VOTES_OFFSET]).unwrap().credits(),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            VoteStateReader::new(&[3, 0, 0, 0]).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_credits_v1_14_11() {
+        let mut vote_state = new_vote_state();
+        for slot in 0..40 {
+            vote_state.process_next_vote_slot(slot, slot / 8, 0);
+        }
+        vote_state.increment_credits(9, 700);
+        let expected = vote_state.credits();
+
+        let versioned = NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(vote_state)));
+        let data = bincode::serialize(&versioned).unwrap();
+
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.version(), VoteStateVersions::V1_14_11);
+        assert!(!reader.is_uninitialized().unwrap());
+        assert_eq!(reader.credits().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_identity_and_commission() {
+        let vote_state = new_vote_state();
+        let data = serialize(vote_state.clone());
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.node_pubkey().unwrap(), &vote_state.node_pubkey.to_bytes());
+        assert_eq!(
+            reader.authorized_withdrawer().unwrap(),
+            &vote_state.authorized_withdrawer.to_bytes()
+        );
+        assert_eq!(reader.commission().unwrap(), 5);
+
+        let versioned =
+            NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(vote_state.clone())));
+        let data = bincode::serialize(&versioned).unwrap();
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(
+            reader.authorized_withdrawer().unwrap(),
+            &vote_state.authorized_withdrawer.to_bytes()
+        );
+        assert_eq!(reader.commission().unwrap(), 5);
+
+        let mut data = [0u8; 3731];
+        data[V0_23_5_AUTHORIZED_WITHDRAWER_OFFSET..V0_23_5_COMMISSION_OFFSET].fill(9);
+        data[V0_23_5_COMMISSION_OFFSET] = 10;
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.authorized_withdrawer().unwrap(), &[9; 32]);
+        assert_eq!(reader.commission().unwrap(), 10);
+        assert_eq!(
+            VoteStateReader::new(&data[..40]).unwrap().commission(),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_check_account_data_sizes() {
+        use crate::state::VoteState;
+
+        let vote_state = new_vote_state();
+        let current = serialize(vote_state.clone());
+        assert_eq!(current.len(), VoteStateVersions::V3.account_size());
+        assert_eq!(VoteState::check_account_data(&current), Ok(()));
+
+        let versioned =
+            NativeVoteStateVersions::V1_14_11(Box::new(VoteState1_14_11::from(vote_state)));
+        let mut legacy = bincode::serialize(&versioned).unwrap();
+        legacy.resize(VoteStateVersions::V1_14_11.account_size(), 0);
+        assert_eq!(VoteState::check_account_data(&legacy), Ok(()));
+
+        // a V1_14_11 body in a V3-sized account, and the other way around
+        let mut legacy_in_current = legacy.clone();
+        legacy_in_current.resize(VoteStateVersions::V3.account_size(), 0);
+        assert_eq!(
+            VoteState::check_account_data(&legacy_in_current),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            VoteState::check_account_data(&current[..legacy.len()]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // right size, never initialized
+        assert_eq!(
+            VoteState::check_account_data(&[0; 3731]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_uninitialized() {
+        let data = serialize(NativeVoteState::default());
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.version(), VoteStateVersions::V3);
+        assert!(reader.is_uninitialized().unwrap());
+
+        let data = [0u8; 3731];
+        let reader = VoteStateReader::new(&data).unwrap();
+        assert_eq!(reader.version(), VoteStateVersions::V0_23_5);
+        assert!(reader.is_uninitialized().unwrap());
+    }
+}
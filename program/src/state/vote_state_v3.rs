@@ -1,12 +1,19 @@
 use pinocchio::{
-    account_info::{ AccountInfo, Ref },
     program_error::ProgramError,
     pubkey::Pubkey,
     sysvars::{ clock::{ Clock, Epoch, Slot, UnixTimestamp }, rent::Rent },
 };
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
-use crate::{consts::{INITIAL_LOCKOUT, MAX_LOCKOUT_HISTORY}, state::Hash};
+use crate::{
+    consts::{
+        DEFAULT_PRIOR_VOTERS_OFFSET,
+        INITIAL_LOCKOUT,
+        MAX_EPOCH_CREDITS_HISTORY,
+        MAX_LOCKOUT_HISTORY,
+    },
+    state::{Hash, VoteStateReader, VoteStateVersions},
+};
 
 // available in /solana-vote-interface-2.2.4/src/state/vote_state_v3.rs
 #[repr(C)]
@@ -21,7 +28,7 @@ pub struct VoteState {
     ///  payout should be given to this VoteAccount
     pub commission: u8,
 
-    pub votes: VecDeque<LandedVote>,
+    pub votes: BoundedVec<LandedVote, MAX_LOCKOUT_HISTORY>,
 
     // This usually the last Lockout which was popped from self.votes.
     // However, it can be arbitrary slot, when being used inside Tower
@@ -37,7 +44,7 @@ pub struct VoteState {
 
     /// history of how many credits earned by the end of each epoch
     ///  each tuple is (Epoch, credits, prev_credits)
-    pub epoch_credits: Vec<(Epoch, u64, u64)>,
+    pub epoch_credits: BoundedVec<(Epoch, u64, u64), MAX_EPOCH_CREDITS_HISTORY>,
 
     /// most recent timestamp submitted with a vote
     pub last_timestamp: BlockTimestamp,
@@ -76,18 +83,50 @@ impl VoteState {
         3762 // see test_vote_state_size_of.
     }
 
-    #[inline]
-    pub fn from_account_info(account_info: &AccountInfo) -> Result<Ref<VoteState>, ProgramError> {
-        if account_info.data_len() != Self::size_of() {
+    /// Same check as native `VoteStateVersions::is_correct_size_and_initialized`
+    pub fn is_correct_size_and_initialized(data: &[u8]) -> bool {
+        const VERSION_OFFSET: usize = 4;
+        const DEFAULT_PRIOR_VOTERS_END: usize = VERSION_OFFSET + DEFAULT_PRIOR_VOTERS_OFFSET;
+        data.len() == Self::size_of()
+            && data[VERSION_OFFSET..DEFAULT_PRIOR_VOTERS_END] != [0; DEFAULT_PRIOR_VOTERS_OFFSET]
+    }
+
+    /// Checks the version tag, size and initialization of serialized vote account data,
+    /// accepting every `VoteStateVersions` layout at its own account size
+    pub fn check_account_data(data: &[u8]) -> Result<(), ProgramError> {
+        let version = VoteStateVersions::from_data(data)?;
+        if data.len() != version.account_size() {
             return Err(ProgramError::InvalidAccountData);
         }
-        let data = account_info.try_borrow_data()?;
-        Ok(Ref::map(data, |data| unsafe { Self::from_bytes(data) }))
+
+        let initialized = match version {
+            VoteStateVersions::V3 => Self::is_correct_size_and_initialized(data),
+            _ => !VoteStateReader::new(data)?.is_uninitialized()?,
+        };
+        if !initialized {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
     }
 
-    #[inline(always)]
-    pub unsafe fn from_bytes(bytes: &[u8]) -> &Self {
-        &*(bytes.as_ptr() as *const Self)
+    // The serialized layout has length-prefixed collections and no padding, so the
+    // account data is never cast to a `VoteState`; on-chain callers go through
+    // `VoteStateReader` instead.
+
+    pub fn epoch_credits(&self) -> &[(Epoch, u64, u64)] {
+        self.epoch_credits.as_slice()
+    }
+
+    /// The last `n` (or fewer) `(epoch, credits, prev_credits)` entries, oldest first
+    pub fn last_epoch_credits(&self, n: usize) -> &[(Epoch, u64, u64)] {
+        let epoch_credits = self.epoch_credits.as_slice();
+        &epoch_credits[epoch_credits.len().saturating_sub(n)..]
+    }
+
+    /// Splits `on` between the validator and the stakers according to the commission,
+    /// see [`commission_split`]
+    pub fn commission_split(&self, on: u64) -> (u64, u64, bool) {
+        commission_split(self.commission, on)
     }
 
     /// Number of "credits" owed to this account from the mining pool. Submit this
@@ -101,6 +140,28 @@ impl VoteState {
     }
 }
 
+/// Returns `(voter_portion, staker_portion, was_split)` of `on` for the given commission,
+/// with the same rounding as the runtime: both portions round down, so a split can
+/// lose up to one lamport. Commissions above 100 are treated as 100.
+pub fn commission_split(commission: u8, on: u64) -> (u64, u64, bool) {
+    match commission.min(100) {
+        0 => (0, on, false),
+        100 => (on, 0, false),
+        split => {
+            let on = u128::from(on);
+            // Calculate mine and theirs independently and symmetrically instead of
+            // using the remainder of the other to treat them strictly equally.
+            // This is also to cancel the rewarding if either of the parties
+            // should receive only fractional lamports, resulting in not being rewarded at all.
+            // Thus, note that we intentionally discard any residual fractional lamports.
+            let mine = on * u128::from(split) / 100u128;
+            let theirs = on * u128::from(100 - split) / 100u128;
+
+            (mine as u64, theirs as u64, true)
+        }
+    }
+}
+
 // -------------solana-vote-interface/src/state/mod.rs------------------
 /// Vote state
 
@@ -425,7 +486,135 @@ impl<I: Default + Copy> Default for CircBuf<I> {
     }
 }
 
+/// Fixed-capacity stand-in for the `VecDeque`/`Vec` fields of the native vote state,
+/// whose lengths the vote program bounds (`MAX_LOCKOUT_HISTORY` votes and
+/// `MAX_EPOCH_CREDITS_HISTORY` epoch credits).
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BoundedVec<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Default + Copy, const N: usize> Default for BoundedVec<T, N> {
+    fn default() -> Self {
+        Self {
+            items: [T::default(); N],
+            len: 0,
+        }
+    }
+}
+
+impl<T: Default + Copy, const N: usize> BoundedVec<T, N> {
+    pub const CAPACITY: usize = N;
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    #[inline(always)]
+    pub fn as_slice(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+
+    #[inline(always)]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.as_slice().first()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.as_slice().last()
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut T> {
+        self.as_mut_slice().last_mut()
+    }
+
+    /// Appends `item`, handing it back if the capacity is exhausted
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(item);
+        }
+        self.items[self.len] = item;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends `item`, dropping the oldest entry first when full. This is what the
+    /// vote program does once a history reaches its bound.
+    pub fn push_evicting(&mut self, item: T) -> Option<T> {
+        let evicted = if self.is_full() { self.pop_front() } else { None };
+        // there is room now
+        let _ = self.push(item);
+        evicted
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let front = self.items[0];
+        self.items.copy_within(1..self.len, 0);
+        self.len -= 1;
+        self.items[self.len] = T::default();
+        Some(front)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        Some(core::mem::take(&mut self.items[self.len]))
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
 impl<I> CircBuf<I> {
+    /// Rebuilds a buffer from deserialized parts. `idx` is only ever used modulo
+    /// `MAX_ITEMS` by `append`, but `last` and any direct indexing rely on it being in
+    /// range, so out-of-range values from hostile account data are rejected.
+    pub fn try_from_parts(
+        buf: [I; MAX_ITEMS],
+        idx: usize,
+        is_empty: bool
+    ) -> Result<Self, ProgramError> {
+        if idx >= MAX_ITEMS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self { buf, idx, is_empty })
+    }
+
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
     pub fn append(&mut self, item: I) {
         // remember prior delegate and when we switched, to support later slashing
         self.idx = self.idx
@@ -626,6 +815,79 @@ impl<I> CircBuf<I> {
 
 // Tests -------------------------------------------------------
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vote_state_commission_split() {
+        let vote_state = VoteState::default();
+
+        assert_eq!(vote_state.commission_split(1), (0, 1, false));
+
+        let mut vote_state = VoteState {
+            commission: u8::MAX,
+            ..VoteState::default()
+        };
+        assert_eq!(vote_state.commission_split(1), (1, 0, false));
+
+        vote_state.commission = 99;
+        assert_eq!(vote_state.commission_split(10), (9, 0, true));
+
+        vote_state.commission = 1;
+        assert_eq!(vote_state.commission_split(10), (0, 9, true));
+
+        vote_state.commission = 50;
+        let (voter_portion, staker_portion, was_split) = vote_state.commission_split(10);
+
+        assert_eq!((voter_portion, staker_portion, was_split), (5, 5, true));
+
+        assert_eq!(commission_split(50, u64::MAX), (u64::MAX / 2, u64::MAX / 2, true));
+    }
+
+    #[test]
+    fn test_circbuf_try_from_parts() {
+        let buf = [0u64; MAX_ITEMS];
+        assert!(CircBuf::try_from_parts(buf, MAX_ITEMS - 1, true).is_ok());
+        assert_eq!(
+            CircBuf::try_from_parts(buf, MAX_ITEMS, false),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            CircBuf::try_from_parts(buf, usize::MAX, false),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let mut circ_buf = CircBuf::try_from_parts(buf, 3, false).unwrap();
+        circ_buf.append(7);
+        assert_eq!(circ_buf.idx(), 4);
+        assert_eq!(circ_buf.last(), Some(&7));
+    }
+
+    #[test]
+    fn test_bounded_vec() {
+        let mut epoch_credits = BoundedVec::<(Epoch, u64, u64), 3>::default();
+        assert!(epoch_credits.is_empty());
+        assert_eq!(epoch_credits.last(), None);
+
+        for epoch in 0..3 {
+            epoch_credits.push((epoch, epoch, 0)).unwrap();
+        }
+        assert!(epoch_credits.is_full());
+        assert_eq!(epoch_credits.push((3, 3, 0)), Err((3, 3, 0)));
+
+        assert_eq!(epoch_credits.push_evicting((3, 3, 0)), Some((0, 0, 0)));
+        assert_eq!(epoch_credits.as_slice(), &[(1, 1, 0), (2, 2, 0), (3, 3, 0)]);
+
+        assert_eq!(epoch_credits.pop(), Some((3, 3, 0)));
+        assert_eq!(epoch_credits.pop_front(), Some((1, 1, 0)));
+        assert_eq!(epoch_credits.as_slice(), &[(2, 2, 0)]);
+
+        epoch_credits.clear();
+        assert_eq!(epoch_credits, BoundedVec::default());
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use ::{
@@ -814,31 +1076,6 @@ impl<I> CircBuf<I> {
 //         }
 //     }
 
-//     #[test]
-//     #[allow(deprecated)]
-//     fn test_vote_state_commission_split() {
-//         let vote_state = VoteState::default();
-
-//         assert_eq!(vote_state.commission_split(1), (0, 1, false));
-
-//         let mut vote_state = VoteState {
-//             commission: u8::MAX,
-//             ..VoteState::default()
-//         };
-//         assert_eq!(vote_state.commission_split(1), (1, 0, false));
-
-//         vote_state.commission = 99;
-//         assert_eq!(vote_state.commission_split(10), (9, 0, true));
-
-//         vote_state.commission = 1;
-//         assert_eq!(vote_state.commission_split(10), (0, 9, true));
-
-//         vote_state.commission = 50;
-//         let (voter_portion, staker_portion, was_split) = vote_state.commission_split(10);
-
-//         assert_eq!((voter_portion, staker_portion, was_split), (5, 5, true));
-//     }
-
 //     #[test]
 //     fn test_vote_state_epoch_credits() {
 //         let mut vote_state = VoteState::default();
@@ -0,0 +1,300 @@
+//! The one place where bytes are reinterpreted as typed values. Everything else in
+//! the crate reads and writes account and instruction data through the wrappers
+//! here, so the invariants below are the only ones to audit:
+//!
+//! * a `&T` is only produced from bytes which are exactly `size_of::<T>()` long and
+//!   aligned for `T` (the runtime aligns account data to 8 bytes, test and wasm
+//!   buffers are not);
+//! * for `StakeStateV2` the 4-byte tag must name a variant; every payload field is a
+//!   byte array, so no other bit pattern can be invalid;
+//! * values are only viewed as bytes when they have no padding (see [`Pod`]), so no
+//!   uninitialized byte is ever read.
+
+use pinocchio::{program_error::ProgramError, sysvars::clock::Clock};
+
+use super::{
+    utils::{DataLen, Initialized},
+    Authorized, Delegation, Lockup, Meta, Stake, StakeFlags, StakeStateV2,
+};
+
+/// Plain data: `Copy`, no padding, and valid for every bit pattern.
+///
+/// # Safety
+///
+/// Implementors must be `repr(C)` (or a primitive) with no padding bytes, and every
+/// combination of field bytes must be a valid value. The stake state layouts are
+/// pinned by the compile-time checks in `layout.rs`.
+pub unsafe trait Pod: Copy {}
+
+unsafe impl Pod for Authorized {}
+unsafe impl Pod for Lockup {}
+unsafe impl Pod for Meta {}
+unsafe impl Pod for Delegation {}
+unsafe impl Pod for Stake {}
+unsafe impl Pod for StakeFlags {}
+// five 8-byte integers
+unsafe impl Pod for Clock {}
+
+/// Whether `bytes` starts at an address suitably aligned for a `T`. The runtime
+/// aligns account data to 8 bytes, but slices handed in by tests, wasm callers or
+/// instruction data carry no such guarantee.
+#[inline(always)]
+pub fn is_aligned_for<T>(bytes: &[u8]) -> bool {
+    (bytes.as_ptr() as usize) % core::mem::align_of::<T>() == 0
+}
+
+#[inline(always)]
+pub fn bytes_of<T: Pod>(value: &T) -> &[u8] {
+    // SAFETY: `Pod` types have no padding, so all `size_of::<T>()` bytes are initialized
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+/// Views `bytes` as a `T`, if it is exactly one suitably aligned `T`
+#[inline(always)]
+pub fn from_bytes<T: Pod>(bytes: &[u8]) -> Option<&T> {
+    if bytes.len() != core::mem::size_of::<T>() || !is_aligned_for::<T>(bytes) {
+        return None;
+    }
+    // SAFETY: length and alignment are checked above, `Pod` accepts any bit pattern
+    Some(unsafe { &*(bytes.as_ptr() as *const T) })
+}
+
+/// Copies a `T` out of `bytes`, whatever their alignment
+#[inline(always)]
+pub fn read<T: Pod>(bytes: &[u8]) -> Option<T> {
+    if bytes.len() != core::mem::size_of::<T>() {
+        return None;
+    }
+    // SAFETY: the length is checked above, `Pod` accepts any bit pattern
+    Some(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// Writes the bytes of `value` to the start of `out`
+#[inline(always)]
+pub fn write<T: Pod>(out: &mut [u8], value: &T) -> Result<(), ProgramError> {
+    out.get_mut(..core::mem::size_of::<T>())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(bytes_of(value));
+    Ok(())
+}
+
+/// Checks that `data` can be viewed as a `StakeStateV2` in place
+#[inline(always)]
+fn check_stake_state(data: &[u8]) -> Result<(), ProgramError> {
+    StakeStateV2::check_data_len(data.len())?;
+    if !is_aligned_for::<StakeStateV2>(data) || !StakeStateV2::has_valid_discriminant(data) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+/// Views account data as a `StakeStateV2` in place
+#[inline(always)]
+pub fn stake_state(data: &[u8]) -> Result<&StakeStateV2, ProgramError> {
+    check_stake_state(data)?;
+    // SAFETY: length, alignment and tag are checked above, the payload fields are
+    // byte arrays valid for any bit pattern
+    Ok(unsafe { &*(data.as_ptr() as *const StakeStateV2) })
+}
+
+/// Views account data as a mutable `StakeStateV2` in place
+#[inline(always)]
+pub fn stake_state_mut(data: &mut [u8]) -> Result<&mut StakeStateV2, ProgramError> {
+    check_stake_state(data)?;
+    // SAFETY: as for `stake_state`; writes through the reference keep the tag valid
+    Ok(unsafe { &mut *(data.as_mut_ptr() as *mut StakeStateV2) })
+}
+
+/// Copies a `StakeStateV2` out of `data`, whatever its alignment
+#[inline(always)]
+pub fn read_stake_state(data: &[u8]) -> Result<StakeStateV2, ProgramError> {
+    StakeStateV2::check_data_len(data.len())?;
+    if !StakeStateV2::has_valid_discriminant(data) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // SAFETY: as for `stake_state`, without the alignment requirement
+    Ok(unsafe { core::ptr::read_unaligned(data.as_ptr() as *const StakeStateV2) })
+}
+
+/// Serializes `state` into account data the way bincode does: the tag, then the
+/// variant's fields. Bytes past the variant's payload are left untouched, and the
+/// enum's padding is never read, except that deinitializing zeroes the whole payload:
+/// stale authorities and lockups must not survive for indexers to misread or for a
+/// later re-initialization to pick up.
+pub fn write_stake_state(data: &mut [u8], state: &StakeStateV2) -> Result<(), ProgramError> {
+    StakeStateV2::check_data_len(data.len())?;
+
+    let discriminant = match state {
+        StakeStateV2::Uninitialized => {
+            data[StakeStateV2::META_OFFSET..].fill(0);
+            StakeStateV2::UNINITIALIZED_DISCRIMINANT
+        }
+        StakeStateV2::Initialized(meta) => {
+            write(&mut data[StakeStateV2::META_OFFSET..], meta)?;
+            StakeStateV2::INITIALIZED_DISCRIMINANT
+        }
+        StakeStateV2::Stake(meta, stake, stake_flags) => {
+            write(&mut data[StakeStateV2::META_OFFSET..], meta)?;
+            write(&mut data[StakeStateV2::STAKE_OFFSET..], stake)?;
+            write(&mut data[StakeStateV2::STAKE_FLAGS_OFFSET..], stake_flags)?;
+            StakeStateV2::STAKE_DISCRIMINANT
+        }
+        StakeStateV2::RewardsPool => StakeStateV2::REWARDS_POOL_DISCRIMINANT,
+    };
+    data[..StakeStateV2::DISCRIMINANT_LEN].copy_from_slice(&discriminant.to_le_bytes());
+    Ok(())
+}
+
+/// Raw casts for the generic `DataLen` helpers in `utils`, whose types are not
+/// necessarily `Pod`.
+///
+/// # Safety
+///
+/// `bytes` must be aligned for `T`, at least `size_of::<T>()` long and hold a valid `T`.
+#[inline(always)]
+pub unsafe fn cast_unchecked<T>(bytes: &[u8]) -> &T {
+    &*(bytes.as_ptr() as *const T)
+}
+
+/// # Safety
+///
+/// As for [`cast_unchecked`].
+#[inline(always)]
+pub unsafe fn cast_mut_unchecked<T>(bytes: &mut [u8]) -> &mut T {
+    &mut *(bytes.as_mut_ptr() as *mut T)
+}
+
+// The `DataLen` loaders below take any `T`, so they stay `unsafe`: the caller
+// vouches that every bit pattern of the checked length is a valid `T`.
+
+#[inline(always)]
+pub unsafe fn load_acc<T: DataLen + Initialized>(bytes: &[u8]) -> Result<&T, ProgramError> {
+    load_acc_unchecked::<T>(bytes).and_then(|acc| {
+        if acc.is_initialized() { Ok(acc) } else { Err(ProgramError::UninitializedAccount) }
+    })
+}
+
+#[inline(always)]
+pub unsafe fn load_acc_unchecked<T: DataLen>(bytes: &[u8]) -> Result<&T, ProgramError> {
+    if bytes.len() != T::LEN || !is_aligned_for::<T>(bytes) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(cast_unchecked(bytes))
+}
+
+#[inline(always)]
+pub unsafe fn load_acc_mut<T: DataLen + Initialized>(
+    bytes: &mut [u8]
+) -> Result<&mut T, ProgramError> {
+    load_acc_mut_unchecked::<T>(bytes).and_then(|acc| {
+        if acc.is_initialized() { Ok(acc) } else { Err(ProgramError::UninitializedAccount) }
+    })
+}
+
+#[inline(always)]
+pub unsafe fn load_acc_mut_unchecked<T: DataLen>(bytes: &mut [u8]) -> Result<&mut T, ProgramError> {
+    if bytes.len() != T::LEN || !is_aligned_for::<T>(bytes) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(cast_mut_unchecked(bytes))
+}
+
+#[inline(always)]
+pub unsafe fn load_ix_data<T: DataLen>(bytes: &[u8]) -> Result<&T, ProgramError> {
+    if bytes.len() != T::LEN || !is_aligned_for::<T>(bytes) {
+        return Err(ProgramError::InvalidInstructionData.into());
+    }
+    Ok(cast_unchecked(bytes))
+}
+
+/// # Safety
+///
+/// `T` must have no padding within its first `T::LEN` bytes.
+pub unsafe fn to_bytes<T: DataLen>(data: &T) -> &[u8] {
+    core::slice::from_raw_parts(data as *const T as *const u8, T::LEN)
+}
+
+/// # Safety
+///
+/// As for [`to_bytes`], and any bytes written must leave a valid `T`.
+pub unsafe fn to_mut_bytes<T: DataLen>(data: &mut T) -> &mut [u8] {
+    core::slice::from_raw_parts_mut(data as *mut T as *mut u8, T::LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift, enough to spray bytes without another dependency
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_stake_state_fuzz() {
+        let mut seed = 0x5eed_u64;
+        // 8-byte aligned backing store, sliced at every offset for misaligned views
+        let mut backing = [0u64; 27];
+
+        for _ in 0..10_000 {
+            for word in backing.iter_mut() {
+                *word = next(&mut seed);
+            }
+            // bias towards valid tags so the payload paths are exercised too
+            let tag = next(&mut seed) % 5;
+            let offset = (next(&mut seed) % 8) as usize;
+
+            let bytes = unsafe {
+                core::slice::from_raw_parts_mut(backing.as_mut_ptr() as *mut u8, 27 * 8)
+            };
+            let data = &mut bytes[offset..offset + StakeStateV2::size_of()];
+            data[..4].copy_from_slice(&(tag as u32).to_le_bytes());
+
+            let read = read_stake_state(data);
+            assert_eq!(read.is_ok(), tag <= 3);
+            match stake_state(data) {
+                Ok(state) => assert_eq!(Ok(*state), read),
+                Err(_) => assert!(offset % 4 != 0 || tag > 3),
+            }
+
+            // writing back what was read yields the same state
+            if let Ok(state) = read {
+                let mut out = [0u8; 200];
+                write_stake_state(&mut out, &state).unwrap();
+                assert_eq!(read_stake_state(&out), Ok(state));
+            }
+        }
+    }
+
+    #[test]
+    fn test_deinitialize_zeroes_payload() {
+        let mut meta = Meta::default();
+        meta.authorized.staker = [1; 32];
+        meta.lockup.custodian = [2; 32];
+        let mut data = [0xffu8; 200];
+        write_stake_state(
+            &mut data,
+            &StakeStateV2::Stake(meta, Stake::default(), StakeFlags::empty()),
+        )
+        .unwrap();
+
+        write_stake_state(&mut data, &StakeStateV2::Uninitialized).unwrap();
+        assert_eq!(data, [0u8; 200]);
+    }
+
+    #[test]
+    fn test_pod_round_trip() {
+        let mut meta = Meta::default();
+        meta.set_rent_exempt_reserve(42);
+        meta.lockup.set_epoch(7);
+
+        let bytes = bytes_of(&meta);
+        assert_eq!(bytes.len(), core::mem::size_of::<Meta>());
+        assert_eq!(read::<Meta>(bytes), Some(meta));
+        assert_eq!(from_bytes::<Meta>(bytes), Some(&meta));
+        assert_eq!(read::<Meta>(&bytes[1..]), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Account requirements which hold for every valid invocation of an instruction,
+//! checked once from the entrypoint before the processor runs. Processors still
+//! perform the checks which depend on account state (authorities, lockups, ...).
+
+use pinocchio::{
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
+};
+
+use crate::{
+    consts::{CLOCK_ID, RENT_ID, STAKE_CONFIG_ID, VOTE_PROGRAM_ID},
+    instruction::StakeInstruction,
+    state::{is_same_account, stake_history, StakeAccountGuard, StakeStateV2},
+};
+
+/// Keys a stake account can never have: the sysvars and accounts instructions take
+/// alongside it, and the vote program. Such a key in a stake position means the
+/// accounts were assembled in the wrong order.
+const RESERVED_KEYS: [Pubkey; 5] =
+    [CLOCK_ID, RENT_ID, stake_history::ID, STAKE_CONFIG_ID, VOTE_PROGRAM_ID];
+
+/// What an instruction requires of the account at one position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountRule {
+    pub writable: bool,
+    pub signer: bool,
+    /// owned by this program and exactly `StakeStateV2` sized
+    pub stake_account: bool,
+    /// a vote account, which must not be one of the instruction's stake accounts
+    pub vote_account: bool,
+}
+
+impl AccountRule {
+    /// No requirement beyond the account being present
+    pub const ANY: Self = Self {
+        writable: false,
+        signer: false,
+        stake_account: false,
+        vote_account: false,
+    };
+    pub const SIGNER: Self = Self {
+        signer: true,
+        ..Self::ANY
+    };
+    pub const WRITABLE: Self = Self {
+        writable: true,
+        ..Self::ANY
+    };
+    /// A stake account the instruction writes to
+    pub const STAKE: Self = Self {
+        writable: true,
+        stake_account: true,
+        ..Self::ANY
+    };
+    pub const VOTE: Self = Self {
+        vote_account: true,
+        ..Self::ANY
+    };
+
+    fn check(&self, account_info: &AccountInfo) -> ProgramResult {
+        if self.stake_account {
+            if !account_info.is_owned_by(&crate::ID) {
+                return Err(ProgramError::InvalidAccountOwner);
+            }
+            StakeStateV2::check_data_len(account_info.data_len())?;
+            if RESERVED_KEYS.contains(account_info.key()) {
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        if self.writable && !account_info.is_writable() {
+            return Err(ProgramError::Immutable);
+        }
+        if self.signer && !account_info.is_signer() {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(())
+    }
+}
+
+use AccountRule as R;
+
+/// The leading accounts of `instruction`, as the native stake program lists them.
+/// Optional trailing accounts (custodians, stake authorities located through the
+/// signer set) are left to the processors.
+pub fn account_rules(instruction: &StakeInstruction) -> &'static [AccountRule] {
+    match instruction {
+        // stake, rent
+        StakeInstruction::Initialize => &[R::STAKE, R::ANY],
+        // stake, clock, authority
+        StakeInstruction::Authorize => &[R::STAKE, R::ANY, R::SIGNER],
+        // stake, vote, clock, stake history, stake config
+        StakeInstruction::DelegateStake => &[R::STAKE, R::VOTE, R::ANY, R::ANY, R::ANY],
+        // source, destination
+        StakeInstruction::Split => &[R::STAKE, R::STAKE],
+        // stake, recipient, clock, stake history, withdraw authority
+        StakeInstruction::Withdraw => &[R::STAKE, R::WRITABLE, R::ANY, R::ANY, R::SIGNER],
+        // stake, clock
+        StakeInstruction::Deactivate => &[R::STAKE, R::ANY],
+        StakeInstruction::SetLockup | StakeInstruction::SetLockupChecked => &[R::STAKE],
+        // destination, source, clock, stake history
+        StakeInstruction::Merge => &[R::STAKE, R::STAKE, R::ANY, R::ANY],
+        // stake, base, clock
+        StakeInstruction::AuthorizeWithSeed => &[R::STAKE, R::SIGNER, R::ANY],
+        // stake, rent, staker, withdrawer
+        StakeInstruction::InitializeChecked => &[R::STAKE, R::ANY, R::ANY, R::SIGNER],
+        // stake, clock, old authority, new authority
+        StakeInstruction::AuthorizeChecked => &[R::STAKE, R::ANY, R::ANY, R::SIGNER],
+        // stake, base, clock, new authority
+        StakeInstruction::AuthorizeCheckedWithSeed => &[R::STAKE, R::SIGNER, R::ANY, R::SIGNER],
+        StakeInstruction::GetMinimumDelegation => &[],
+        // stake, delinquent vote, reference vote
+        StakeInstruction::DeactivateDelinquent => &[R::STAKE, R::VOTE, R::VOTE],
+        #[allow(deprecated)]
+        StakeInstruction::Redelegate => &[],
+        // source, destination, stake authority
+        StakeInstruction::MoveStake | StakeInstruction::MoveLamports => {
+            &[R::STAKE, R::STAKE, R::SIGNER]
+        }
+    }
+}
+
+/// The most accounts any instruction uses, the largest of `max_accounts`
+pub const MAX_INSTRUCTION_ACCOUNTS: usize = {
+    let mut max = 0;
+    let mut discriminant = 0;
+    while let Some(instruction) = StakeInstruction::from_discriminant(discriminant) {
+        let accounts = max_accounts(&instruction);
+        if accounts > max {
+            max = accounts;
+        }
+        discriminant += 1;
+    }
+    max
+};
+
+/// How many accounts `instruction` can use at most, optional trailing accounts
+/// included. The native program ignores anything past these; with the
+/// `strict-accounts` feature they are rejected instead. The entrypoint only
+/// deserializes up to `MAX_INSTRUCTION_ACCOUNTS`.
+pub const fn max_accounts(instruction: &StakeInstruction) -> usize {
+    match instruction {
+        // stake, rent
+        StakeInstruction::Initialize => 2,
+        // stake, clock, authority, custodian
+        StakeInstruction::Authorize => 4,
+        // stake, vote, clock, stake history, stake config, stake authority
+        StakeInstruction::DelegateStake => 6,
+        // source, destination, stake authority
+        StakeInstruction::Split => 3,
+        // stake, recipient, clock, stake history, withdraw authority, custodian
+        StakeInstruction::Withdraw => 6,
+        // stake, clock, stake authority
+        StakeInstruction::Deactivate => 3,
+        // stake, lockup or withdraw authority
+        StakeInstruction::SetLockup => 2,
+        // destination, source, clock, stake history, stake authority
+        StakeInstruction::Merge => 5,
+        // stake, base, clock, custodian
+        StakeInstruction::AuthorizeWithSeed => 4,
+        // stake, rent, staker, withdrawer
+        StakeInstruction::InitializeChecked => 4,
+        // stake, clock, old authority, new authority, custodian
+        StakeInstruction::AuthorizeChecked => 5,
+        // stake, base, clock, new authority, custodian
+        StakeInstruction::AuthorizeCheckedWithSeed => 5,
+        // stake, lockup or withdraw authority, new lockup authority
+        StakeInstruction::SetLockupChecked => 3,
+        StakeInstruction::GetMinimumDelegation => 0,
+        // stake, delinquent vote, reference vote
+        StakeInstruction::DeactivateDelinquent => 3,
+        // stake, uninitialized stake, vote, stake config, stake authority
+        #[allow(deprecated)]
+        StakeInstruction::Redelegate => 5,
+        // source, destination, stake authority
+        StakeInstruction::MoveStake | StakeInstruction::MoveLamports => 3,
+    }
+}
+
+/// The accounts of an instruction, once `validate_accounts` has accepted them.
+/// Processors take their stake accounts from here, so the owner, size and
+/// writability checks made by the rules are not repeated.
+#[derive(Clone, Copy)]
+pub struct ValidatedAccounts<'a> {
+    accounts: &'a [AccountInfo],
+    rules: &'static [AccountRule],
+}
+
+impl<'a> ValidatedAccounts<'a> {
+    #[inline(always)]
+    pub fn accounts(&self) -> &'a [AccountInfo] {
+        self.accounts
+    }
+
+    /// Guards `account_info`, one of `accounts()`. An account in a position without a
+    /// stake account rule is checked as a writable stake account instead.
+    #[inline(always)]
+    pub fn stake_account(
+        &self,
+        account_info: &'a AccountInfo,
+    ) -> Result<StakeAccountGuard<'a>, ProgramError> {
+        // the position is found by address: `parse_accounts!` binds into the same slice
+        let rule = self
+            .rules
+            .iter()
+            .zip(self.accounts)
+            .find(|(_, validated)| core::ptr::eq(*validated, account_info))
+            .map(|(rule, _)| rule);
+        match rule {
+            Some(rule) if rule.stake_account => {
+                Ok(StakeAccountGuard::validated(account_info, rule.writable))
+            }
+            _ => StakeAccountGuard::new_writable(account_info),
+        }
+    }
+}
+
+/// Checks `accounts` against the rules of `instruction`
+pub fn validate_accounts<'a>(
+    instruction: &StakeInstruction,
+    accounts: &'a [AccountInfo],
+) -> Result<ValidatedAccounts<'a>, ProgramError> {
+    let rules = account_rules(instruction);
+    if accounts.len() < rules.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    #[cfg(feature = "strict-accounts")]
+    if accounts.len() > max_accounts(instruction) {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    rules
+        .iter()
+        .zip(accounts)
+        .try_for_each(|(rule, account_info)| rule.check(account_info))?;
+
+    // a stake account cannot double as a vote account of the same instruction
+    let positions = || rules.iter().zip(accounts);
+    for (_, vote_info) in positions().filter(|(rule, _)| rule.vote_account) {
+        if positions()
+            .any(|(rule, stake_info)| rule.stake_account && is_same_account(stake_info, vote_info))
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    Ok(ValidatedAccounts { accounts, rules })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_rules() {
+        for discriminant in 0u8..=17 {
+            let instruction = StakeInstruction::try_from(&discriminant).unwrap();
+            let rules = account_rules(&instruction);
+            // every instruction which takes accounts leads with a stake account
+            if let Some(first) = rules.first() {
+                assert_eq!(*first, AccountRule::STAKE);
+            }
+        }
+
+        assert!(account_rules(&StakeInstruction::GetMinimumDelegation).is_empty());
+        assert_eq!(account_rules(&StakeInstruction::Merge).len(), 4);
+        assert_eq!(account_rules(&StakeInstruction::DelegateStake).len(), 5);
+        assert_eq!(account_rules(&StakeInstruction::DelegateStake)[1], AccountRule::VOTE);
+    }
+
+    #[test]
+    fn test_max_accounts() {
+        for discriminant in 0u8..=17 {
+            let instruction = StakeInstruction::try_from(&discriminant).unwrap();
+            assert!(max_accounts(&instruction) >= account_rules(&instruction).len());
+            assert!(max_accounts(&instruction) <= MAX_INSTRUCTION_ACCOUNTS);
+        }
+        assert_eq!(MAX_INSTRUCTION_ACCOUNTS, 6);
+
+        assert_eq!(max_accounts(&StakeInstruction::GetMinimumDelegation), 0);
+        assert_eq!(max_accounts(&StakeInstruction::Merge), 5);
+        assert_eq!(max_accounts(&StakeInstruction::Withdraw), 6);
+    }
+}
@@ -0,0 +1,198 @@
+mod harness;
+
+use harness::{discriminant::DEACTIVATE, *};
+use mollusk_svm::{result::Check, Mollusk};
+use solana_sdk::{
+    account::Account,
+    instruction::AccountMeta,
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    stake::state::{Authorized, Lockup, Meta},
+};
+
+/// `StakeError::AlreadyDeactivated`
+const ALREADY_DEACTIVATED: u32 = 2;
+
+struct Authorities {
+    staker: Pubkey,
+    withdrawer: Pubkey,
+    custodian: Pubkey,
+}
+
+impl Authorities {
+    fn new() -> Self {
+        Self {
+            staker: Pubkey::new_unique(),
+            withdrawer: Pubkey::new_unique(),
+            custodian: Pubkey::new_unique(),
+        }
+    }
+}
+
+fn delegated_stake_account(
+    mollusk: &Mollusk,
+    authorities: &Authorities,
+    deactivation_epoch: u64,
+) -> Account {
+    let meta = Meta {
+        rent_exempt_reserve: rent_exempt_reserve(mollusk),
+        authorized: Authorized {
+            staker: authorities.staker,
+            withdrawer: authorities.withdrawer,
+        },
+        lockup: Lockup {
+            unix_timestamp: 0,
+            epoch: u64::MAX,
+            custodian: authorities.custodian,
+        },
+    };
+    let mut stake = delegation(&Pubkey::new_unique(), LAMPORTS_PER_SOL, 0);
+    stake.delegation.deactivation_epoch = deactivation_epoch;
+
+    funded_stake_account(mollusk, &stake_state(meta, stake))
+}
+
+/// Deactivates a delegated stake account, with `signer` as the trailing stake authority
+fn deactivate(
+    mollusk: &Mollusk,
+    authorities: &Authorities,
+    signer: &Pubkey,
+    deactivation_epoch: u64,
+    checks: &[Check],
+) {
+    let stake = Pubkey::new_unique();
+    let (clock, clock_account) = clock_sysvar(mollusk);
+
+    let instruction = stake_instruction(
+        DEACTIVATE,
+        &[],
+        vec![
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(*signer, true),
+        ],
+    );
+    let accounts = vec![
+        (stake, delegated_stake_account(mollusk, authorities, deactivation_epoch)),
+        (clock, clock_account),
+        (*signer, Account::default()),
+    ];
+
+    execute(mollusk, &instruction, &accounts, checks);
+}
+
+#[test]
+fn test_deactivate_signed_by_staker() {
+    let mollusk = mollusk();
+    let authorities = Authorities::new();
+
+    deactivate(&mollusk, &authorities, &authorities.staker, u64::MAX, &[Check::success()]);
+}
+
+#[test]
+fn test_deactivate_other_roles_cannot_substitute() {
+    let mollusk = mollusk();
+    let authorities = Authorities::new();
+
+    for signer in [authorities.withdrawer, authorities.custodian, Pubkey::new_unique()] {
+        deactivate(
+            &mollusk,
+            &authorities,
+            &signer,
+            u64::MAX,
+            &[Check::err(ProgramError::MissingRequiredSignature)],
+        );
+    }
+}
+
+#[test]
+fn test_deactivate_already_deactivated() {
+    let mollusk = mollusk();
+    let authorities = Authorities::new();
+
+    deactivate(
+        &mollusk,
+        &authorities,
+        &authorities.staker,
+        0,
+        &[Check::err(ProgramError::Custom(ALREADY_DEACTIVATED))],
+    );
+}
+
+/// Deactivates a delegated stake account passed with four unrelated accounts, seven
+/// in all: one past the most any instruction uses. The staker signs third, or last
+/// when `staker_last` is set.
+fn deactivate_past_account_cap(mollusk: &Mollusk, staker_last: bool, checks: &[Check]) {
+    let authorities = Authorities::new();
+    let stake = Pubkey::new_unique();
+    let (clock, clock_account) = clock_sysvar(mollusk);
+
+    let mut account_metas = vec![
+        AccountMeta::new(stake, false),
+        AccountMeta::new_readonly(clock, false),
+    ];
+    let mut accounts = vec![
+        (stake, delegated_stake_account(mollusk, &authorities, u64::MAX)),
+        (clock, clock_account),
+    ];
+    let staker = (
+        AccountMeta::new_readonly(authorities.staker, true),
+        (authorities.staker, Account::default()),
+    );
+    let mut extras: Vec<_> = (0..4)
+        .map(|_| {
+            let extra = Pubkey::new_unique();
+            (AccountMeta::new_readonly(extra, false), (extra, Account::default()))
+        })
+        .collect();
+    if staker_last {
+        extras.push(staker);
+    } else {
+        extras.insert(0, staker);
+    }
+    for (account_meta, account) in extras {
+        account_metas.push(account_meta);
+        accounts.push(account);
+    }
+
+    execute(mollusk, &stake_instruction(DEACTIVATE, &[], account_metas), &accounts, checks);
+}
+
+/// Like native, accounts past those the instruction uses are ignored
+#[cfg(not(feature = "strict-accounts"))]
+#[test]
+fn test_deactivate_ignores_trailing_accounts() {
+    let mollusk = mollusk();
+
+    deactivate_past_account_cap(&mollusk, false, &[Check::success()]);
+}
+
+/// Intended divergence: accounts past the cap are never deserialized, so a signer
+/// among them goes unseen where native would count it
+#[cfg(not(feature = "strict-accounts"))]
+#[test]
+fn test_deactivate_signer_past_account_cap() {
+    let mollusk = mollusk();
+
+    deactivate_past_account_cap(
+        &mollusk,
+        true,
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+    );
+}
+
+/// With `strict-accounts`, accounts past the cap are refused rather than skipped
+#[cfg(feature = "strict-accounts")]
+#[test]
+fn test_deactivate_past_account_cap() {
+    let mollusk = mollusk();
+
+    for staker_last in [false, true] {
+        deactivate_past_account_cap(
+            &mollusk,
+            staker_last,
+            &[Check::err(ProgramError::InvalidArgument)],
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Mollusk harness shared by the instruction tests: loads the compiled program,
+//! builds stake, vote and sysvar accounts, and runs single instructions with their
+//! compute units metered. Each test file pulls it in with `mod harness;`, so not
+//! every helper is used by every file; the account builders serve the
+//! `program_test` suite as well.
+//!
+//! The program is loaded from `target/deploy`, so run `cargo build-sbf` first.
+#![allow(dead_code)]
+
+use mollusk_svm::{
+    result::{Check, InstructionResult},
+    Mollusk,
+};
+use solana_pinocchio_starter::ID;
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    rent::Rent,
+    stake::{
+        stake_flags::StakeFlags,
+        state::{Authorized, Delegation, Lockup, Meta, Stake, StakeStateV2},
+    },
+    vote::{
+        self,
+        state::{VoteInit, VoteState, VoteStateVersions},
+    },
+};
+
+pub const PROGRAM: Pubkey = Pubkey::new_from_array(ID);
+
+/// `StakeInstruction` discriminants of the instructions under test
+pub mod discriminant {
+    pub const INITIALIZE: u8 = 0;
+    pub const DELEGATE_STAKE: u8 = 2;
+    pub const SPLIT: u8 = 3;
+    pub const WITHDRAW: u8 = 4;
+    pub const DEACTIVATE: u8 = 5;
+    pub const SET_LOCKUP: u8 = 6;
+    pub const MERGE: u8 = 7;
+    pub const GET_MINIMUM_DELEGATION: u8 = 13;
+}
+
+/// The compiled program, as `cargo build-sbf` leaves it
+pub const PROGRAM_PATH: &str = "target/deploy/solana_pinocchio_starter";
+
+pub fn mollusk() -> Mollusk {
+    Mollusk::new(&PROGRAM, PROGRAM_PATH)
+}
+
+/// The rent exempt reserve of a `StakeStateV2` sized account
+pub fn rent_exempt_reserve(mollusk: &Mollusk) -> u64 {
+    mollusk
+        .sysvars
+        .rent
+        .minimum_balance(StakeStateV2::size_of())
+}
+
+/// A meta with no lockup and `staker`/`withdrawer` as its authorities
+pub fn meta(mollusk: &Mollusk, staker: &Pubkey, withdrawer: &Pubkey) -> Meta {
+    Meta {
+        rent_exempt_reserve: rent_exempt_reserve(mollusk),
+        authorized: Authorized {
+            staker: *staker,
+            withdrawer: *withdrawer,
+        },
+        lockup: Lockup::default(),
+    }
+}
+
+/// A meta with no lockup and `authority` as both its authorities, its reserve taken
+/// from `rent` for harnesses other than Mollusk
+pub fn authority_meta(rent: &Rent, authority: &Pubkey) -> Meta {
+    Meta {
+        rent_exempt_reserve: rent.minimum_balance(StakeStateV2::size_of()),
+        authorized: Authorized::auto(authority),
+        lockup: Lockup::default(),
+    }
+}
+
+/// `stake` lamports delegated to `voter` from `activation_epoch`, never deactivated
+pub fn delegation(voter: &Pubkey, stake: u64, activation_epoch: u64) -> Stake {
+    Stake {
+        delegation: Delegation::new(voter, stake, activation_epoch),
+        credits_observed: 0,
+    }
+}
+
+pub fn initialized_state(meta: Meta) -> StakeStateV2 {
+    StakeStateV2::Initialized(meta)
+}
+
+pub fn stake_state(meta: Meta, stake: Stake) -> StakeStateV2 {
+    StakeStateV2::Stake(meta, stake, StakeFlags::empty())
+}
+
+/// A stake account holding `lamports` in `state`, owned by the program under test
+pub fn stake_account(lamports: u64, state: &StakeStateV2) -> Account {
+    let mut data = vec![0; StakeStateV2::size_of()];
+    bincode::serialize_into(&mut data[..], state).unwrap();
+    Account {
+        lamports,
+        data,
+        owner: PROGRAM,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// A stake account in `state` funded with its reserve and one SOL on top
+pub fn funded_stake_account(mollusk: &Mollusk, state: &StakeStateV2) -> Account {
+    stake_account(rent_exempt_reserve(mollusk) + LAMPORTS_PER_SOL, state)
+}
+
+/// A rent exempt vote account whose node and authorities are all `authority`
+pub fn vote_account(rent: &Rent, authority: &Pubkey) -> Account {
+    let vote_state = VoteState::new(
+        &VoteInit {
+            node_pubkey: *authority,
+            authorized_voter: *authority,
+            authorized_withdrawer: *authority,
+            commission: 0,
+        },
+        &Clock::default(),
+    );
+    let mut data = vec![0; VoteState::size_of()];
+    VoteState::serialize(&VoteStateVersions::new_current(vote_state), &mut data).unwrap();
+    Account {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: vote::program::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// The clock, rent and stake history sysvars as the harness' `Mollusk` holds them
+pub fn clock_sysvar(mollusk: &Mollusk) -> (Pubkey, Account) {
+    mollusk.sysvars.keyed_account_for_clock_sysvar()
+}
+
+pub fn rent_sysvar(mollusk: &Mollusk) -> (Pubkey, Account) {
+    mollusk.sysvars.keyed_account_for_rent_sysvar()
+}
+
+pub fn stake_history_sysvar(mollusk: &Mollusk) -> (Pubkey, Account) {
+    mollusk.sysvars.keyed_account_for_stake_history_sysvar()
+}
+
+/// An instruction to the program under test, its data led by `discriminant`
+pub fn stake_instruction(
+    discriminant: u8,
+    args: &[u8],
+    accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut data = Vec::with_capacity(1 + args.len());
+    data.push(discriminant);
+    data.extend_from_slice(args);
+    Instruction::new_with_bytes(PROGRAM, &data, accounts)
+}
+
+/// Runs `instruction` alone and validates `checks` against the result, which is
+/// returned for further inspection, its compute units included
+pub fn execute(
+    mollusk: &Mollusk,
+    instruction: &Instruction,
+    accounts: &[(Pubkey, Account)],
+    checks: &[Check],
+) -> InstructionResult {
+    mollusk.process_and_validate_instruction(instruction, accounts, checks)
+}
+
+/// Runs `instruction`, which must succeed, and returns the compute units it took
+pub fn compute_units(
+    mollusk: &Mollusk,
+    instruction: &Instruction,
+    accounts: &[(Pubkey, Account)],
+) -> u64 {
+    execute(mollusk, instruction, accounts, &[Check::success()]).compute_units_consumed
+}
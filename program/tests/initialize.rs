@@ -0,0 +1,106 @@
+mod harness;
+
+use harness::{discriminant::INITIALIZE, *};
+use mollusk_svm::{result::Check, Mollusk};
+use solana_sdk::{
+    instruction::AccountMeta,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    stake::state::{Authorized, Lockup, Meta, StakeStateV2},
+};
+
+fn authorized() -> Authorized {
+    Authorized {
+        staker: Pubkey::new_unique(),
+        withdrawer: Pubkey::new_unique(),
+    }
+}
+
+/// Initializes the account at `stake`, holding `lamports` in `state`, under
+/// `authorized` and `lockup`
+fn initialize(
+    mollusk: &Mollusk,
+    stake: &Pubkey,
+    lamports: u64,
+    state: &StakeStateV2,
+    authorized: &Authorized,
+    lockup: &Lockup,
+    checks: &[Check],
+) {
+    let (rent, rent_account) = rent_sysvar(mollusk);
+
+    let instruction = stake_instruction(
+        INITIALIZE,
+        &bincode::serialize(&(authorized, lockup)).unwrap(),
+        vec![
+            AccountMeta::new(*stake, false),
+            AccountMeta::new_readonly(rent, false),
+        ],
+    );
+    let accounts = vec![(*stake, stake_account(lamports, state)), (rent, rent_account)];
+
+    execute(mollusk, &instruction, &accounts, checks);
+}
+
+#[test]
+fn test_initialize() {
+    let mollusk = mollusk();
+    let stake = Pubkey::new_unique();
+    let authorized = authorized();
+    let lockup = Lockup {
+        unix_timestamp: 1,
+        epoch: 2,
+        custodian: Pubkey::new_unique(),
+    };
+    let reserve = rent_exempt_reserve(&mollusk);
+    let initialized = stake_account(
+        reserve,
+        &initialized_state(Meta {
+            rent_exempt_reserve: reserve,
+            authorized,
+            lockup,
+        }),
+    );
+
+    initialize(
+        &mollusk,
+        &stake,
+        reserve,
+        &StakeStateV2::Uninitialized,
+        &authorized,
+        &lockup,
+        &[Check::success(), Check::account(&stake).data(&initialized.data).build()],
+    );
+}
+
+#[test]
+fn test_initialize_twice() {
+    let mollusk = mollusk();
+    let authorized = authorized();
+
+    initialize(
+        &mollusk,
+        &Pubkey::new_unique(),
+        rent_exempt_reserve(&mollusk),
+        &initialized_state(meta(&mollusk, &authorized.staker, &authorized.withdrawer)),
+        &authorized,
+        &Lockup::default(),
+        &[Check::err(ProgramError::InvalidAccountData)],
+    );
+}
+
+/// Intended divergence: native fails with `InsufficientFunds` here
+#[test]
+fn test_initialize_not_rent_exempt() {
+    let mollusk = mollusk();
+
+    initialize(
+        &mollusk,
+        &Pubkey::new_unique(),
+        rent_exempt_reserve(&mollusk) - 1,
+        &StakeStateV2::Uninitialized,
+        &authorized(),
+        &Lockup::default(),
+        &[Check::err(ProgramError::AccountNotRentExempt)],
+    );
+}
@@ -0,0 +1,304 @@
+//! LiteSVM scenarios following stake accounts across epochs. The clock is warped
+//! between transactions and the stake history is written directly, so the
+//! warmup and cooldown a scenario goes through is exactly the one it sets up.
+//!
+//! Run `cargo build-sbf` first; the program replaces the builtin stake program.
+
+mod harness;
+
+use harness::*;
+use litesvm::LiteSVM;
+use solana_sdk::{
+    clock::{Clock, Epoch},
+    epoch_schedule::EpochSchedule,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    stake::state::{Authorized, Lockup, StakeStateV2},
+    stake_history::{StakeHistory, StakeHistoryEntry},
+    sysvar,
+    transaction::Transaction,
+};
+
+/// Cluster stake the synthetic history is built around, large enough that the
+/// stake in these scenarios warms up and cools down within one epoch
+const CLUSTER_STAKE: u64 = 1_000 * LAMPORTS_PER_SOL;
+
+fn svm() -> LiteSVM {
+    let mut svm = LiteSVM::new();
+    svm.add_program_from_file(PROGRAM, format!("{PROGRAM_PATH}.so")).unwrap();
+    svm
+}
+
+/// Moves the clock to the first slot of `epoch`
+fn warp_to_epoch(svm: &mut LiteSVM, epoch: Epoch) {
+    let slot = svm
+        .get_sysvar::<EpochSchedule>()
+        .get_first_slot_in_epoch(epoch);
+    svm.warp_to_slot(slot);
+    let mut clock = svm.get_sysvar::<Clock>();
+    clock.epoch = epoch;
+    clock.leader_schedule_epoch = epoch + 1;
+    svm.set_sysvar(&clock);
+    // transactions repeated after the warp must not collide with the earlier ones
+    svm.expire_blockhash();
+}
+
+/// Replaces the stake history sysvar with `entries`
+fn set_stake_history(svm: &mut LiteSVM, entries: &[(Epoch, StakeHistoryEntry)]) {
+    let mut stake_history = StakeHistory::default();
+    for (epoch, entry) in entries {
+        stake_history.add(*epoch, entry.clone());
+    }
+    svm.set_sysvar(&stake_history);
+}
+
+/// An epoch in which `activating` lamports warm up against the cluster stake
+fn warming_up(activating: u64) -> StakeHistoryEntry {
+    StakeHistoryEntry {
+        effective: CLUSTER_STAKE,
+        activating,
+        deactivating: 0,
+    }
+}
+
+/// An epoch in which `deactivating` lamports cool down against the cluster stake
+fn cooling_down(deactivating: u64) -> StakeHistoryEntry {
+    StakeHistoryEntry {
+        effective: CLUSTER_STAKE,
+        activating: 0,
+        deactivating,
+    }
+}
+
+fn send(svm: &mut LiteSVM, instruction: Instruction, payer: &Keypair, signers: &[&Keypair]) {
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &all_signers,
+        svm.latest_blockhash(),
+    );
+    if let Err(failed) = svm.send_transaction(transaction) {
+        panic!("{:?}\n{:#?}", failed.err, failed.meta.logs);
+    }
+}
+
+fn fetch_stake_state(svm: &LiteSVM, stake: &Pubkey) -> StakeStateV2 {
+    bincode::deserialize(&svm.get_account(stake).unwrap().data).unwrap()
+}
+
+fn merge(destination: &Pubkey, source: &Pubkey, authority: &Pubkey) -> Instruction {
+    stake_instruction(
+        discriminant::MERGE,
+        &[],
+        vec![
+            AccountMeta::new(*destination, false),
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+fn deactivate(stake: &Pubkey, authority: &Pubkey) -> Instruction {
+    stake_instruction(
+        discriminant::DEACTIVATE,
+        &[],
+        vec![
+            AccountMeta::new(*stake, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+/// Two delegations warm up and merge, the merged stake cools down, and the inactive
+/// account then absorbs an initialized one
+#[test]
+fn test_warm_up_merge_cool_down() {
+    let mut svm = svm();
+    let payer = Keypair::new();
+    svm.airdrop(&payer.pubkey(), LAMPORTS_PER_SOL).unwrap();
+    let authority = Keypair::new();
+    let voter = Pubkey::new_unique();
+    let meta = authority_meta(&Rent::default(), &authority.pubkey());
+    let lamports = meta.rent_exempt_reserve + LAMPORTS_PER_SOL;
+
+    let (destination, source, initialized) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    for stake in [destination, source] {
+        let state = stake_state(meta, delegation(&voter, LAMPORTS_PER_SOL, 0));
+        svm.set_account(stake, stake_account(lamports, &state)).unwrap();
+    }
+    svm.set_account(initialized, stake_account(lamports, &initialized_state(meta))).unwrap();
+
+    // both delegations are fully active by epoch 1
+    set_stake_history(&mut svm, &[(0, warming_up(2 * LAMPORTS_PER_SOL))]);
+    warp_to_epoch(&mut svm, 5);
+    send(&mut svm, merge(&destination, &source, &authority.pubkey()), &payer, &[&authority]);
+
+    let StakeStateV2::Stake(_, stake, _) = fetch_stake_state(&svm, &destination) else {
+        panic!("merged into a delegation");
+    };
+    assert_eq!(stake.delegation.stake, 2 * LAMPORTS_PER_SOL);
+    assert_eq!(svm.get_account(&destination).unwrap().lamports, 2 * lamports);
+
+    send(&mut svm, deactivate(&destination, &authority.pubkey()), &payer, &[&authority]);
+    let StakeStateV2::Stake(_, stake, _) = fetch_stake_state(&svm, &destination) else {
+        panic!("still delegated while cooling down");
+    };
+    assert_eq!(stake.delegation.deactivation_epoch, 5);
+
+    // the merged stake is fully inactive by epoch 6
+    set_stake_history(
+        &mut svm,
+        &[
+            (0, warming_up(2 * LAMPORTS_PER_SOL)),
+            (5, cooling_down(2 * LAMPORTS_PER_SOL)),
+        ],
+    );
+    warp_to_epoch(&mut svm, 6);
+    send(&mut svm, merge(&destination, &initialized, &authority.pubkey()), &payer, &[&authority]);
+
+    assert_eq!(svm.get_account(&destination).unwrap().lamports, 3 * lamports);
+    assert_eq!(svm.get_account(&initialized).map_or(0, |account| account.lamports), 0);
+}
+
+/// Stake still warming up cannot merge with fully active stake
+#[test]
+fn test_merge_rejected_mid_warmup() {
+    let mut svm = svm();
+    let payer = Keypair::new();
+    svm.airdrop(&payer.pubkey(), LAMPORTS_PER_SOL).unwrap();
+    let authority = Keypair::new();
+    let voter = Pubkey::new_unique();
+    let meta = authority_meta(&Rent::default(), &authority.pubkey());
+    let lamports = meta.rent_exempt_reserve + LAMPORTS_PER_SOL;
+
+    let (active, activating) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let active_state = stake_state(meta, delegation(&voter, LAMPORTS_PER_SOL, 0));
+    let activating_state = stake_state(meta, delegation(&voter, LAMPORTS_PER_SOL, 4));
+    svm.set_account(active, stake_account(lamports, &active_state)).unwrap();
+    svm.set_account(activating, stake_account(lamports, &activating_state)).unwrap();
+
+    // the second delegation is only partly effective at epoch 5
+    set_stake_history(
+        &mut svm,
+        &[
+            (0, warming_up(LAMPORTS_PER_SOL)),
+            (
+                4,
+                StakeHistoryEntry {
+                    effective: LAMPORTS_PER_SOL,
+                    activating: 100 * LAMPORTS_PER_SOL,
+                    deactivating: 0,
+                },
+            ),
+        ],
+    );
+    warp_to_epoch(&mut svm, 5);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[merge(&active, &activating, &authority.pubkey())],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        svm.latest_blockhash(),
+    );
+    assert!(svm.send_transaction(transaction).is_err());
+    assert_eq!(fetch_stake_state(&svm, &activating), activating_state);
+}
+
+/// An account goes through Initialize, DelegateStake, Deactivate and a full Withdraw
+#[test]
+fn test_full_lifecycle() {
+    let mut svm = svm();
+    let payer = Keypair::new();
+    svm.airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL).unwrap();
+    let authority = Keypair::new();
+    let rent = Rent::default();
+    let vote = Pubkey::new_unique();
+    svm.set_account(vote, vote_account(&rent, &Pubkey::new_unique())).unwrap();
+
+    // initialize
+    let stake = Pubkey::new_unique();
+    let reserve = rent.minimum_balance(StakeStateV2::size_of());
+    let lamports = reserve + 2 * LAMPORTS_PER_SOL;
+    svm.set_account(stake, stake_account(lamports, &StakeStateV2::Uninitialized)).unwrap();
+    let initialize_args =
+        bincode::serialize(&(Authorized::auto(&authority.pubkey()), Lockup::default())).unwrap();
+    send(
+        &mut svm,
+        stake_instruction(
+            discriminant::INITIALIZE,
+            &initialize_args,
+            vec![
+                AccountMeta::new(stake, false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+        ),
+        &payer,
+        &[],
+    );
+
+    // delegate
+    send(
+        &mut svm,
+        stake_instruction(
+            discriminant::DELEGATE_STAKE,
+            &[],
+            vec![
+                AccountMeta::new(stake, false),
+                AccountMeta::new_readonly(vote, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+                AccountMeta::new_readonly(
+                    Pubkey::new_from_array(solana_pinocchio_starter::consts::STAKE_CONFIG_ID),
+                    false,
+                ),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+            ],
+        ),
+        &payer,
+        &[&authority],
+    );
+
+    // warp until active, then deactivate
+    set_stake_history(&mut svm, &[(0, warming_up(2 * LAMPORTS_PER_SOL))]);
+    warp_to_epoch(&mut svm, 3);
+    send(&mut svm, deactivate(&stake, &authority.pubkey()), &payer, &[&authority]);
+
+    // warp until inactive, then withdraw everything
+    set_stake_history(
+        &mut svm,
+        &[
+            (0, warming_up(2 * LAMPORTS_PER_SOL)),
+            (3, cooling_down(2 * LAMPORTS_PER_SOL)),
+        ],
+    );
+    warp_to_epoch(&mut svm, 4);
+    let recipient = Pubkey::new_unique();
+    send(
+        &mut svm,
+        stake_instruction(
+            discriminant::WITHDRAW,
+            &lamports.to_le_bytes(),
+            vec![
+                AccountMeta::new(stake, false),
+                AccountMeta::new(recipient, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+            ],
+        ),
+        &payer,
+        &[&authority],
+    );
+
+    assert_eq!(svm.get_account(&recipient).unwrap().lamports, lamports);
+    assert_eq!(svm.get_account(&stake).map_or(0, |account| account.lamports), 0);
+}
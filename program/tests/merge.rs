@@ -0,0 +1,150 @@
+mod harness;
+
+use harness::{discriminant::MERGE, *};
+use mollusk_svm::{result::Check, Mollusk};
+use solana_sdk::{
+    account::Account, instruction::AccountMeta, program_error::ProgramError, pubkey::Pubkey,
+    stake::state::StakeStateV2,
+};
+
+fn initialized_stake_account(mollusk: &Mollusk, staker: &Pubkey) -> Account {
+    funded_stake_account(mollusk, &initialized_state(meta(mollusk, staker, staker)))
+}
+
+/// Merges `source` into `destination`, with `authority` as the trailing stake authority
+fn merge(
+    mollusk: &Mollusk,
+    staker: &Pubkey,
+    authority: &Pubkey,
+    authority_is_signer: bool,
+    checks: &[Check],
+) {
+    let destination = Pubkey::new_unique();
+    let source = Pubkey::new_unique();
+    let (clock, clock_account) = clock_sysvar(mollusk);
+    let (stake_history, stake_history_account) = stake_history_sysvar(mollusk);
+
+    let instruction = stake_instruction(
+        MERGE,
+        &[],
+        vec![
+            AccountMeta::new(destination, false),
+            AccountMeta::new(source, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(stake_history, false),
+            AccountMeta::new_readonly(*authority, authority_is_signer),
+        ],
+    );
+    let accounts = vec![
+        (destination, initialized_stake_account(mollusk, staker)),
+        (source, initialized_stake_account(mollusk, staker)),
+        (clock, clock_account),
+        (stake_history, stake_history_account),
+        (*authority, Account::default()),
+    ];
+
+    execute(mollusk, &instruction, &accounts, checks);
+}
+
+#[test]
+fn test_merge_signed_by_staker() {
+    let mollusk = mollusk();
+    let staker = Pubkey::new_unique();
+
+    merge(&mollusk, &staker, &staker, true, &[Check::success()]);
+}
+
+#[test]
+fn test_merge_staker_not_signing() {
+    let mollusk = mollusk();
+    let staker = Pubkey::new_unique();
+
+    merge(
+        &mollusk,
+        &staker,
+        &staker,
+        false,
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+    );
+}
+
+#[test]
+fn test_merge_signed_by_other_authority() {
+    let mollusk = mollusk();
+    let staker = Pubkey::new_unique();
+
+    merge(
+        &mollusk,
+        &staker,
+        &Pubkey::new_unique(),
+        true,
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+    );
+}
+
+#[test]
+fn test_merge_default_staker_without_signers() {
+    let mollusk = mollusk();
+    // a default authority must not be satisfied by an empty signer set
+    let staker = Pubkey::default();
+
+    merge(
+        &mollusk,
+        &staker,
+        &Pubkey::new_unique(),
+        false,
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+    );
+}
+
+#[test]
+fn test_merge_inactive_leaves_destination_data() {
+    let mollusk = mollusk();
+    let staker = Pubkey::new_unique();
+    let destination = Pubkey::new_unique();
+    let source = Pubkey::new_unique();
+    let (clock, clock_account) = clock_sysvar(&mollusk);
+    let (stake_history, stake_history_account) = stake_history_sysvar(&mollusk);
+
+    let destination_account = initialized_stake_account(&mollusk, &staker);
+    let source_account = initialized_stake_account(&mollusk, &staker);
+    let total_lamports = destination_account.lamports + source_account.lamports;
+    let destination_data = destination_account.data.clone();
+
+    let instruction = stake_instruction(
+        MERGE,
+        &[],
+        vec![
+            AccountMeta::new(destination, false),
+            AccountMeta::new(source, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(stake_history, false),
+            AccountMeta::new_readonly(staker, true),
+        ],
+    );
+    let accounts = vec![
+        (destination, destination_account),
+        (source, source_account),
+        (clock, clock_account),
+        (stake_history, stake_history_account),
+        (staker, Account::default()),
+    ];
+
+    // two inactive accounts merge into the destination as it was, only the lamports move
+    execute(
+        &mollusk,
+        &instruction,
+        &accounts,
+        &[
+            Check::success(),
+            Check::account(&destination)
+                .data(&destination_data)
+                .lamports(total_lamports)
+                .build(),
+            Check::account(&source)
+                .data(&[0; StakeStateV2::size_of()])
+                .lamports(0)
+                .build(),
+        ],
+    );
+}
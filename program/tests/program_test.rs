@@ -0,0 +1,305 @@
+//! BanksClient tests: whole transactions against a bank, for what a single
+//! instruction on Mollusk cannot show, such as accounts the system program creates
+//! in the same transaction, rent checks on the resulting accounts and accounts
+//! purged once drained.
+//!
+//! The program replaces the builtin stake program in genesis, so run
+//! `cargo build-sbf` first.
+
+mod harness;
+
+use harness::*;
+use solana_pinocchio_starter::consts::STAKE_CONFIG_ID;
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    stake::{
+        instruction::LockupArgs,
+        state::{Authorized, Lockup, StakeStateV2},
+    },
+    system_instruction, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+
+async fn start(accounts: &[(Pubkey, Account)]) -> ProgramTestContext {
+    let mut program_test = ProgramTest::default();
+    program_test.prefer_bpf(true);
+    program_test.add_upgradeable_program_to_genesis("solana_pinocchio_starter", &PROGRAM);
+    for (pubkey, account) in accounts {
+        program_test.add_account(*pubkey, account.clone().into());
+    }
+    program_test.start_with_context().await
+}
+
+async fn send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        context.last_blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|error| error.unwrap())
+}
+
+async fn fetch_stake_state(context: &mut ProgramTestContext, stake: &Pubkey) -> StakeStateV2 {
+    let account = context
+        .banks_client
+        .get_account(*stake)
+        .await
+        .unwrap()
+        .expect("stake account exists");
+    bincode::deserialize(&account.data).unwrap()
+}
+
+fn create_stake_account(payer: &Pubkey, stake: &Pubkey, lamports: u64) -> Instruction {
+    system_instruction::create_account(
+        payer,
+        stake,
+        lamports,
+        StakeStateV2::size_of() as u64,
+        &PROGRAM,
+    )
+}
+
+#[tokio::test]
+async fn test_create_account_then_get_minimum_delegation() {
+    let mut context = start(&[]).await;
+    let stake = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let lamports = rent.minimum_balance(StakeStateV2::size_of());
+
+    send(
+        &mut context,
+        &[
+            create_stake_account(&context.payer.pubkey(), &stake.pubkey(), lamports),
+            stake_instruction(discriminant::GET_MINIMUM_DELEGATION, &[], vec![]),
+        ],
+        &[&stake],
+    )
+    .await
+    .unwrap();
+
+    // the system program hands the account over zeroed, which reads as uninitialized
+    assert_eq!(
+        fetch_stake_state(&mut context, &stake.pubkey()).await,
+        StakeStateV2::Uninitialized
+    );
+}
+
+#[tokio::test]
+async fn test_create_account_below_rent_exemption() {
+    let mut context = start(&[]).await;
+    let stake = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let lamports = rent.minimum_balance(StakeStateV2::size_of()) - 1;
+
+    let result = send(
+        &mut context,
+        &[create_stake_account(&context.payer.pubkey(), &stake.pubkey(), lamports)],
+        &[&stake],
+    )
+    .await;
+
+    assert_eq!(
+        result,
+        Err(TransactionError::InsufficientFundsForRent { account_index: 1 })
+    );
+}
+
+#[tokio::test]
+async fn test_set_lockup_then_deactivate() {
+    let authority = Keypair::new();
+    let stake = Pubkey::new_unique();
+    let meta = authority_meta(&Rent::default(), &authority.pubkey());
+    let state = stake_state(meta, delegation(&Pubkey::new_unique(), LAMPORTS_PER_SOL, 0));
+    let mut context = start(&[(
+        stake,
+        stake_account(meta.rent_exempt_reserve + LAMPORTS_PER_SOL, &state),
+    )])
+    .await;
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+
+    let args = LockupArgs {
+        unix_timestamp: None,
+        epoch: Some(clock.epoch + 10),
+        custodian: None,
+    };
+    send(
+        &mut context,
+        &[
+            stake_instruction(
+                discriminant::SET_LOCKUP,
+                &bincode::serialize(&args).unwrap(),
+                vec![
+                    AccountMeta::new(stake, false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+            ),
+            stake_instruction(
+                discriminant::DEACTIVATE,
+                &[],
+                vec![
+                    AccountMeta::new(stake, false),
+                    AccountMeta::new_readonly(sysvar::clock::id(), false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+            ),
+        ],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    // the second instruction sees what the first wrote
+    let StakeStateV2::Stake(meta, stake, _) = fetch_stake_state(&mut context, &stake).await else {
+        panic!("still delegated");
+    };
+    assert_eq!(meta.lockup.epoch, clock.epoch + 10);
+    assert_eq!(stake.delegation.deactivation_epoch, clock.epoch);
+}
+
+#[tokio::test]
+async fn test_merge_purges_drained_source() {
+    let authority = Keypair::new();
+    let destination = Pubkey::new_unique();
+    let source = Pubkey::new_unique();
+    let meta = authority_meta(&Rent::default(), &authority.pubkey());
+    let lamports = meta.rent_exempt_reserve + LAMPORTS_PER_SOL;
+    let mut context = start(&[
+        (destination, stake_account(lamports, &initialized_state(meta))),
+        (source, stake_account(lamports, &initialized_state(meta))),
+    ])
+    .await;
+
+    send(
+        &mut context,
+        &[stake_instruction(
+            discriminant::MERGE,
+            &[],
+            vec![
+                AccountMeta::new(destination, false),
+                AccountMeta::new(source, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+            ],
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    let banks_client = &mut context.banks_client;
+    let destination_account = banks_client.get_account(destination).await.unwrap().unwrap();
+    assert_eq!(destination_account.lamports, 2 * lamports);
+    // a drained account does not outlive the transaction
+    assert_eq!(banks_client.get_account(source).await.unwrap(), None);
+}
+
+/// The system program creates the account, which Initialize and DelegateStake then
+/// set up in the same transaction
+#[tokio::test]
+async fn test_create_initialize_delegate() {
+    let rent = Rent::default();
+    let authority = Keypair::new();
+    let stake = Keypair::new();
+    let vote = Pubkey::new_unique();
+    let mut context = start(&[(vote, vote_account(&rent, &Pubkey::new_unique()))]).await;
+    let payer = context.payer.pubkey();
+
+    let initialize_args = bincode::serialize(&(
+        Authorized::auto(&authority.pubkey()),
+        Lockup::default(),
+    ))
+    .unwrap();
+    let lamports = rent.minimum_balance(StakeStateV2::size_of()) + 2 * LAMPORTS_PER_SOL;
+    send(
+        &mut context,
+        &[
+            create_stake_account(&payer, &stake.pubkey(), lamports),
+            stake_instruction(
+                discriminant::INITIALIZE,
+                &initialize_args,
+                vec![
+                    AccountMeta::new(stake.pubkey(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+            ),
+            stake_instruction(
+                discriminant::DELEGATE_STAKE,
+                &[],
+                vec![
+                    AccountMeta::new(stake.pubkey(), false),
+                    AccountMeta::new_readonly(vote, false),
+                    AccountMeta::new_readonly(sysvar::clock::id(), false),
+                    AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+                    AccountMeta::new_readonly(Pubkey::new_from_array(STAKE_CONFIG_ID), false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+            ),
+        ],
+        &[&stake, &authority],
+    )
+    .await
+    .unwrap();
+
+    let StakeStateV2::Stake(meta, stake, _) =
+        fetch_stake_state(&mut context, &stake.pubkey()).await
+    else {
+        panic!("delegated");
+    };
+    assert_eq!(meta.authorized, Authorized::auto(&authority.pubkey()));
+    assert_eq!(stake.delegation.voter_pubkey, vote);
+    assert_eq!(stake.delegation.stake, lamports - meta.rent_exempt_reserve);
+}
+
+/// A program error surfaces from the bank as the failing instruction's error
+#[tokio::test]
+async fn test_instruction_error_surfaces() {
+    let authority = Keypair::new();
+    let stake = Pubkey::new_unique();
+    let mut context = start(&[(
+        stake,
+        stake_account(LAMPORTS_PER_SOL, &StakeStateV2::Uninitialized),
+    )])
+    .await;
+
+    let result = send(
+        &mut context,
+        &[stake_instruction(
+            discriminant::DEACTIVATE,
+            &[],
+            vec![
+                AccountMeta::new(stake, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+            ],
+        )],
+        &[&authority],
+    )
+    .await;
+
+    assert_eq!(
+        result,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidAccountData
+        ))
+    );
+}
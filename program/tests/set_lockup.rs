@@ -0,0 +1,95 @@
+mod harness;
+
+use harness::{discriminant::SET_LOCKUP, *};
+use mollusk_svm::{result::Check, Mollusk};
+use solana_sdk::{
+    account::Account,
+    instruction::AccountMeta,
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    stake::{
+        instruction::LockupArgs,
+        state::{Meta, StakeStateV2},
+    },
+};
+
+/// A meta with `withdrawer` as the authority `SetLockup` checks while no lockup is
+/// in force
+fn withdrawer_meta(mollusk: &Mollusk, withdrawer: &Pubkey) -> Meta {
+    meta(mollusk, &Pubkey::new_unique(), withdrawer)
+}
+
+/// Sets a new lockup epoch on an account in `state`, signed by `signer`
+fn set_lockup(mollusk: &Mollusk, state: &StakeStateV2, signer: &Pubkey, checks: &[Check]) {
+    let stake = Pubkey::new_unique();
+    let args = LockupArgs {
+        unix_timestamp: None,
+        epoch: Some(1),
+        custodian: None,
+    };
+
+    let instruction = stake_instruction(
+        SET_LOCKUP,
+        &bincode::serialize(&args).unwrap(),
+        vec![
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(*signer, true),
+        ],
+    );
+    let accounts = vec![
+        (stake, stake_account(LAMPORTS_PER_SOL, state)),
+        (*signer, Account::default()),
+    ];
+
+    execute(mollusk, &instruction, &accounts, checks);
+}
+
+#[test]
+fn test_set_lockup_initialized() {
+    let mollusk = mollusk();
+    let withdrawer = Pubkey::new_unique();
+    let state = initialized_state(withdrawer_meta(&mollusk, &withdrawer));
+
+    set_lockup(&mollusk, &state, &withdrawer, &[Check::success()]);
+    set_lockup(
+        &mollusk,
+        &state,
+        &Pubkey::new_unique(),
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+    );
+}
+
+#[test]
+fn test_set_lockup_stake() {
+    let mollusk = mollusk();
+    let withdrawer = Pubkey::new_unique();
+    let stake = delegation(&Pubkey::new_unique(), LAMPORTS_PER_SOL / 2, 0);
+    let state = stake_state(withdrawer_meta(&mollusk, &withdrawer), stake);
+
+    set_lockup(&mollusk, &state, &withdrawer, &[Check::success()]);
+}
+
+#[test]
+fn test_set_lockup_uninitialized() {
+    let mollusk = mollusk();
+
+    set_lockup(
+        &mollusk,
+        &StakeStateV2::Uninitialized,
+        &Pubkey::new_unique(),
+        &[Check::err(ProgramError::UninitializedAccount)],
+    );
+}
+
+#[test]
+fn test_set_lockup_rewards_pool() {
+    let mollusk = mollusk();
+
+    set_lockup(
+        &mollusk,
+        &StakeStateV2::RewardsPool,
+        &Pubkey::new_unique(),
+        &[Check::err(ProgramError::InvalidAccountData)],
+    );
+}
@@ -0,0 +1,255 @@
+mod harness;
+
+use harness::{discriminant::WITHDRAW, *};
+use mollusk_svm::{
+    result::{Check, ProgramResult},
+    Mollusk,
+};
+use solana_sdk::{
+    account::Account,
+    instruction::AccountMeta,
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    stake::state::{Lockup, Meta, StakeStateV2},
+};
+
+/// `StakeError::LockupInForce`
+const LOCKUP_IN_FORCE: u32 = 1;
+
+/// Withdraws `lamports` from `stake_account` into a fresh recipient, signed by
+/// `signer` as the withdraw authority and by `custodian` when given. Returns the
+/// balances of the stake account and the recipient afterwards.
+fn withdraw(
+    mollusk: &Mollusk,
+    stake_account: Account,
+    signer: &Pubkey,
+    custodian: Option<&Pubkey>,
+    lamports: u64,
+) -> Result<(u64, u64), ProgramError> {
+    withdraw_from(mollusk, &Pubkey::new_unique(), stake_account, signer, custodian, lamports)
+}
+
+/// `withdraw` from the account at `stake`, which may sign for itself
+fn withdraw_from(
+    mollusk: &Mollusk,
+    stake: &Pubkey,
+    stake_account: Account,
+    signer: &Pubkey,
+    custodian: Option<&Pubkey>,
+    lamports: u64,
+) -> Result<(u64, u64), ProgramError> {
+    let recipient = Pubkey::new_unique();
+    let (clock, clock_account) = clock_sysvar(mollusk);
+    let (stake_history, stake_history_account) = stake_history_sysvar(mollusk);
+
+    let mut account_metas = vec![
+        // an uninitialized account signs as its own withdraw authority
+        AccountMeta::new(*stake, signer == stake),
+        AccountMeta::new(recipient, false),
+        AccountMeta::new_readonly(clock, false),
+        AccountMeta::new_readonly(stake_history, false),
+        AccountMeta::new_readonly(*signer, true),
+    ];
+    let mut accounts = vec![
+        (*stake, stake_account),
+        (recipient, Account::default()),
+        (clock, clock_account),
+        (stake_history, stake_history_account),
+    ];
+    if signer != stake {
+        accounts.push((*signer, Account::default()));
+    }
+    if let Some(custodian) = custodian {
+        account_metas.push(AccountMeta::new_readonly(*custodian, true));
+        accounts.push((*custodian, Account::default()));
+    }
+
+    let result = execute(
+        mollusk,
+        &stake_instruction(WITHDRAW, &lamports.to_le_bytes(), account_metas),
+        &accounts,
+        &[],
+    );
+    match result.program_result {
+        ProgramResult::Success => Ok((
+            result.get_account(stake).unwrap().lamports,
+            result.get_account(&recipient).unwrap().lamports,
+        )),
+        ProgramResult::Failure(error) => Err(error),
+        ProgramResult::UnknownError(error) => panic!("{error:?}"),
+    }
+}
+
+#[test]
+fn test_withdraw_initialized() {
+    let mollusk = mollusk();
+    let withdrawer = Pubkey::new_unique();
+    let meta = meta(&mollusk, &Pubkey::new_unique(), &withdrawer);
+    let account = || funded_stake_account(&mollusk, &initialized_state(meta));
+    let reserve = rent_exempt_reserve(&mollusk);
+
+    // everything above the reserve
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL),
+        Ok((reserve, LAMPORTS_PER_SOL))
+    );
+    // one lamport into the reserve
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL + 1),
+        Err(ProgramError::InsufficientFunds)
+    );
+    // the whole balance closes the account
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, None, reserve + LAMPORTS_PER_SOL),
+        Ok((0, reserve + LAMPORTS_PER_SOL))
+    );
+}
+
+#[test]
+fn test_withdraw_requires_withdrawer() {
+    let mollusk = mollusk();
+    let staker = Pubkey::new_unique();
+    let meta = meta(&mollusk, &staker, &Pubkey::new_unique());
+    let account = funded_stake_account(&mollusk, &initialized_state(meta));
+
+    assert_eq!(
+        withdraw(&mollusk, account, &staker, None, 1),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+}
+
+#[test]
+fn test_withdraw_lockup() {
+    let mollusk = mollusk();
+    let (withdrawer, custodian) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let locked = Meta {
+        lockup: Lockup {
+            unix_timestamp: 0,
+            epoch: u64::MAX,
+            custodian,
+        },
+        ..meta(&mollusk, &Pubkey::new_unique(), &withdrawer)
+    };
+    let account = || funded_stake_account(&mollusk, &initialized_state(locked));
+    let lockup_in_force = Err(ProgramError::Custom(LOCKUP_IN_FORCE));
+
+    assert_eq!(withdraw(&mollusk, account(), &withdrawer, None, 1), lockup_in_force);
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, Some(&Pubkey::new_unique()), 1),
+        lockup_in_force
+    );
+    assert!(withdraw(&mollusk, account(), &withdrawer, Some(&custodian), 1).is_ok());
+}
+
+#[test]
+fn test_withdraw_active_stake() {
+    let mollusk = mollusk();
+    let withdrawer = Pubkey::new_unique();
+    let meta = meta(&mollusk, &Pubkey::new_unique(), &withdrawer);
+    let staked = delegation(&Pubkey::new_unique(), LAMPORTS_PER_SOL / 2, 0);
+    let account = || funded_stake_account(&mollusk, &stake_state(meta, staked));
+
+    // only what backs neither the stake nor the reserve
+    assert!(withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL / 2).is_ok());
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL / 2 + 1),
+        Err(ProgramError::InsufficientFunds)
+    );
+    // active stake keeps the account open
+    assert_eq!(
+        withdraw(
+            &mollusk,
+            account(),
+            &withdrawer,
+            None,
+            rent_exempt_reserve(&mollusk) + LAMPORTS_PER_SOL
+        ),
+        Err(ProgramError::InsufficientFunds)
+    );
+}
+
+#[test]
+fn test_withdraw_uninitialized() {
+    let mollusk = mollusk();
+    let stake = Pubkey::new_unique();
+    let account = || funded_stake_account(&mollusk, &StakeStateV2::Uninitialized);
+    let reserve = rent_exempt_reserve(&mollusk);
+
+    // no authorities before Initialize, the account signs for itself
+    assert_eq!(
+        withdraw_from(&mollusk, &stake, account(), &stake, None, reserve + LAMPORTS_PER_SOL),
+        Ok((0, reserve + LAMPORTS_PER_SOL))
+    );
+    // only the account itself can sign for it
+    assert_eq!(
+        withdraw(&mollusk, account(), &Pubkey::new_unique(), None, 1),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+}
+
+/// A full withdrawal closes the account like a merged-away source: its data is
+/// zeroed along with the tag
+#[test]
+fn test_withdraw_all_zeroes_data() {
+    let mollusk = mollusk();
+    let (stake, recipient, withdrawer) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let (clock, clock_account) = clock_sysvar(&mollusk);
+    let (stake_history, stake_history_account) = stake_history_sysvar(&mollusk);
+    let meta = meta(&mollusk, &Pubkey::new_unique(), &withdrawer);
+    let lamports = rent_exempt_reserve(&mollusk) + LAMPORTS_PER_SOL;
+
+    let instruction = stake_instruction(
+        WITHDRAW,
+        &lamports.to_le_bytes(),
+        vec![
+            AccountMeta::new(stake, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(clock, false),
+            AccountMeta::new_readonly(stake_history, false),
+            AccountMeta::new_readonly(withdrawer, true),
+        ],
+    );
+    let accounts = vec![
+        (stake, stake_account(lamports, &initialized_state(meta))),
+        (recipient, Account::default()),
+        (clock, clock_account),
+        (stake_history, stake_history_account),
+        (withdrawer, Account::default()),
+    ];
+
+    execute(
+        &mollusk,
+        &instruction,
+        &accounts,
+        &[
+            Check::success(),
+            Check::account(&stake)
+                .data(&[0; StakeStateV2::size_of()])
+                .lamports(0)
+                .build(),
+            Check::account(&recipient).lamports(lamports).build(),
+        ],
+    );
+}
+
+/// Intended divergence: a reserve recorded under older, cheaper rent does not let a
+/// partial withdrawal leave the account below today's rent-exempt minimum, which
+/// native allows
+#[test]
+fn test_withdraw_stale_reserve() {
+    let mollusk = mollusk();
+    let withdrawer = Pubkey::new_unique();
+    let stale = Meta {
+        rent_exempt_reserve: rent_exempt_reserve(&mollusk) / 2,
+        ..meta(&mollusk, &Pubkey::new_unique(), &withdrawer)
+    };
+    let account = || funded_stake_account(&mollusk, &initialized_state(stale));
+
+    assert_eq!(
+        withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL + 1),
+        Err(ProgramError::AccountNotRentExempt)
+    );
+    assert!(withdraw(&mollusk, account(), &withdrawer, None, LAMPORTS_PER_SOL).is_ok());
+}